@@ -1,7 +1,7 @@
 use std::{mem::MaybeUninit, io::Cursor};
 use std::io::Write;
 
-use opentelemetry_api::trace::{SpanId, TraceId};
+use opentelemetry_api::trace::{SpanContext, SpanId, TraceId};
 
 pub(crate) struct Activities {
     pub(crate) span_id: [u8; 16],                    // Hex string
@@ -17,9 +17,19 @@ impl Activities {
         span_id: &SpanId,
         parent_span_id: &SpanId,
         trace_id: &TraceId,
+        trace_scoped: bool,
     ) -> Activities {
+        // The low 8 bytes of a W3C trace id are its random part.
+        let trace_bytes = trace_id.to_bytes();
+        let high_bytes: [u8; 8] = if trace_scoped {
+            trace_bytes[8..].try_into().expect("!trace_id")
+        } else {
+            [0; 8]
+        };
+
         let mut activity_id: [u8; 16] = [0; 16];
-        let (_, half) = activity_id.split_at_mut(8);
+        let (high, half) = activity_id.split_at_mut(8);
+        high.copy_from_slice(&high_bytes);
         half.copy_from_slice(&span_id.to_bytes());
 
         let (parent_activity_id, parent_span_name) = if *parent_span_id == SpanId::INVALID {
@@ -27,10 +37,11 @@ impl Activities {
         } else {
             let mut buf: [u8; 16] = unsafe { MaybeUninit::uninit().assume_init() };
             let mut cur = Cursor::new(&mut buf[..]);
-            write!(&mut cur, "{:16x}", span_id).expect("!write");
+            write!(&mut cur, "{:16x}", parent_span_id).expect("!write");
 
             let mut activity_id: [u8; 16] = [0; 16];
-            let (_, half) = activity_id.split_at_mut(8);
+            let (high, half) = activity_id.split_at_mut(8);
+            high.copy_from_slice(&high_bytes);
             half.copy_from_slice(&parent_span_id.to_bytes());
             (Some(activity_id), buf)
         };
@@ -51,4 +62,92 @@ impl Activities {
             trace_id_name: buf2,
         }
     }
+
+    /// Replace the derived activity id, such as with an external correlation id.
+    pub(crate) fn with_activity_id(mut self, activity_id: Option<[u8; 16]>) -> Self {
+        if let Some(activity_id) = activity_id {
+            self.activity_id = activity_id;
+        }
+        self
+    }
+}
+
+/// Format the span context as a W3C `traceparent` header value:
+/// `00-<trace id>-<span id>-<trace flags>`.
+pub(crate) fn get_traceparent(span_context: &SpanContext) -> String {
+    format!(
+        "00-{:032x}-{:016x}-{:02x}",
+        span_context.trace_id(),
+        span_context.span_id(),
+        span_context.trace_flags().to_u8()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry_api::trace::{TraceFlags, TraceState};
+
+    #[test]
+    fn trace_scoped_activities() {
+        let span_id = SpanId::from_bytes(1u64.to_be_bytes());
+        let first_trace = TraceId::from_bytes(1u128.to_be_bytes());
+        let second_trace = TraceId::from_bytes(2u128.to_be_bytes());
+
+        let first = Activities::generate(&span_id, &SpanId::INVALID, &first_trace, true);
+        let second = Activities::generate(&span_id, &SpanId::INVALID, &second_trace, true);
+        assert_ne!(first.activity_id, second.activity_id);
+
+        let first = Activities::generate(&span_id, &SpanId::INVALID, &first_trace, false);
+        let second = Activities::generate(&span_id, &SpanId::INVALID, &second_trace, false);
+        assert_eq!(first.activity_id, second.activity_id);
+    }
+
+    #[test]
+    fn activity_id_contains_span_id() {
+        let span_id = SpanId::from_hex("00f067aa0ba902b7").unwrap();
+        let trace_id = TraceId::from_hex("0af7651916cd43dd8448eb211c80319c").unwrap();
+
+        for trace_scoped in [false, true] {
+            let activities =
+                Activities::generate(&span_id, &SpanId::INVALID, &trace_id, trace_scoped);
+            assert_eq!(activities.activity_id[8..], span_id.to_bytes());
+        }
+    }
+
+    #[test]
+    fn nested_span_activities() {
+        let trace_id = TraceId::from_bytes(0x0af7651916cd43dd8448eb211c80319cu128.to_be_bytes());
+        let parent_span_id = SpanId::from_bytes(0x00f067aa0ba902b7u64.to_be_bytes());
+        let child_span_id = SpanId::from_bytes(0x53995c3f42cd8ad8u64.to_be_bytes());
+
+        for trace_scoped in [false, true] {
+            let parent =
+                Activities::generate(&parent_span_id, &SpanId::INVALID, &trace_id, trace_scoped);
+            let child =
+                Activities::generate(&child_span_id, &parent_span_id, &trace_id, trace_scoped);
+
+            assert_eq!(parent.parent_activity_id, None);
+            // The child's related activity is the parent's own activity
+            assert_eq!(child.parent_activity_id, Some(parent.activity_id));
+            assert_ne!(child.activity_id, parent.activity_id);
+            assert_eq!(child.parent_span_id, parent.span_id);
+        }
+    }
+
+    #[test]
+    fn traceparent() {
+        let span_context = SpanContext::new(
+            TraceId::from_hex("0af7651916cd43dd8448eb211c80319c").unwrap(),
+            SpanId::from_hex("00f067aa0ba902b7").unwrap(),
+            TraceFlags::SAMPLED,
+            false,
+            TraceState::default(),
+        );
+
+        assert_eq!(
+            get_traceparent(&span_context),
+            "00-0af7651916cd43dd8448eb211c80319c-00f067aa0ba902b7-01"
+        );
+    }
 }
@@ -0,0 +1,70 @@
+use opentelemetry::trace::SpanId;
+
+/// The thread activity ids to restore when the spans that replaced them end, innermost span last.
+/// Spans that end out of order hand their saved activity id to the span started after them,
+/// so the thread always returns to the id that was current before the outermost span started.
+pub(crate) struct ActivityStack<T> {
+    entries: Vec<(SpanId, T)>,
+}
+
+impl<T> ActivityStack<T> {
+    pub(crate) const fn new() -> Self {
+        ActivityStack {
+            entries: Vec::new(),
+        }
+    }
+
+    /// Save the activity id that was current when the span set its own.
+    pub(crate) fn push(&mut self, span_id: SpanId, prior_activity_id: T) {
+        self.entries.push((span_id, prior_activity_id));
+    }
+
+    /// Remove the span's entry, returning the activity id to restore if it was the innermost span.
+    /// Returns `None` if a span started within it is still active,
+    /// or if the span set the activity id on a different thread.
+    pub(crate) fn pop(&mut self, span_id: SpanId) -> Option<T> {
+        let pos = self.entries.iter().rposition(|(id, _)| *id == span_id)?;
+        let (_, prior_activity_id) = self.entries.remove(pos);
+        if pos == self.entries.len() {
+            Some(prior_activity_id)
+        } else {
+            self.entries[pos].1 = prior_activity_id;
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nested_restore() {
+        let parent = SpanId::from_bytes(1u64.to_be_bytes());
+        let child = SpanId::from_bytes(2u64.to_be_bytes());
+        let mut stack = ActivityStack::new();
+
+        stack.push(parent, "none");
+        stack.push(child, "parent");
+        assert_eq!(stack.pop(child), Some("parent"));
+        assert_eq!(stack.pop(parent), Some("none"));
+        assert!(stack.entries.is_empty());
+    }
+
+    #[test]
+    fn out_of_order_restore() {
+        let parent = SpanId::from_bytes(1u64.to_be_bytes());
+        let child = SpanId::from_bytes(2u64.to_be_bytes());
+        let other = SpanId::from_bytes(3u64.to_be_bytes());
+        let mut stack = ActivityStack::new();
+
+        stack.push(parent, "none");
+        stack.push(child, "parent");
+        // The child is still current, and now restores what the parent would have
+        assert_eq!(stack.pop(parent), None);
+        assert_eq!(stack.pop(child), Some("none"));
+
+        // Spans that set the activity id on another thread have nothing to restore here
+        assert_eq!(stack.pop(other), None);
+    }
+}
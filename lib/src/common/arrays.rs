@@ -0,0 +1,80 @@
+use opentelemetry::{Array, Value};
+use std::borrow::Cow;
+
+/// The suffix of the field that marks an array attribute as truncated.
+pub(crate) const TRUNCATED_SUFFIX: &str = ".truncated";
+
+fn get_array_len(value: &Value) -> Option<usize> {
+    match value {
+        Value::Array(Array::Bool(v)) => Some(v.len()),
+        Value::Array(Array::I64(v)) => Some(v.len()),
+        Value::Array(Array::F64(v)) => Some(v.len()),
+        Value::Array(Array::String(v)) => Some(v.len()),
+        _ => None,
+    }
+}
+
+/// Whether the attribute is an array with more than `max_len` elements.
+pub(crate) fn is_truncated(value: &Value, max_len: Option<usize>) -> bool {
+    match (get_array_len(value), max_len) {
+        (Some(len), Some(max_len)) => len > max_len,
+        _ => false,
+    }
+}
+
+/// The number of fields the attribute is logged as: one, plus the
+/// `<name>.truncated` marker if it is a truncated array.
+pub(crate) fn get_field_count(value: &Value, max_len: Option<usize>) -> usize {
+    1 + is_truncated(value, max_len) as usize
+}
+
+/// The attribute with only the first `max_len` elements, if it is a longer array,
+/// and whether it was truncated. Other attributes are returned as is.
+pub(crate) fn truncate_array(
+    value: Cow<'_, Value>,
+    max_len: Option<usize>,
+) -> (Cow<'_, Value>, bool) {
+    let max_len = match max_len {
+        Some(max_len) if is_truncated(&value, Some(max_len)) => max_len,
+        _ => return (value, false),
+    };
+
+    let truncated = match value.as_ref() {
+        Value::Array(Array::Bool(v)) => Array::Bool(v[..max_len].to_vec()),
+        Value::Array(Array::I64(v)) => Array::I64(v[..max_len].to_vec()),
+        Value::Array(Array::F64(v)) => Array::F64(v[..max_len].to_vec()),
+        Value::Array(Array::String(v)) => Array::String(v[..max_len].to_vec()),
+        _ => unreachable!(),
+    };
+    (Cow::Owned(Value::Array(truncated)), true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn array_truncation() {
+        let value = Value::Array(Array::I64((0..10).collect()));
+
+        let (truncated, was_truncated) = truncate_array(Cow::Borrowed(&value), Some(3));
+        assert!(was_truncated);
+        assert_eq!(truncated.as_ref(), &Value::Array(Array::I64(vec![0, 1, 2])));
+        assert_eq!(get_field_count(&value, Some(3)), 2);
+
+        let (untouched, was_truncated) = truncate_array(Cow::Borrowed(&value), Some(10));
+        assert!(!was_truncated);
+        assert!(matches!(untouched, Cow::Borrowed(_)));
+        assert_eq!(get_field_count(&value, Some(10)), 1);
+
+        assert!(!truncate_array(Cow::Borrowed(&value), None).1);
+        assert!(!is_truncated(&Value::from("a long string"), Some(1)));
+
+        let strings = Value::Array(Array::String(vec!["a".into(), "b".into()]));
+        let (truncated, _) = truncate_array(Cow::Borrowed(&strings), Some(1));
+        assert_eq!(
+            truncated.as_ref(),
+            &Value::Array(Array::String(vec!["a".into()]))
+        );
+    }
+}
@@ -0,0 +1,322 @@
+use std::borrow::Cow;
+use std::collections::BTreeSet;
+use std::sync::Arc;
+
+use opentelemetry::{trace::Link, Key, Value};
+use opentelemetry_sdk::trace::EvictedHashMap;
+
+use crate::common::{arrays, floats};
+use crate::exporter_traits::{StringOutType, ValueRedaction};
+
+/// The name of the event field for a user attribute.
+/// A prefix keeps user attributes from colliding with the exporter's own fields,
+/// such as `SpanId` or `Kind`.
+/// Null bytes and other control characters are replaced with `_`, since field names
+/// are null-terminated in the event metadata.
+pub(crate) fn get_field_name(key: &Key, prefix: Option<&str>) -> String {
+    let field_name = match prefix {
+        Some(prefix) => format!("{prefix}{}", key.as_str()),
+        None => key.to_string(),
+    };
+
+    if field_name.contains(char::is_control) {
+        field_name.replace(char::is_control, "_")
+    } else {
+        field_name
+    }
+}
+
+/// The OutType of a string attribute, from the first suffix in `map` that its key ends with.
+pub(crate) fn find_string_out_type(
+    key: &Key,
+    map: Option<&[(Arc<str>, StringOutType)]>,
+) -> Option<StringOutType> {
+    map?.iter()
+        .find(|(suffix, _)| key.as_str().ends_with(&**suffix))
+        .map(|(_, out_type)| *out_type)
+}
+
+/// The number of attributes the span carries, for the `AttrCount` field.
+/// Spans with more attributes than a `u16` can count are logged with `u16::MAX`.
+pub(crate) fn get_attribute_count(attributes: &EvictedHashMap) -> u16 {
+    u16::try_from(attributes.len()).unwrap_or(u16::MAX)
+}
+
+/// The number of attributes a span link carries, for the `LinkAttrCount` field.
+pub(crate) fn get_link_attribute_count(link: &Link) -> u16 {
+    u16::try_from(link.attributes.len()).unwrap_or(u16::MAX)
+}
+
+/// Attribute keys that are never emitted, or the only keys that are emitted.
+/// The denylist takes precedence, so a key on both lists is dropped.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct AttributeFilter {
+    denylist: BTreeSet<String>,
+    allowlist: Option<BTreeSet<String>>,
+}
+
+impl AttributeFilter {
+    pub(crate) fn deny(&mut self, keys: &[&str]) {
+        self.denylist.extend(keys.iter().map(|key| key.to_string()));
+    }
+
+    pub(crate) fn allow(&mut self, keys: &[&str]) {
+        self.allowlist
+            .get_or_insert_with(BTreeSet::new)
+            .extend(keys.iter().map(|key| key.to_string()));
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.denylist.is_empty() && self.allowlist.is_none()
+    }
+
+    pub(crate) fn is_allowed(&self, key: &str) -> bool {
+        !self.denylist.contains(key)
+            && self
+                .allowlist
+                .as_ref()
+                .map_or(true, |allowlist| allowlist.contains(key))
+    }
+}
+
+/// Skip the attributes that the filter doesn't allow, if there is one.
+pub(crate) fn filter_attributes<'k, 'v, 'f>(
+    attribs: impl Iterator<Item = (&'k Key, &'v Value)> + 'f,
+    filter: Option<&'f AttributeFilter>,
+) -> impl Iterator<Item = (&'k Key, &'v Value)> + 'f {
+    attribs.filter(move |(key, _)| filter.map_or(true, |filter| filter.is_allowed(key.as_str())))
+}
+
+/// Call `f` with the attributes, after replacing the values that `redaction` masks.
+/// Without a redaction, the attributes are passed through without copying them.
+pub(crate) fn with_redacted_values<R>(
+    attribs: &mut dyn Iterator<Item = (&Key, &Value)>,
+    redaction: Option<&ValueRedaction>,
+    f: impl FnOnce(&mut dyn Iterator<Item = (&Key, &Value)>) -> R,
+) -> R {
+    match redaction {
+        Some(redaction) => {
+            let redacted: Vec<(&Key, Cow<Value>)> = attribs
+                .map(|(key, value)| {
+                    let value =
+                        redaction(key.as_str(), value).map_or(Cow::Borrowed(value), Cow::Owned);
+                    (key, value)
+                })
+                .collect();
+            f(&mut redacted.iter().map(|(key, value)| (*key, value.as_ref())))
+        }
+        None => f(attribs),
+    }
+}
+
+/// The number of fields that the attributes are logged as, after the attribute filter
+/// and value redaction, for the struct that groups a span event's attributes.
+/// Non-finite floats are left out when they are sanitized, and truncated arrays
+/// are followed by their `<name>.truncated` marker.
+pub(crate) fn get_attribute_field_count(
+    attribs: &mut dyn Iterator<Item = (&Key, &Value)>,
+    filter: Option<&AttributeFilter>,
+    redaction: Option<&ValueRedaction>,
+    float_sanitization: bool,
+    max_array_length: Option<usize>,
+) -> usize {
+    with_redacted_values(
+        &mut filter_attributes(attribs, filter),
+        redaction,
+        |attribs| {
+            attribs
+                .filter(|(_, value)| !(float_sanitization && floats::is_non_finite_f64(value)))
+                .map(|(_, value)| arrays::get_field_count(value, max_array_length))
+                .sum()
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry::{trace::SpanContext, KeyValue};
+
+    #[test]
+    fn string_out_types() {
+        let map: [(Arc<str>, StringOutType); 2] = [
+            (Arc::from(".xml"), StringOutType::Xml),
+            (Arc::from("body.json"), StringOutType::Json),
+        ];
+        let out_type = |key: &'static str| find_string_out_type(&Key::new(key), Some(&map));
+
+        assert_eq!(out_type("request.xml"), Some(StringOutType::Xml));
+        assert_eq!(out_type("response.body.json"), Some(StringOutType::Json));
+        assert_eq!(out_type("request"), None);
+        assert_eq!(find_string_out_type(&Key::new("request.xml"), None), None);
+    }
+
+    #[test]
+    fn attribute_count() {
+        let mut attributes = EvictedHashMap::new(128, 3);
+        assert_eq!(get_attribute_count(&attributes), 0);
+
+        attributes.insert(KeyValue::new("http.method", "GET"));
+        attributes.insert(KeyValue::new("http.status_code", 200));
+        attributes.insert(KeyValue::new("http.status_code", 404));
+        // Setting an attribute again replaces it
+        assert_eq!(get_attribute_count(&attributes), 2);
+    }
+
+    #[test]
+    fn link_attribute_count() {
+        let link = Link::new(
+            SpanContext::empty_context(),
+            vec![
+                KeyValue::new("link.reason", "retry"),
+                KeyValue::new("link.attempt", 2),
+            ],
+        );
+        assert_eq!(get_link_attribute_count(&link), 2);
+
+        let link = Link::new(SpanContext::empty_context(), Vec::new());
+        assert_eq!(get_link_attribute_count(&link), 0);
+    }
+
+    fn filtered_keys(filter: &AttributeFilter) -> Vec<String> {
+        let attribs = [
+            KeyValue::new("user.email", "someone@example.com"),
+            KeyValue::new("http.method", "GET"),
+            KeyValue::new("http.status_code", 200),
+        ];
+        filter_attributes(
+            &mut attribs.iter().map(|kv| (&kv.key, &kv.value)),
+            Some(filter),
+        )
+        .map(|(key, _)| key.to_string())
+        .collect()
+    }
+
+    #[test]
+    fn attribute_denylist() {
+        let mut filter = AttributeFilter::default();
+        assert!(filter.is_empty());
+        assert_eq!(filtered_keys(&filter).len(), 3);
+
+        filter.deny(&["user.email", "not.present"]);
+        assert!(!filter.is_empty());
+        assert_eq!(filtered_keys(&filter), ["http.method", "http.status_code"]);
+    }
+
+    #[test]
+    fn attribute_allowlist() {
+        let mut filter = AttributeFilter::default();
+        filter.allow(&["http.status_code", "http.method"]);
+        assert_eq!(filtered_keys(&filter), ["http.method", "http.status_code"]);
+
+        // An empty allowlist drops every attribute
+        let mut filter = AttributeFilter::default();
+        filter.allow(&[]);
+        assert!(!filter.is_empty());
+        assert!(filtered_keys(&filter).is_empty());
+    }
+
+    #[test]
+    fn attribute_denylist_takes_precedence() {
+        let mut filter = AttributeFilter::default();
+        filter.allow(&["user.email", "http.method"]);
+        filter.deny(&["user.email"]);
+        assert_eq!(filtered_keys(&filter), ["http.method"]);
+    }
+
+    #[test]
+    fn attribute_prefix() {
+        let key = Key::new("SpanId");
+        assert_eq!(get_field_name(&key, Some("attr.")), "attr.SpanId");
+        assert_ne!(get_field_name(&key, Some("attr.")), "SpanId");
+        assert_eq!(get_field_name(&key, None), "SpanId");
+    }
+
+    #[test]
+    fn sanitized_field_names() {
+        let key = Key::new("bad\0key\n");
+        assert_eq!(get_field_name(&key, None), "bad_key_");
+        assert_eq!(get_field_name(&key, Some("attr\0.")), "attr_.bad_key_");
+        assert_eq!(get_field_name(&Key::new("good.key"), None), "good.key");
+    }
+
+    #[test]
+    fn value_redaction() {
+        let attribs = [
+            KeyValue::new("card", "4111 1111 1111 1111"),
+            KeyValue::new("amount", 25),
+        ];
+        let redaction: ValueRedaction =
+            Arc::new(|key: &str, _: &Value| (key == "card").then(|| Value::from("***")));
+
+        let redacted: Vec<(String, Value)> = with_redacted_values(
+            &mut attribs.iter().map(|kv| (&kv.key, &kv.value)),
+            Some(&redaction),
+            |attribs| {
+                attribs
+                    .map(|(key, value)| (key.to_string(), value.clone()))
+                    .collect()
+            },
+        );
+        assert_eq!(
+            redacted,
+            [
+                ("card".to_owned(), Value::from("***")),
+                ("amount".to_owned(), Value::I64(25))
+            ]
+        );
+
+        let unchanged = with_redacted_values(
+            &mut attribs.iter().map(|kv| (&kv.key, &kv.value)),
+            None,
+            |attribs| attribs.filter(|(_, value)| value.as_str() == "***").count(),
+        );
+        assert_eq!(unchanged, 0);
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn value_redaction_in_json_payload() {
+        let attribs = [KeyValue::new("card", "4111 1111 1111 1111")];
+        let redaction: ValueRedaction =
+            Arc::new(|key: &str, _: &Value| (key == "card").then(|| Value::from("***")));
+
+        let json_string = with_redacted_values(
+            &mut attribs.iter().map(|kv| (&kv.key, &kv.value)),
+            Some(&redaction),
+            crate::common::json::get_attributes_as_json,
+        );
+        assert_eq!(json_string, "{\"card\":\"***\"}");
+    }
+
+    #[test]
+    fn grouped_attribute_field_count() {
+        let attribs = [
+            KeyValue::new("user.email", "someone@example.com"),
+            KeyValue::new("retries", 3),
+            KeyValue::new("ratio", f64::NAN),
+            KeyValue::new(
+                "ids",
+                Value::Array(opentelemetry::Array::I64(vec![1, 2, 3, 4])),
+            ),
+        ];
+        let count = |filter, float_sanitization, max_array_length| {
+            get_attribute_field_count(
+                &mut attribs.iter().map(|kv| (&kv.key, &kv.value)),
+                filter,
+                None,
+                float_sanitization,
+                max_array_length,
+            )
+        };
+
+        assert_eq!(count(None, false, None), 4);
+
+        // Every attribute that is logged is counted, along with any truncation markers
+        let mut filter = AttributeFilter::default();
+        filter.deny(&["user.email"]);
+        assert_eq!(count(Some(&filter), false, None), 3);
+        assert_eq!(count(Some(&filter), true, None), 2);
+        assert_eq!(count(Some(&filter), true, Some(2)), 3);
+    }
+}
@@ -0,0 +1,95 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use opentelemetry::trace::SpanId;
+
+/// The most spans with unended children that are tracked at once.
+pub(crate) const MAX_TRACKED_PARENTS: usize = 4096;
+
+/// Counts the children of each span that have started and not yet ended.
+/// Only spans with active children have an entry, and once `capacity` spans do,
+/// children of other spans are not counted until some of the entries are gone.
+pub(crate) struct ChildTracker {
+    active: Mutex<HashMap<SpanId, u32>>,
+    capacity: usize,
+}
+
+impl ChildTracker {
+    pub(crate) fn new(capacity: usize) -> Self {
+        ChildTracker {
+            active: Mutex::new(HashMap::new()),
+            capacity,
+        }
+    }
+
+    /// Count a started child of `parent_span_id`.
+    /// Returns whether it was counted, in which case `child_ended` must be called when it ends.
+    pub(crate) fn child_started(&self, parent_span_id: SpanId) -> bool {
+        let mut active = self.active.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(count) = active.get_mut(&parent_span_id) {
+            *count += 1;
+            true
+        } else if active.len() < self.capacity {
+            active.insert(parent_span_id, 1);
+            true
+        } else {
+            false
+        }
+    }
+
+    pub(crate) fn child_ended(&self, parent_span_id: SpanId) {
+        let mut active = self.active.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(count) = active.get_mut(&parent_span_id) {
+            *count -= 1;
+            if *count == 0 {
+                active.remove(&parent_span_id);
+            }
+        }
+    }
+
+    /// The number of children of the span that have started and not yet ended.
+    pub(crate) fn active_children(&self, span_id: SpanId) -> u32 {
+        let active = self.active.lock().unwrap_or_else(|e| e.into_inner());
+        active.get(&span_id).copied().unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn child_tracking() {
+        let parent = SpanId::from_bytes(1u64.to_be_bytes());
+        let tracker = ChildTracker::new(MAX_TRACKED_PARENTS);
+        assert_eq!(tracker.active_children(parent), 0);
+
+        assert!(tracker.child_started(parent));
+        assert!(tracker.child_started(parent));
+        assert_eq!(tracker.active_children(parent), 2);
+
+        tracker.child_ended(parent);
+        assert_eq!(tracker.active_children(parent), 1);
+        tracker.child_ended(parent);
+        assert_eq!(tracker.active_children(parent), 0);
+        assert!(tracker.active.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn bounded_child_tracking() {
+        let first = SpanId::from_bytes(1u64.to_be_bytes());
+        let second = SpanId::from_bytes(2u64.to_be_bytes());
+        let tracker = ChildTracker::new(1);
+
+        assert!(tracker.child_started(first));
+        assert!(!tracker.child_started(second));
+        // Spans that already have an entry keep counting
+        assert!(tracker.child_started(first));
+        assert_eq!(tracker.active_children(first), 2);
+        assert_eq!(tracker.active_children(second), 0);
+
+        tracker.child_ended(first);
+        tracker.child_ended(first);
+        assert!(tracker.child_started(second));
+    }
+}
@@ -0,0 +1,48 @@
+/// A string attribute value that is entirely a number.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum NumericString {
+    I64(i64),
+    F64(f64),
+}
+
+/// Parse `s` as an `i64`, or failing that a finite `f64`.
+/// Returns `None` for strings with anything besides the number, including whitespace,
+/// and for the `inf` and `NaN` spellings that `f64` would otherwise accept.
+pub(crate) fn parse_numeric_string(s: &str) -> Option<NumericString> {
+    if let Ok(i) = s.parse::<i64>() {
+        return Some(NumericString::I64(i));
+    }
+
+    match s.parse::<f64>() {
+        Ok(f) if f.is_finite() => Some(NumericString::F64(f)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numeric_strings() {
+        assert_eq!(parse_numeric_string("42"), Some(NumericString::I64(42)));
+        assert_eq!(parse_numeric_string("-7"), Some(NumericString::I64(-7)));
+        assert_eq!(parse_numeric_string("2.5"), Some(NumericString::F64(2.5)));
+        assert_eq!(
+            parse_numeric_string("1e3"),
+            Some(NumericString::F64(1000.0))
+        );
+        // Too large for an i64
+        assert_eq!(
+            parse_numeric_string("9223372036854775808"),
+            Some(NumericString::F64(9223372036854775808.0))
+        );
+
+        assert_eq!(parse_numeric_string(""), None);
+        assert_eq!(parse_numeric_string(" 42"), None);
+        assert_eq!(parse_numeric_string("42ms"), None);
+        assert_eq!(parse_numeric_string("0x2a"), None);
+        assert_eq!(parse_numeric_string("inf"), None);
+        assert_eq!(parse_numeric_string("NaN"), None);
+    }
+}
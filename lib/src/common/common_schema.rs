@@ -0,0 +1,485 @@
+use opentelemetry::trace::{SpanContext, SpanId, SpanKind, Status};
+use opentelemetry::{Array, InstrumentationLibrary, Key, Value};
+use opentelemetry_sdk::export::trace::SpanData;
+use std::sync::Arc;
+
+/// The fields of the Common Schema PartA `ext_app` struct.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct AppExtension {
+    pub(crate) id: Arc<str>,
+    pub(crate) ver: Arc<str>,
+}
+
+impl AppExtension {
+    pub(crate) fn new(id: &str, ver: &str) -> Self {
+        AppExtension {
+            id: Arc::from(id),
+            ver: Arc::from(ver),
+        }
+    }
+}
+
+/// The fields of the Common Schema PartA `ext_sdk` struct, from the span's instrumentation library.
+/// Libraries without a version log an empty `libVer`.
+pub(crate) fn get_sdk_extension(instrumentation_lib: &InstrumentationLibrary) -> (&str, &str) {
+    (
+        instrumentation_lib.name.as_ref(),
+        instrumentation_lib.version.as_deref().unwrap_or_default(),
+    )
+}
+
+/// The number of fields in the Common Schema PartA struct.
+pub(crate) fn get_parta_field_count(
+    app_extension: Option<&AppExtension>,
+    sdk_extension: bool,
+) -> u8 {
+    let mut parta_field_count = 2;
+    if app_extension.is_some() {
+        parta_field_count += 1;
+    }
+    if sdk_extension {
+        parta_field_count += 1;
+    }
+    parta_field_count
+}
+
+/// The number of fields in the Common Schema PartA `ext_dt` struct.
+pub(crate) fn get_ext_dt_field_count(trace_flags: bool) -> u8 {
+    if trace_flags {
+        3
+    } else {
+        2
+    }
+}
+
+/// The `ext_dt.traceFlags` value, formatted the same as the flags in a `traceparent`.
+pub(crate) fn get_trace_flags(span_context: &SpanContext) -> String {
+    format!("{:02x}", span_context.trace_flags().to_u8())
+}
+
+/// Which of the optional fields of the Common Schema PartB struct are written for a span.
+/// The writers check these instead of the span, so the struct's field count always
+/// matches what is written.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct PartBFields {
+    pub(crate) parent_id: bool,
+    pub(crate) kind: bool,
+    pub(crate) status_message: bool,
+    pub(crate) links: bool,
+    pub(crate) link_count: bool,
+    pub(crate) event_count: bool,
+}
+
+impl PartBFields {
+    /// In compact mode, fields that hold their default value are left out as well:
+    /// `kind` for internal spans, and `linkCount` and `eventCount` when they are 0.
+    pub(crate) fn new(span_data: &SpanData, include_counts: bool, compact: bool) -> Self {
+        let (link_count, event_count) = get_partb_counts(span_data);
+        PartBFields {
+            parent_id: span_data.parent_span_id != SpanId::INVALID,
+            kind: !(compact && span_data.span_kind == SpanKind::Internal),
+            // statusMessage is only written for a non-empty description
+            status_message: matches!(
+                &span_data.status,
+                Status::Error { description } if !description.is_empty()
+            ),
+            // TODO: azureResourceProvider: string
+            links: !span_data.links.is_empty(),
+            link_count: include_counts && !(compact && link_count == 0),
+            event_count: include_counts && !(compact && event_count == 0),
+        }
+    }
+
+    /// The number of fields in the struct, not including promoted fields.
+    pub(crate) fn field_count(&self) -> u8 {
+        // _typeName, name, startTime, and success are always written
+        4 + [
+            self.parent_id,
+            self.kind,
+            self.status_message,
+            self.links,
+            self.link_count,
+            self.event_count,
+        ]
+        .iter()
+        .filter(|written| **written)
+        .count() as u8
+    }
+}
+
+/// Whether the value is an empty string or array, which compact events leave out.
+pub(crate) fn is_empty_value(value: &Value) -> bool {
+    match value {
+        Value::String(s) => s.as_str().is_empty(),
+        Value::Array(Array::Bool(v)) => v.is_empty(),
+        Value::Array(Array::I64(v)) => v.is_empty(),
+        Value::Array(Array::F64(v)) => v.is_empty(),
+        Value::Array(Array::String(v)) => v.is_empty(),
+        _ => false,
+    }
+}
+
+/// Database attributes and the PartB fields they are promoted to.
+const DB_PROMOTED_FIELDS: [(&str, &str); 3] = [
+    ("db.system", "dbSystem"),
+    ("db.name", "dbName"),
+    ("db.statement", "dbStatement"),
+];
+
+/// A span attribute that is logged as a named PartB field instead of in PartC.
+pub(crate) struct PromotedField<'a> {
+    pub(crate) name: &'static str,
+    pub(crate) key: &'a Key,
+    pub(crate) value: &'a Value,
+}
+
+/// The attributes of a span that are promoted to PartB fields.
+/// Only database client spans have promoted fields.
+pub(crate) fn get_promoted_fields(span_data: &SpanData) -> Vec<PromotedField<'_>> {
+    if span_data.span_kind != SpanKind::Client {
+        return Vec::new();
+    }
+
+    DB_PROMOTED_FIELDS
+        .iter()
+        .filter_map(|(attribute, name)| {
+            span_data
+                .attributes
+                .iter()
+                .find(|(key, _)| key.as_str() == *attribute)
+                .map(|(key, value)| PromotedField { name, key, value })
+        })
+        .collect()
+}
+
+/// Whether the attribute is logged as a PartB field and should be left out of PartC.
+pub(crate) fn is_promoted(promoted_fields: &[PromotedField], key: &Key) -> bool {
+    promoted_fields.iter().any(|field| field.key == key)
+}
+
+/// The most fields a struct can have, for both TraceLogging and EventHeader events.
+pub(crate) const MAX_STRUCT_FIELDS: usize = 127;
+
+/// How the attributes of a span fit in the Common Schema PartC struct.
+/// When there are more attributes than a struct can hold, only the first ones are logged
+/// and the struct ends with a `truncatedAttributes` field with the number left out.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct PartCFields {
+    pub(crate) attributes: usize,
+    pub(crate) fields: usize,
+    pub(crate) truncated: u32,
+}
+
+impl PartCFields {
+    #[cfg(test)]
+    pub(crate) fn new(attribute_count: usize) -> Self {
+        Self::with_field_counts(std::iter::repeat(1).take(attribute_count))
+    }
+
+    /// From the number of fields each attribute is logged as, for attributes that are
+    /// logged with a companion field. An attribute's fields are never split up.
+    pub(crate) fn with_field_counts(field_counts: impl Iterator<Item = usize>) -> Self {
+        let field_counts: Vec<usize> = field_counts.collect();
+        let total: usize = field_counts.iter().sum();
+        if total <= MAX_STRUCT_FIELDS {
+            return PartCFields {
+                attributes: field_counts.len(),
+                fields: total,
+                truncated: 0,
+            };
+        }
+
+        // Leave room for the truncatedAttributes field
+        let mut fields = 0;
+        let attributes = field_counts
+            .iter()
+            .take_while(|count| {
+                fields += *count;
+                fields < MAX_STRUCT_FIELDS
+            })
+            .count();
+        PartCFields {
+            attributes,
+            fields: field_counts[..attributes].iter().sum(),
+            truncated: (field_counts.len() - attributes) as u32,
+        }
+    }
+
+    pub(crate) fn field_count(&self) -> u8 {
+        (self.fields + (self.truncated > 0) as usize) as u8
+    }
+}
+
+/// The values of the `linkCount` and `eventCount` PartB fields.
+pub(crate) fn get_partb_counts(span_data: &SpanData) -> (u32, u32) {
+    (span_data.links.len() as u32, span_data.events.len() as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry::trace::{
+        Event, Link, SpanContext, SpanKind, TraceFlags, TraceId, TraceState,
+    };
+    use opentelemetry::{InstrumentationLibrary, KeyValue};
+    use opentelemetry_sdk::trace::{EvictedHashMap, EvictedQueue};
+    use opentelemetry_sdk::Resource;
+    use std::borrow::Cow;
+    use std::time::SystemTime;
+
+    #[test]
+    fn partb_counts() {
+        let mut links = EvictedQueue::new(128);
+        links.extend((1..=2u64).map(|i| {
+            Link::new(
+                SpanContext::new(
+                    TraceId::from_bytes(1u128.to_be_bytes()),
+                    SpanId::from_bytes(i.to_be_bytes()),
+                    TraceFlags::SAMPLED,
+                    false,
+                    TraceState::default(),
+                ),
+                vec![],
+            )
+        }));
+
+        let mut events = EvictedQueue::new(128);
+        events.extend(
+            ["first", "second", "third"]
+                .into_iter()
+                .map(|name| Event::new(name, SystemTime::now(), vec![], 0)),
+        );
+
+        let span_data = SpanData {
+            span_context: SpanContext::empty_context(),
+            parent_span_id: SpanId::INVALID,
+            span_kind: SpanKind::Internal,
+            name: "span".into(),
+            start_time: SystemTime::now(),
+            end_time: SystemTime::now(),
+            attributes: EvictedHashMap::new(128, 0),
+            events,
+            links,
+            status: Status::Unset,
+            resource: Cow::Owned(Resource::empty()),
+            instrumentation_lib: InstrumentationLibrary::new("test", None, None),
+        };
+
+        assert_eq!(get_partb_counts(&span_data), (2, 3));
+        assert_eq!(PartBFields::new(&span_data, false, false).field_count(), 6);
+        assert_eq!(PartBFields::new(&span_data, true, false).field_count(), 8);
+    }
+
+    #[test]
+    fn partb_status_message() {
+        let mut links = EvictedQueue::new(128);
+        links.extend([Link::new(SpanContext::empty_context(), vec![])]);
+        let mut span_data = SpanData {
+            span_context: SpanContext::empty_context(),
+            parent_span_id: SpanId::from_bytes(1u64.to_be_bytes()),
+            span_kind: SpanKind::Client,
+            name: "span".into(),
+            start_time: SystemTime::now(),
+            end_time: SystemTime::now(),
+            attributes: EvictedHashMap::new(128, 0),
+            events: EvictedQueue::new(128),
+            links,
+            status: Status::error(""),
+            resource: Cow::Owned(Resource::empty()),
+            instrumentation_lib: InstrumentationLibrary::new("test", None, None),
+        };
+        assert_eq!(PartBFields::new(&span_data, false, false).field_count(), 7);
+
+        span_data.status = Status::error("failed");
+        assert_eq!(PartBFields::new(&span_data, false, false).field_count(), 8);
+
+        // Every optional field and every promoted field still fits in one struct
+        let max_field_count = PartBFields::new(&span_data, true, false).field_count() as usize
+            + DB_PROMOTED_FIELDS.len();
+        assert!(max_field_count <= MAX_STRUCT_FIELDS);
+    }
+
+    #[test]
+    fn compact_partb_fields() {
+        let mut span_data = SpanData {
+            span_context: SpanContext::empty_context(),
+            parent_span_id: SpanId::INVALID,
+            span_kind: SpanKind::Internal,
+            name: "span".into(),
+            start_time: SystemTime::now(),
+            end_time: SystemTime::now(),
+            attributes: EvictedHashMap::new(128, 0),
+            events: EvictedQueue::new(128),
+            links: EvictedQueue::new(128),
+            status: Status::Unset,
+            resource: Cow::Owned(Resource::empty()),
+            instrumentation_lib: InstrumentationLibrary::new("test", None, None),
+        };
+
+        // Only the fields that are always written
+        let fields = PartBFields::new(&span_data, true, true);
+        assert!(!fields.parent_id && !fields.kind && !fields.status_message && !fields.links);
+        assert!(!fields.link_count && !fields.event_count);
+        assert_eq!(fields.field_count(), 4);
+        assert_eq!(PartBFields::new(&span_data, false, false).field_count(), 5);
+        assert_eq!(PartBFields::new(&span_data, true, false).field_count(), 7);
+
+        span_data.parent_span_id = SpanId::from_bytes(1u64.to_be_bytes());
+        span_data.span_kind = SpanKind::Server;
+        span_data.status = Status::error("failed");
+        span_data
+            .links
+            .extend([Link::new(SpanContext::empty_context(), vec![])]);
+        let fields = PartBFields::new(&span_data, true, true);
+        assert!(fields.parent_id && fields.kind && fields.status_message && fields.links);
+        assert!(fields.link_count && !fields.event_count);
+        assert_eq!(fields.field_count(), 9);
+        assert_eq!(PartBFields::new(&span_data, false, true).field_count(), 8);
+        assert_eq!(
+            PartBFields::new(&span_data, true, false).field_count(),
+            PartBFields::new(&span_data, true, false).field_count()
+        );
+        assert_eq!(PartBFields::new(&span_data, true, false).field_count(), 10);
+    }
+
+    #[test]
+    fn empty_values() {
+        assert!(is_empty_value(&Value::from("")));
+        assert!(is_empty_value(&Value::Array(Array::I64(vec![]))));
+        assert!(is_empty_value(&Value::Array(Array::String(vec![]))));
+        assert!(!is_empty_value(&Value::from("value")));
+        assert!(!is_empty_value(&Value::Array(Array::Bool(vec![false]))));
+        assert!(!is_empty_value(&Value::I64(0)));
+        assert!(!is_empty_value(&Value::Bool(false)));
+    }
+
+    #[test]
+    fn partc_field_cap() {
+        let fields = PartCFields::new(10);
+        assert_eq!((fields.attributes, fields.truncated), (10, 0));
+        assert_eq!(fields.field_count(), 10);
+
+        let fields = PartCFields::new(MAX_STRUCT_FIELDS);
+        assert_eq!(
+            (fields.attributes, fields.truncated),
+            (MAX_STRUCT_FIELDS, 0)
+        );
+        assert_eq!(fields.field_count() as usize, MAX_STRUCT_FIELDS);
+
+        let fields = PartCFields::new(300);
+        assert_eq!(fields.attributes, MAX_STRUCT_FIELDS - 1);
+        assert_eq!(fields.truncated, 300 - (MAX_STRUCT_FIELDS as u32 - 1));
+        assert_eq!(fields.field_count() as usize, MAX_STRUCT_FIELDS);
+    }
+
+    #[test]
+    fn partc_field_cap_with_companion_fields() {
+        let fields = PartCFields::with_field_counts([1, 2, 1].into_iter());
+        assert_eq!(
+            (fields.attributes, fields.fields, fields.truncated),
+            (3, 4, 0)
+        );
+        assert_eq!(fields.field_count(), 4);
+
+        // The attribute that would overflow the struct is left out with its companion field
+        let counts = std::iter::repeat(1)
+            .take(MAX_STRUCT_FIELDS - 2)
+            .chain([2, 1]);
+        let fields = PartCFields::with_field_counts(counts);
+        assert_eq!(fields.attributes, MAX_STRUCT_FIELDS - 2);
+        assert_eq!(fields.truncated, 2);
+        assert_eq!(fields.field_count() as usize, MAX_STRUCT_FIELDS - 1);
+    }
+
+    #[test]
+    fn parta_app_extension() {
+        let app_extension = AppExtension::new("my_app", "1.2.3");
+        assert_eq!(app_extension.id.as_ref(), "my_app");
+        assert_eq!(app_extension.ver.as_ref(), "1.2.3");
+
+        assert_eq!(get_parta_field_count(None, false), 2);
+        assert_eq!(get_parta_field_count(Some(&app_extension), false), 3);
+    }
+
+    #[test]
+    fn parta_sdk_extension() {
+        let instrumentation_lib = InstrumentationLibrary::new("my_lib", Some("0.1.0"), None);
+        assert_eq!(get_sdk_extension(&instrumentation_lib), ("my_lib", "0.1.0"));
+
+        let instrumentation_lib = InstrumentationLibrary::new("my_lib", None, None);
+        assert_eq!(get_sdk_extension(&instrumentation_lib), ("my_lib", ""));
+
+        assert_eq!(get_parta_field_count(None, true), 3);
+        assert_eq!(
+            get_parta_field_count(Some(&AppExtension::new("my_app", "1.2.3")), true),
+            4
+        );
+    }
+
+    #[test]
+    fn promoted_db_fields() {
+        let mut attributes = EvictedHashMap::new(128, 4);
+        attributes.insert(KeyValue::new("db.system", "postgresql"));
+        attributes.insert(KeyValue::new("db.name", "customers"));
+        attributes.insert(KeyValue::new("db.statement", "SELECT * FROM orders"));
+        attributes.insert(KeyValue::new("net.peer.name", "db.example.com"));
+
+        let mut span_data = SpanData {
+            span_context: SpanContext::empty_context(),
+            parent_span_id: SpanId::INVALID,
+            span_kind: SpanKind::Client,
+            name: "query".into(),
+            start_time: SystemTime::now(),
+            end_time: SystemTime::now(),
+            attributes,
+            events: EvictedQueue::new(128),
+            links: EvictedQueue::new(128),
+            status: Status::Unset,
+            resource: Cow::Owned(Resource::empty()),
+            instrumentation_lib: InstrumentationLibrary::new("test", None, None),
+        };
+
+        let promoted = get_promoted_fields(&span_data);
+        let promoted_values: Vec<(&str, String)> = promoted
+            .iter()
+            .map(|field| (field.name, field.value.to_string()))
+            .collect();
+        assert_eq!(
+            promoted_values,
+            [
+                ("dbSystem", "postgresql".to_string()),
+                ("dbName", "customers".to_string()),
+                ("dbStatement", "SELECT * FROM orders".to_string()),
+            ]
+        );
+
+        let partc_keys: Vec<&str> = span_data
+            .attributes
+            .iter()
+            .filter(|(key, _)| !is_promoted(&promoted, key))
+            .map(|(key, _)| key.as_str())
+            .collect();
+        assert_eq!(partc_keys, ["net.peer.name"]);
+
+        span_data.span_kind = SpanKind::Server;
+        assert!(get_promoted_fields(&span_data).is_empty());
+    }
+
+    #[test]
+    fn ext_dt_trace_flags() {
+        let span_context = |trace_flags| {
+            SpanContext::new(
+                TraceId::from_bytes(1u128.to_be_bytes()),
+                SpanId::from_bytes(1u64.to_be_bytes()),
+                trace_flags,
+                false,
+                TraceState::default(),
+            )
+        };
+
+        assert_eq!(get_trace_flags(&span_context(TraceFlags::SAMPLED)), "01");
+        assert_eq!(get_trace_flags(&span_context(TraceFlags::default())), "00");
+        assert_eq!(get_ext_dt_field_count(false), 2);
+        assert_eq!(get_ext_dt_field_count(true), 3);
+    }
+}
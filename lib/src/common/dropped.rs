@@ -0,0 +1,80 @@
+use opentelemetry_sdk::export::trace::SpanData;
+
+/// The number of attributes, events, and links that were dropped from a span
+/// because of the span limits.
+pub(crate) struct DroppedCounts {
+    pub(crate) attributes: u32,
+    pub(crate) events: u32,
+    pub(crate) links: u32,
+}
+
+impl DroppedCounts {
+    pub(crate) fn from_span_data(span_data: &SpanData) -> Self {
+        DroppedCounts {
+            attributes: span_data.attributes.dropped_count(),
+            events: span_data.events.dropped_count(),
+            links: span_data.links.dropped_count(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry::trace::{
+        Event, Link, SpanContext, SpanId, SpanKind, Status, TraceFlags, TraceId, TraceState,
+    };
+    use opentelemetry::{InstrumentationLibrary, KeyValue};
+    use opentelemetry_sdk::trace::{EvictedHashMap, EvictedQueue};
+    use opentelemetry_sdk::Resource;
+    use std::borrow::Cow;
+    use std::time::SystemTime;
+
+    #[test]
+    fn dropped_counts() {
+        let mut attributes = EvictedHashMap::new(1, 2);
+        attributes.insert(KeyValue::new("first", 1));
+        attributes.insert(KeyValue::new("second", 2));
+
+        let mut events = EvictedQueue::new(1);
+        events.extend(
+            ["first", "second", "third"]
+                .into_iter()
+                .map(|name| Event::new(name, SystemTime::now(), vec![], 0)),
+        );
+
+        let mut links = EvictedQueue::new(1);
+        links.extend((1..=5u64).map(|i| {
+            Link::new(
+                SpanContext::new(
+                    TraceId::from_bytes(1u128.to_be_bytes()),
+                    SpanId::from_bytes(i.to_be_bytes()),
+                    TraceFlags::SAMPLED,
+                    false,
+                    TraceState::default(),
+                ),
+                vec![],
+            )
+        }));
+
+        let span_data = SpanData {
+            span_context: SpanContext::empty_context(),
+            parent_span_id: SpanId::INVALID,
+            span_kind: SpanKind::Internal,
+            name: "span".into(),
+            start_time: SystemTime::now(),
+            end_time: SystemTime::now(),
+            attributes,
+            events,
+            links,
+            status: Status::Unset,
+            resource: Cow::Owned(Resource::empty()),
+            instrumentation_lib: InstrumentationLibrary::new("test", None, None),
+        };
+
+        let dropped = DroppedCounts::from_span_data(&span_data);
+        assert_eq!(dropped.attributes, 1);
+        assert_eq!(dropped.events, 2);
+        assert_eq!(dropped.links, 4);
+    }
+}
@@ -0,0 +1,90 @@
+use std::time::{Duration, SystemTime};
+
+/// The time between the start and end of a span.
+/// A clock adjustment or an explicit end time can put the end time before the start time,
+/// so the duration is clamped to zero and `clock_skew` is set instead of going negative.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct SpanDuration {
+    pub(crate) duration: Duration,
+    pub(crate) clock_skew: bool,
+}
+
+impl SpanDuration {
+    pub(crate) fn new(start_time: &SystemTime, end_time: &SystemTime) -> Self {
+        match end_time.duration_since(*start_time) {
+            Ok(duration) => SpanDuration {
+                duration,
+                clock_skew: false,
+            },
+            Err(_) => SpanDuration {
+                duration: Duration::ZERO,
+                clock_skew: true,
+            },
+        }
+    }
+
+    pub(crate) fn as_millis(&self) -> u64 {
+        self.duration.as_millis() as u64
+    }
+
+    // In 100ns ticks, the same unit as FILETIME
+    pub(crate) fn as_100ns(&self) -> u64 {
+        (self.duration.as_nanos() / 100) as u64
+    }
+}
+
+/// The time from the start of a span to one of its events, in nanoseconds.
+/// Events timestamped before the span started have an offset of zero.
+pub(crate) fn get_offset_ns(start_time: &SystemTime, event_time: &SystemTime) -> u64 {
+    event_time
+        .duration_since(*start_time)
+        .map_or(0, |offset| offset.as_nanos() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn span_duration() {
+        let start_time = SystemTime::now();
+        let end_time = start_time + Duration::from_micros(1500);
+
+        let duration = SpanDuration::new(&start_time, &end_time);
+        assert_eq!(duration.as_millis(), 1);
+        assert_eq!(duration.as_100ns(), 15000);
+        assert!(!duration.clock_skew);
+
+        assert_eq!(
+            SpanDuration::new(&start_time, &(start_time + Duration::from_nanos(150))).as_100ns(),
+            1
+        );
+        assert!(!SpanDuration::new(&start_time, &start_time).clock_skew);
+    }
+
+    #[test]
+    fn clock_skew() {
+        let start_time = SystemTime::now();
+        let end_time = start_time - Duration::from_secs(5);
+
+        let duration = SpanDuration::new(&start_time, &end_time);
+        assert_eq!(duration.duration, Duration::ZERO);
+        assert_eq!(duration.as_millis(), 0);
+        assert_eq!(duration.as_100ns(), 0);
+        assert!(duration.clock_skew);
+    }
+
+    #[test]
+    fn event_offset() {
+        let start_time = SystemTime::now();
+        assert_eq!(
+            get_offset_ns(&start_time, &(start_time + Duration::from_secs(1))),
+            1_000_000_000
+        );
+        assert_eq!(get_offset_ns(&start_time, &start_time), 0);
+        assert_eq!(
+            get_offset_ns(&start_time, &(start_time - Duration::from_secs(1))),
+            0
+        );
+    }
+}
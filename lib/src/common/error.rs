@@ -33,3 +33,72 @@ impl opentelemetry::ExportError for LinuxError {
         "UserEvents TraceLogging"
     }
 }
+
+/// Some of the spans in a batch failed to export. The rest of the batch was still exported.
+#[derive(Debug)]
+pub struct BatchExportError {
+    /// The number of spans in the batch.
+    pub span_count: usize,
+    /// The error from each span that failed to export.
+    pub errors: Vec<opentelemetry::trace::TraceError>,
+}
+
+impl std::fmt::Display for BatchExportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_fmt(format_args!(
+            "{} of {} spans failed to export",
+            self.errors.len(),
+            self.span_count
+        ))?;
+        if let Some(err) = self.errors.first() {
+            f.write_fmt(format_args!(", the first with: {}", err))?;
+        }
+        Ok(())
+    }
+}
+impl std::error::Error for BatchExportError {}
+
+impl opentelemetry::ExportError for BatchExportError {
+    fn exporter_name(&self) -> &'static str {
+        if cfg!(target_os = "windows") {
+            "ETW TraceLogging"
+        } else {
+            "UserEvents TraceLogging"
+        }
+    }
+}
+
+/// Another provider with the same id is already registered in this process.
+/// Events from both providers are logged as if they came from one provider.
+#[derive(Debug)]
+pub struct DuplicateProviderError {
+    /// The provider id on Windows, or the provider name on Linux.
+    pub provider: String,
+}
+
+impl std::fmt::Display for DuplicateProviderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_fmt(format_args!(
+            "provider {} is already registered in this process",
+            self.provider
+        ))
+    }
+}
+impl std::error::Error for DuplicateProviderError {}
+
+#[derive(Debug)]
+pub enum InstallError {
+    /// A tracer provider from this exporter is already installed as the global tracer provider.
+    AlreadyInstalled,
+}
+
+impl std::fmt::Display for InstallError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InstallError::AlreadyInstalled => f.write_str(
+                "a tracer provider from this exporter is already installed as the global tracer provider",
+            ),
+        }
+    }
+}
+impl std::error::Error for InstallError {}
@@ -1,3 +1,18 @@
 pub trait EtwSpan {
     fn get_span_data(&self) -> &opentelemetry_sdk::export::trace::SpanData;
+
+    /// Whether the span's parent was propagated from another process.
+    fn is_remote_parent(&self) -> bool;
+
+    /// The keywords stashed in the span's parent context, which are added to the keywords
+    /// of the span and its events.
+    fn get_context_keyword(&self) -> Option<u64>;
+
+    /// The activity id stashed in the span's parent context, if the span is a root span
+    /// and the exporter was configured to use it instead of the derived activity id.
+    fn get_root_activity_id(&self) -> Option<[u8; 16]>;
+
+    /// The number of the span's children that had not ended when it ended,
+    /// if the exporter was configured to track child spans.
+    fn get_active_children(&self) -> Option<u32>;
 }
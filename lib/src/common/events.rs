@@ -0,0 +1,159 @@
+use opentelemetry::trace::Event;
+use opentelemetry::{Key, Value};
+use opentelemetry_sdk::trace::EvictedQueue;
+
+/// The span event attribute that adds keywords to the event, when per-event keywords are enabled.
+pub(crate) const EVENT_KEYWORD_KEY: Key = Key::from_static_str("etw.keyword");
+
+/// The keywords for a span event: `keywords`, OR'd with the value of the
+/// event's integer `etw.keyword` attribute if it has one.
+pub(crate) fn get_per_event_keywords(event: &Event, keywords: u64) -> u64 {
+    event
+        .attributes
+        .iter()
+        .find(|kv| kv.key == EVENT_KEYWORD_KEY)
+        .and_then(|kv| match kv.value {
+            Value::I64(value) => Some(value as u64),
+            _ => None,
+        })
+        .map_or(keywords, |event_keywords| keywords | event_keywords)
+}
+
+/// The attributes of a span event to add to its payload.
+/// The `etw.keyword` control attribute is left out if per-event keywords are enabled.
+pub(crate) fn get_event_attributes(
+    event: &Event,
+    per_event_keywords: bool,
+) -> impl Iterator<Item = (&Key, &Value)> {
+    event
+        .attributes
+        .iter()
+        .filter(move |kv| !per_event_keywords || kv.key != EVENT_KEYWORD_KEY)
+        .map(|kv| (&kv.key, &kv.value))
+}
+
+/// The total number of events added to the span, including any that were
+/// dropped because of the span limits.
+pub(crate) fn get_event_count(events: &EvictedQueue<Event>) -> u32 {
+    events.len() as u32 + events.dropped_count()
+}
+
+/// Limits the number of span events that are written for a span,
+/// and counts the events that are left out.
+pub(crate) struct EventLimit {
+    max: Option<usize>,
+    written: usize,
+    omitted: u64,
+}
+
+impl EventLimit {
+    pub(crate) fn new(max: Option<usize>) -> Self {
+        EventLimit {
+            max,
+            written: 0,
+            omitted: 0,
+        }
+    }
+
+    /// Whether another event can be written. Events past the limit are counted as omitted.
+    pub(crate) fn try_take(&mut self) -> bool {
+        if self.max.map_or(false, |max| self.written >= max) {
+            self.omitted += 1;
+            false
+        } else {
+            self.written += 1;
+            true
+        }
+    }
+
+    /// The number of events that were not written because of the limit.
+    pub(crate) fn omitted(&self) -> u64 {
+        self.omitted
+    }
+}
+
+#[cfg_attr(not(feature = "json"), allow(unused_variables))]
+pub(crate) fn get_event_names(events: &EvictedQueue<Event>, as_json: bool) -> String {
+    #[cfg(feature = "json")]
+    if as_json {
+        let names: Vec<&str> = events.iter().map(|e| e.name.as_ref()).collect();
+        if let Ok(json_string) = serde_json::to_string(&names) {
+            return json_string;
+        }
+    }
+
+    let mut names = String::with_capacity(events.len() * 16);
+    for event in events.iter() {
+        if !names.is_empty() {
+            names += ",";
+        }
+        names += &event.name;
+    }
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry::KeyValue;
+    use std::time::SystemTime;
+
+    #[test]
+    fn event_names() {
+        let mut events = EvictedQueue::new(128);
+        events.extend(
+            ["first", "second", "third"]
+                .into_iter()
+                .map(|name| Event::new(name, SystemTime::now(), vec![], 0)),
+        );
+
+        assert_eq!(get_event_count(&events), 3);
+        assert_eq!(get_event_names(&events, false), "first,second,third");
+
+        #[cfg(feature = "json")]
+        assert_eq!(
+            get_event_names(&events, true),
+            "[\"first\",\"second\",\"third\"]"
+        );
+    }
+
+    #[test]
+    fn event_limit() {
+        let mut limit = EventLimit::new(Some(2));
+        let taken = (0..5).filter(|_| limit.try_take()).count();
+        assert_eq!(taken, 2);
+        assert_eq!(limit.omitted(), 3);
+
+        let mut limit = EventLimit::new(None);
+        assert!((0..5).all(|_| limit.try_take()));
+        assert_eq!(limit.omitted(), 0);
+    }
+
+    #[test]
+    fn per_event_keywords() {
+        let events = [
+            Event::new(
+                "first",
+                SystemTime::now(),
+                vec![EVENT_KEYWORD_KEY.i64(0x10), KeyValue::new("a", 1)],
+                0,
+            ),
+            Event::new(
+                "second",
+                SystemTime::now(),
+                vec![KeyValue::new("a", 1), EVENT_KEYWORD_KEY.i64(0x20)],
+                0,
+            ),
+            Event::new("third", SystemTime::now(), vec![KeyValue::new("a", 1)], 0),
+        ];
+
+        assert_eq!(get_per_event_keywords(&events[0], 0x1), 0x11);
+        assert_eq!(get_per_event_keywords(&events[1], 0x1), 0x21);
+        assert_eq!(get_per_event_keywords(&events[2], 0x1), 0x1);
+
+        for event in events.iter() {
+            assert_eq!(get_event_attributes(event, true).count(), 1);
+        }
+        assert_eq!(get_event_attributes(&events[0], false).count(), 2);
+    }
+}
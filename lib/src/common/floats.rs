@@ -0,0 +1,76 @@
+use opentelemetry::{Array, Value};
+use std::borrow::Cow;
+
+/// Whether the attribute is an `f64` that is NaN or infinite,
+/// which can't be represented in JSON and confuses some event decoders.
+pub(crate) fn is_non_finite_f64(value: &Value) -> bool {
+    match value {
+        Value::F64(f) => !f.is_finite(),
+        _ => false,
+    }
+}
+
+/// Round `f` to `decimals` decimal places.
+/// Values that are too large to be scaled without overflowing are returned unchanged.
+pub(crate) fn round_f64(f: f64, decimals: u8) -> f64 {
+    let scale = 10f64.powi(decimals as i32);
+    let scaled = f * scale;
+    if scaled.is_finite() {
+        scaled.round() / scale
+    } else {
+        f
+    }
+}
+
+/// The attribute with its `f64` value, or each value of an `f64` array,
+/// rounded to `decimals` decimal places. Other attributes are returned as is.
+pub(crate) fn round_floats(value: &Value, decimals: Option<u8>) -> Cow<'_, Value> {
+    match (value, decimals) {
+        (Value::F64(f), Some(decimals)) => Cow::Owned(Value::F64(round_f64(*f, decimals))),
+        (Value::Array(Array::F64(v)), Some(decimals)) => Cow::Owned(Value::Array(Array::F64(
+            v.iter().map(|f| round_f64(*f, decimals)).collect(),
+        ))),
+        _ => Cow::Borrowed(value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn non_finite_f64() {
+        assert!(is_non_finite_f64(&Value::F64(f64::NAN)));
+        assert!(is_non_finite_f64(&Value::F64(f64::INFINITY)));
+        assert!(is_non_finite_f64(&Value::F64(f64::NEG_INFINITY)));
+
+        assert!(!is_non_finite_f64(&Value::F64(7.1)));
+        assert!(!is_non_finite_f64(&Value::I64(5)));
+
+        let array = Value::Array(Array::F64(vec![f64::NAN]));
+        assert!(!is_non_finite_f64(&array));
+    }
+
+    #[test]
+    #[allow(clippy::approx_constant)]
+    fn float_rounding() {
+        assert_eq!(round_f64(3.14159, 2), 3.14);
+        assert_eq!(round_f64(-2.5, 0), -3.0);
+        assert_eq!(round_f64(f64::MAX, 2), f64::MAX);
+        assert!(round_f64(f64::NAN, 2).is_nan());
+
+        assert_eq!(
+            *round_floats(&Value::F64(3.14159), Some(2)),
+            Value::F64(3.14)
+        );
+        assert_eq!(
+            *round_floats(&Value::Array(Array::F64(vec![3.14159, 2.71828])), Some(2)),
+            Value::Array(Array::F64(vec![3.14, 2.72]))
+        );
+        assert_eq!(
+            *round_floats(&Value::F64(3.14159), None),
+            Value::F64(3.14159)
+        );
+        assert_eq!(*round_floats(&Value::I64(3), Some(2)), Value::I64(3));
+    }
+}
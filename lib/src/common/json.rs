@@ -2,10 +2,26 @@
 
 use std::{borrow::Cow, collections::HashMap};
 
-use opentelemetry::{Array, Key, Value};
+use opentelemetry::{
+    trace::{Event, Link},
+    Array, Key, Value,
+};
 
 #[cfg(feature = "json")]
 pub(crate) fn get_attributes_as_json(attribs: &mut dyn Iterator<Item = (&Key, &Value)>) -> String {
+    let payload = get_attributes_as_json_map(attribs);
+
+    if let Ok(json_string) = serde_json::to_string(&payload) {
+        json_string
+    } else {
+        todo!()
+    }
+}
+
+#[cfg(feature = "json")]
+fn get_attributes_as_json_map(
+    attribs: &mut dyn Iterator<Item = (&Key, &Value)>,
+) -> std::collections::BTreeMap<String, serde_json::Value> {
     let mut payload: std::collections::BTreeMap<String, serde_json::Value> = Default::default();
 
     for attrib in attribs {
@@ -21,68 +37,118 @@ pub(crate) fn get_attributes_as_json(attribs: &mut dyn Iterator<Item = (&Key, &V
                 );
             }
             Value::F64(f) => {
-                payload.insert(
-                    field_name.clone(),
-                    serde_json::Value::Number(serde_json::Number::from_f64(*f).unwrap()),
-                );
+                payload.insert(field_name.clone(), get_f64_as_json_value(*f));
             }
             Value::String(s) => {
                 payload.insert(field_name.clone(), serde_json::Value::String(s.to_string()));
             }
-            Value::Array(array) => match array {
-                Array::Bool(v) => {
-                    payload.insert(
-                        field_name.clone(),
-                        serde_json::Value::Array(
-                            v.iter().map(|b| serde_json::Value::Bool(*b)).collect(),
-                        ),
-                    );
-                }
-                Array::I64(v) => {
-                    payload.insert(
-                        field_name.clone(),
-                        serde_json::Value::Array(
-                            v.iter()
-                                .map(|i| serde_json::Value::Number(serde_json::Number::from(*i)))
-                                .collect(),
-                        ),
-                    );
-                }
-                Array::F64(v) => {
-                    payload.insert(
-                        field_name.clone(),
-                        serde_json::Value::Array(
-                            v.iter()
-                                .map(|f| {
-                                    serde_json::Value::Number(
-                                        serde_json::Number::from_f64(*f).unwrap(),
-                                    )
-                                })
-                                .collect(),
-                        ),
-                    );
-                }
-                Array::String(v) => {
-                    payload.insert(
-                        field_name.clone(),
-                        serde_json::Value::Array(
-                            v.iter()
-                                .map(|s| serde_json::Value::String(s.to_string()))
-                                .collect(),
-                        ),
-                    );
-                }
-            },
+            Value::Array(array) => {
+                payload.insert(field_name.clone(), get_array_as_json_value(array));
+            }
         }
     }
 
-    if let Ok(json_string) = serde_json::to_string(&payload) {
-        json_string
-    } else {
-        todo!()
+    payload
+}
+
+/// Serialize span links as a JSON array of objects with the linked trace and span ids
+/// and the link's attributes, so that they can be logged as a single field.
+#[cfg(feature = "json")]
+pub(crate) fn get_links_as_json(links: &mut dyn Iterator<Item = &Link>) -> Option<String> {
+    let links: Vec<serde_json::Value> = links
+        .map(|link| {
+            serde_json::json!({
+                "traceId": link.span_context.trace_id().to_string(),
+                "spanId": link.span_context.span_id().to_string(),
+                "attributes": get_attributes_as_json_map(
+                    &mut link.attributes.iter().map(|kv| (&kv.key, &kv.value))
+                ),
+            })
+        })
+        .collect();
+
+    serde_json::to_string(&links).ok()
+}
+
+/// Inline links are only available with the `json` feature.
+#[cfg(not(feature = "json"))]
+pub(crate) fn get_links_as_json(_links: &mut dyn Iterator<Item = &Link>) -> Option<String> {
+    None
+}
+
+/// Serialize span events as a JSON array of objects with the event name, the event time
+/// in nanoseconds since the Unix epoch and the event's attributes,
+/// so that they can be logged as a single field.
+#[cfg(feature = "json")]
+pub(crate) fn get_events_as_json(events: &mut dyn Iterator<Item = &Event>) -> Option<String> {
+    let events: Vec<serde_json::Value> = events
+        .map(|event| {
+            let time = event
+                .timestamp
+                .duration_since(std::time::UNIX_EPOCH)
+                .map_or(0, |duration| duration.as_nanos() as u64);
+            serde_json::json!({
+                "name": event.name,
+                "timeUnixNano": time,
+                "attributes": get_attributes_as_json_map(
+                    &mut event.attributes.iter().map(|kv| (&kv.key, &kv.value))
+                ),
+            })
+        })
+        .collect();
+
+    serde_json::to_string(&events).ok()
+}
+
+/// Inline events are only available with the `json` feature.
+#[cfg(not(feature = "json"))]
+pub(crate) fn get_events_as_json(_events: &mut dyn Iterator<Item = &Event>) -> Option<String> {
+    None
+}
+
+/// JSON has no representation for NaN or infinite numbers, so they become `null`.
+#[cfg(feature = "json")]
+fn get_f64_as_json_value(f: f64) -> serde_json::Value {
+    serde_json::Number::from_f64(f).map_or(serde_json::Value::Null, serde_json::Value::Number)
+}
+
+#[cfg(feature = "json")]
+fn get_array_as_json_value(array: &Array) -> serde_json::Value {
+    match array {
+        Array::Bool(v) => {
+            serde_json::Value::Array(v.iter().map(|b| serde_json::Value::Bool(*b)).collect())
+        }
+        Array::I64(v) => serde_json::Value::Array(
+            v.iter()
+                .map(|i| serde_json::Value::Number(serde_json::Number::from(*i)))
+                .collect(),
+        ),
+        Array::F64(v) => {
+            serde_json::Value::Array(v.iter().map(|f| get_f64_as_json_value(*f)).collect())
+        }
+        Array::String(v) => serde_json::Value::Array(
+            v.iter()
+                .map(|s| serde_json::Value::String(s.to_string()))
+                .collect(),
+        ),
+    }
+}
+
+/// If the value is an array, serialize it as a JSON array string.
+/// Scalar values return `None` and should be logged as typed fields.
+#[cfg(feature = "json")]
+pub(crate) fn get_array_as_json(value: &Value) -> Option<String> {
+    match value {
+        Value::Array(array) => serde_json::to_string(&get_array_as_json_value(array)).ok(),
+        _ => None,
     }
 }
 
+/// Whether a string attribute already contains JSON, based on its key ending with `suffix`.
+pub(crate) fn is_json_string_key(key: &Key, suffix: Option<&str>) -> bool {
+    suffix.map_or(false, |suffix| key.as_str().ends_with(suffix))
+}
+
 #[allow(dead_code)]
 pub(crate) fn extract_common_schema_parta_exts<'a, C>(
     attributes: C,
@@ -153,3 +219,136 @@ where
 
     partA_exts
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry::StringValue;
+
+    #[test]
+    fn json_string_keys() {
+        assert!(is_json_string_key(&Key::new("payload.json"), Some(".json")));
+        assert!(!is_json_string_key(&Key::new("payload"), Some(".json")));
+        assert!(!is_json_string_key(&Key::new("payload.json"), None));
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn arrays_as_json() {
+        assert_eq!(
+            get_array_as_json(&Value::Array(Array::I64(vec![5, 6, 7]))).as_deref(),
+            Some("[5,6,7]")
+        );
+        assert_eq!(
+            get_array_as_json(&Value::Array(Array::String(vec![StringValue::from(
+                "is cool"
+            )])))
+            .as_deref(),
+            Some("[\"is cool\"]")
+        );
+
+        assert_eq!(get_array_as_json(&Value::I64(5)), None);
+        assert_eq!(get_array_as_json(&Value::from("is cool")), None);
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn large_integers_as_json() {
+        let attributes = [
+            opentelemetry::KeyValue::new("max", i64::MAX),
+            opentelemetry::KeyValue::new("min", i64::MIN),
+            Key::new("array").array(vec![i64::MAX, i64::MIN]),
+        ];
+
+        assert_eq!(
+            get_attributes_as_json(&mut attributes.iter().map(|kv| (&kv.key, &kv.value))),
+            "{\"array\":[9223372036854775807,-9223372036854775808],\"max\":9223372036854775807,\"min\":-9223372036854775808}"
+        );
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn non_finite_floats_as_json() {
+        let attributes = [
+            opentelemetry::KeyValue::new("nan", f64::NAN),
+            opentelemetry::KeyValue::new("inf", f64::INFINITY),
+            Key::new("array").array(vec![f64::NAN, 1.5, f64::INFINITY]),
+        ];
+
+        assert_eq!(
+            get_attributes_as_json(&mut attributes.iter().map(|kv| (&kv.key, &kv.value))),
+            "{\"array\":[null,1.5,null],\"inf\":null,\"nan\":null}"
+        );
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn links_as_json() {
+        use opentelemetry::trace::{SpanContext, SpanId, TraceFlags, TraceId, TraceState};
+
+        let links = [
+            Link::new(
+                SpanContext::new(
+                    TraceId::from_bytes(1u128.to_be_bytes()),
+                    SpanId::from_bytes(2u64.to_be_bytes()),
+                    TraceFlags::SAMPLED,
+                    false,
+                    TraceState::default(),
+                ),
+                vec![opentelemetry::KeyValue::new("reason", "retry")],
+            ),
+            Link::new(
+                SpanContext::new(
+                    TraceId::from_bytes(3u128.to_be_bytes()),
+                    SpanId::from_bytes(4u64.to_be_bytes()),
+                    TraceFlags::SAMPLED,
+                    false,
+                    TraceState::default(),
+                ),
+                vec![],
+            ),
+        ];
+
+        assert_eq!(
+            get_links_as_json(&mut links.iter()).as_deref(),
+            Some(concat!(
+                "[{\"attributes\":{\"reason\":\"retry\"},",
+                "\"spanId\":\"0000000000000002\",",
+                "\"traceId\":\"00000000000000000000000000000001\"},",
+                "{\"attributes\":{},",
+                "\"spanId\":\"0000000000000004\",",
+                "\"traceId\":\"00000000000000000000000000000003\"}]"
+            ))
+        );
+        assert_eq!(get_links_as_json(&mut [].iter()).as_deref(), Some("[]"));
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn events_as_json() {
+        use std::time::{Duration, UNIX_EPOCH};
+
+        let events = [
+            Event::new(
+                "retry",
+                UNIX_EPOCH + Duration::from_nanos(1_500),
+                vec![opentelemetry::KeyValue::new("attempt", 2)],
+                0,
+            ),
+            Event::new("done", UNIX_EPOCH + Duration::from_secs(2), vec![], 0),
+        ];
+
+        assert_eq!(
+            get_events_as_json(&mut events.iter()).as_deref(),
+            Some(concat!(
+                "[{\"attributes\":{\"attempt\":2},",
+                "\"name\":\"retry\",",
+                "\"timeUnixNano\":1500},",
+                "{\"attributes\":{},",
+                "\"name\":\"done\",",
+                "\"timeUnixNano\":2000000000}]"
+            ))
+        );
+        assert_eq!(get_events_as_json(&mut [].iter()).as_deref(), Some("[]"));
+    }
+}
@@ -1,5 +1,22 @@
 pub mod activities;
+pub mod activity_stack;
+pub mod arrays;
+pub mod attributes;
+pub mod children;
+pub mod coercion;
+pub mod common_schema;
+pub mod dropped;
+pub mod durations;
+pub mod events;
+pub mod floats;
 pub mod json;
+pub mod msgpack;
+pub mod registry;
+pub mod schema;
+pub mod split;
+pub mod status;
+pub mod threads;
+pub mod units;
 mod etw_span;
 mod constants;
 mod error;
@@ -0,0 +1,278 @@
+#![allow(unused_imports, dead_code)]
+
+use std::collections::BTreeMap;
+
+use opentelemetry::{Array, Key, Value};
+
+/// Serialize the attributes as a MessagePack map from attribute names to values,
+/// for consumers of the C++ exporter's MsgPack payload.
+/// Like the JSON payload, duplicate keys keep the last value and keys are sorted.
+#[cfg(feature = "msgpack")]
+pub(crate) fn get_attributes_as_msgpack(
+    attribs: &mut dyn Iterator<Item = (&Key, &Value)>,
+) -> Vec<u8> {
+    let payload: BTreeMap<&str, &Value> =
+        attribs.map(|(key, value)| (key.as_str(), value)).collect();
+
+    let mut buf = Vec::new();
+    write_map_len(&mut buf, payload.len());
+    for (key, value) in payload {
+        write_str(&mut buf, key);
+        write_value(&mut buf, value);
+    }
+    buf
+}
+
+fn write_value(buf: &mut Vec<u8>, value: &Value) {
+    match value {
+        Value::Bool(b) => write_bool(buf, *b),
+        Value::I64(i) => write_i64(buf, *i),
+        Value::F64(f) => write_f64(buf, *f),
+        Value::String(s) => write_str(buf, s.as_str()),
+        Value::Array(Array::Bool(array)) => {
+            write_array_len(buf, array.len());
+            array.iter().for_each(|b| write_bool(buf, *b));
+        }
+        Value::Array(Array::I64(array)) => {
+            write_array_len(buf, array.len());
+            array.iter().for_each(|i| write_i64(buf, *i));
+        }
+        Value::Array(Array::F64(array)) => {
+            write_array_len(buf, array.len());
+            array.iter().for_each(|f| write_f64(buf, *f));
+        }
+        Value::Array(Array::String(array)) => {
+            write_array_len(buf, array.len());
+            array.iter().for_each(|s| write_str(buf, s.as_str()));
+        }
+    }
+}
+
+fn write_bool(buf: &mut Vec<u8>, value: bool) {
+    buf.push(if value { 0xc3 } else { 0xc2 });
+}
+
+// Integers use the smallest signed encoding that fits the value
+fn write_i64(buf: &mut Vec<u8>, value: i64) {
+    if (-32..=127).contains(&value) {
+        // positive and negative fixint
+        buf.push(value as i8 as u8);
+    } else if let Ok(value) = i8::try_from(value) {
+        buf.push(0xd0);
+        buf.extend_from_slice(&value.to_be_bytes());
+    } else if let Ok(value) = i16::try_from(value) {
+        buf.push(0xd1);
+        buf.extend_from_slice(&value.to_be_bytes());
+    } else if let Ok(value) = i32::try_from(value) {
+        buf.push(0xd2);
+        buf.extend_from_slice(&value.to_be_bytes());
+    } else {
+        buf.push(0xd3);
+        buf.extend_from_slice(&value.to_be_bytes());
+    }
+}
+
+fn write_f64(buf: &mut Vec<u8>, value: f64) {
+    buf.push(0xcb);
+    buf.extend_from_slice(&value.to_be_bytes());
+}
+
+fn write_str(buf: &mut Vec<u8>, value: &str) {
+    let len = value.len();
+    if len < 32 {
+        buf.push(0xa0 | len as u8);
+    } else if len <= u8::MAX as usize {
+        buf.push(0xd9);
+        buf.push(len as u8);
+    } else if len <= u16::MAX as usize {
+        buf.push(0xda);
+        buf.extend_from_slice(&(len as u16).to_be_bytes());
+    } else {
+        buf.push(0xdb);
+        buf.extend_from_slice(&(len as u32).to_be_bytes());
+    }
+    buf.extend_from_slice(value.as_bytes());
+}
+
+fn write_array_len(buf: &mut Vec<u8>, len: usize) {
+    write_container_len(buf, len, 0x90, 0xdc);
+}
+
+fn write_map_len(buf: &mut Vec<u8>, len: usize) {
+    write_container_len(buf, len, 0x80, 0xde);
+}
+
+// The 32-bit marker always follows the 16-bit one
+fn write_container_len(buf: &mut Vec<u8>, len: usize, fix_marker: u8, marker16: u8) {
+    if len < 16 {
+        buf.push(fix_marker | len as u8);
+    } else if len <= u16::MAX as usize {
+        buf.push(marker16);
+        buf.extend_from_slice(&(len as u16).to_be_bytes());
+    } else {
+        buf.push(marker16 + 1);
+        buf.extend_from_slice(&(len as u32).to_be_bytes());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry::{KeyValue, StringValue};
+
+    // Just enough of a MessagePack decoder to read back what the encoder writes
+    struct Decoder<'a> {
+        buf: &'a [u8],
+    }
+
+    impl<'a> Decoder<'a> {
+        fn take(&mut self, len: usize) -> &'a [u8] {
+            let (head, rest) = self.buf.split_at(len);
+            self.buf = rest;
+            head
+        }
+
+        fn read_len(&mut self, size: usize) -> usize {
+            self.take(size)
+                .iter()
+                .fold(0, |len, byte| (len << 8) | *byte as usize)
+        }
+
+        fn read_map(&mut self) -> Vec<(String, Value)> {
+            let len = match self.take(1)[0] {
+                marker @ 0x80..=0x8f => (marker & 0x0f) as usize,
+                0xde => self.read_len(2),
+                0xdf => self.read_len(4),
+                marker => panic!("not a map: {:#x}", marker),
+            };
+            (0..len)
+                .map(|_| {
+                    let key = match self.read_value() {
+                        Value::String(s) => s.to_string(),
+                        value => panic!("not a string key: {:?}", value),
+                    };
+                    (key, self.read_value())
+                })
+                .collect()
+        }
+
+        fn read_array(&mut self, len: usize) -> Value {
+            let values: Vec<Value> = (0..len).map(|_| self.read_value()).collect();
+            match values.first() {
+                Some(Value::Bool(_)) => Value::Array(Array::Bool(
+                    values
+                        .into_iter()
+                        .map(|v| matches!(v, Value::Bool(true)))
+                        .collect(),
+                )),
+                Some(Value::I64(_)) => Value::Array(Array::I64(
+                    values
+                        .into_iter()
+                        .map(|v| if let Value::I64(i) = v { i } else { panic!() })
+                        .collect(),
+                )),
+                Some(Value::F64(_)) => Value::Array(Array::F64(
+                    values
+                        .into_iter()
+                        .map(|v| if let Value::F64(f) = v { f } else { panic!() })
+                        .collect(),
+                )),
+                _ => Value::Array(Array::String(
+                    values
+                        .into_iter()
+                        .map(|v| {
+                            if let Value::String(s) = v {
+                                s
+                            } else {
+                                panic!()
+                            }
+                        })
+                        .collect(),
+                )),
+            }
+        }
+
+        fn read_str(&mut self, len: usize) -> Value {
+            Value::String(StringValue::from(
+                String::from_utf8(self.take(len).to_vec()).unwrap(),
+            ))
+        }
+
+        fn read_value(&mut self) -> Value {
+            match self.take(1)[0] {
+                marker @ 0x00..=0x7f => Value::I64(marker as i64),
+                marker @ 0xe0..=0xff => Value::I64(marker as i8 as i64),
+                marker @ 0x90..=0x9f => self.read_array((marker & 0x0f) as usize),
+                marker @ 0xa0..=0xbf => self.read_str((marker & 0x1f) as usize),
+                0xc2 => Value::Bool(false),
+                0xc3 => Value::Bool(true),
+                0xcb => Value::F64(f64::from_be_bytes(self.take(8).try_into().unwrap())),
+                0xd0 => Value::I64(i8::from_be_bytes(self.take(1).try_into().unwrap()) as i64),
+                0xd1 => Value::I64(i16::from_be_bytes(self.take(2).try_into().unwrap()) as i64),
+                0xd2 => Value::I64(i32::from_be_bytes(self.take(4).try_into().unwrap()) as i64),
+                0xd3 => Value::I64(i64::from_be_bytes(self.take(8).try_into().unwrap())),
+                0xd9 => {
+                    let len = self.read_len(1);
+                    self.read_str(len)
+                }
+                0xda => {
+                    let len = self.read_len(2);
+                    self.read_str(len)
+                }
+                0xdc => {
+                    let len = self.read_len(2);
+                    self.read_array(len)
+                }
+                marker => panic!("unexpected marker: {:#x}", marker),
+            }
+        }
+    }
+
+    #[cfg(feature = "msgpack")]
+    #[test]
+    fn attributes_as_msgpack() {
+        let attribs = vec![
+            KeyValue::new("str", "is cool"),
+            KeyValue::new("long_str", "x".repeat(300)),
+            KeyValue::new("bool", true),
+            KeyValue::new("int", 5),
+            KeyValue::new("negative", -20),
+            KeyValue::new("big", -70000),
+            KeyValue::new("huge", i64::MAX),
+            KeyValue::new("float", 7.5),
+            KeyValue::new("bools", Value::Array(Array::Bool(vec![false, true]))),
+            KeyValue::new("ints", Value::Array(Array::I64((0..20).collect()))),
+            KeyValue::new("floats", Value::Array(Array::F64(vec![0.25, -1.5]))),
+            KeyValue::new(
+                "strs",
+                Value::Array(Array::String(vec![
+                    StringValue::from("a"),
+                    StringValue::from("b"),
+                ])),
+            ),
+        ];
+
+        let blob = get_attributes_as_msgpack(&mut attribs.iter().map(|kv| (&kv.key, &kv.value)));
+
+        let mut decoder = Decoder { buf: &blob };
+        let decoded = decoder.read_map();
+        assert!(decoder.buf.is_empty());
+
+        let mut expected: Vec<(String, Value)> = attribs
+            .into_iter()
+            .map(|kv| (kv.key.to_string(), kv.value))
+            .collect();
+        expected.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(decoded, expected);
+    }
+
+    #[cfg(feature = "msgpack")]
+    #[test]
+    fn duplicate_keys_keep_last_value() {
+        let attribs = [KeyValue::new("key", 1), KeyValue::new("key", 2)];
+
+        let blob = get_attributes_as_msgpack(&mut attribs.iter().map(|kv| (&kv.key, &kv.value)));
+
+        assert_eq!(blob, vec![0x81, 0xa3, b'k', b'e', b'y', 0x02]);
+    }
+}
@@ -0,0 +1,71 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use super::DuplicateProviderError;
+
+// The number of live registrations of each provider in this process.
+static REGISTERED_PROVIDERS: Mutex<Option<HashMap<String, usize>>> = Mutex::new(None);
+
+/// Records that a provider is registered in this process until it is dropped.
+/// ETW merges providers with the same id, and user_events tracepoints with the same name
+/// are shared, so a second registration of the same provider is reported as an error.
+pub(crate) struct ProviderRegistration {
+    key: String,
+}
+
+impl ProviderRegistration {
+    /// Record the registration of the provider identified by `key`.
+    /// The error is returned along with the registration, which is still recorded,
+    /// so that the provider can be used anyway.
+    pub(crate) fn new(key: String) -> (Self, Option<DuplicateProviderError>) {
+        let mut registered = REGISTERED_PROVIDERS
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        let count = registered
+            .get_or_insert_with(HashMap::new)
+            .entry(key.clone())
+            .or_default();
+        *count += 1;
+
+        let err = (*count > 1).then(|| DuplicateProviderError {
+            provider: key.clone(),
+        });
+        (ProviderRegistration { key }, err)
+    }
+}
+
+impl Drop for ProviderRegistration {
+    fn drop(&mut self) {
+        let mut registered = REGISTERED_PROVIDERS
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        if let Some(registered) = registered.as_mut() {
+            if let Some(count) = registered.get_mut(&self.key) {
+                *count -= 1;
+                if *count == 0 {
+                    registered.remove(&self.key);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn duplicate_registration() {
+        let (first, err) = ProviderRegistration::new("duplicate_registration_test".into());
+        assert!(err.is_none());
+
+        let (second, err) = ProviderRegistration::new("duplicate_registration_test".into());
+        assert_eq!(err.unwrap().provider, "duplicate_registration_test");
+
+        // A provider can be registered again once every earlier registration is gone
+        drop(first);
+        drop(second);
+        let (_third, err) = ProviderRegistration::new("duplicate_registration_test".into());
+        assert!(err.is_none());
+    }
+}
@@ -0,0 +1,70 @@
+#![allow(dead_code)]
+
+/// The built-in fields the exporters can add to span events, with their types.
+/// Attribute fields are not included, since their names come from the instrumentation.
+pub(crate) const SPAN_EVENT_FIELDS: &[(&str, &str)] = &[
+    ("otel_event_time", "time"),
+    ("StartTime", "time"),
+    ("EndTime", "time"),
+    ("DurationMs", "u64"),
+    ("Duration100ns", "u64"),
+    ("ClockSkew", "bool"),
+    ("Kind", "string"),
+    ("Status", "string"),
+    ("StatusMessage", "string"),
+    ("SpanId", "string"),
+    ("ParentId", "string"),
+    ("TraceId", "string"),
+    ("traceparent", "string"),
+    ("IsRemoteParent", "bool"),
+    ("Links", "json"),
+    ("Events", "json"),
+    ("ThreadName", "string"),
+    ("ActiveChildren", "u32"),
+    ("Component", "string"),
+    ("ServiceInstanceId", "string"),
+    ("AttrCount", "u16"),
+    ("Attributes", "struct"),
+    ("LinkAttrCount", "u16"),
+    ("Part", "u32"),
+    ("PartCount", "u32"),
+    ("EventCount", "u32"),
+    ("EventNames", "string"),
+    ("OffsetNs", "u64"),
+    ("DroppedAttributes", "u32"),
+    ("DroppedEvents", "u32"),
+    ("DroppedLinks", "u32"),
+    ("Payload", "json"),
+];
+
+/// Serialize the built-in field names and types as a JSON array of `{"name":..,"type":..}`
+/// objects for the `ProviderSchema` event.
+/// The names are all plain identifiers, so this doesn't need the `json` feature.
+pub(crate) fn get_provider_schema_json() -> String {
+    let fields: Vec<String> = SPAN_EVENT_FIELDS
+        .iter()
+        .map(|(name, ty)| format!("{{\"name\":\"{}\",\"type\":\"{}\"}}", name, ty))
+        .collect();
+    format!("[{}]", fields.join(","))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn provider_schema_json() {
+        let json = get_provider_schema_json();
+
+        assert!(json.starts_with("[{\"name\":\"otel_event_time\",\"type\":\"time\"},"));
+        assert!(json.contains("{\"name\":\"SpanId\",\"type\":\"string\"}"));
+        assert!(json.ends_with("}]"));
+        assert_eq!(json.matches("\"name\"").count(), SPAN_EVENT_FIELDS.len());
+
+        #[cfg(feature = "json")]
+        {
+            let parsed: serde_json::Value = serde_json::from_str(&json).unwrap();
+            assert_eq!(parsed.as_array().unwrap().len(), SPAN_EVENT_FIELDS.len());
+        }
+    }
+}
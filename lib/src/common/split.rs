@@ -0,0 +1,90 @@
+use opentelemetry::{Array, Key, Value};
+
+/// A rough estimate of the number of bytes an attribute will take up in an event,
+/// including the field name and type information.
+pub(crate) fn get_attribute_size(key: &Key, value: &Value) -> usize {
+    let value_size = match value {
+        Value::Bool(_) => 4,
+        Value::I64(_) | Value::F64(_) => 8,
+        Value::String(s) => s.as_str().len() + 1,
+        Value::Array(array) => {
+            2 + match array {
+                Array::Bool(v) => v.len() * 4,
+                Array::I64(v) => v.len() * 8,
+                Array::F64(v) => v.len() * 8,
+                Array::String(v) => v.iter().map(|s| s.as_str().len() + 1).sum(),
+            }
+        }
+    };
+
+    key.as_str().len() + 1 + 2 + value_size
+}
+
+/// Split attributes into parts that each fit within `max_size` bytes.
+/// An attribute that is larger than `max_size` by itself is placed in its own part.
+/// At least one (possibly empty) part is always returned.
+pub(crate) fn split_attributes<'k, 'v>(
+    attributes: &mut dyn Iterator<Item = (&'k Key, &'v Value)>,
+    max_size: usize,
+) -> Vec<Vec<(&'k Key, &'v Value)>> {
+    let mut parts = vec![Vec::new()];
+    let mut part_size = 0;
+
+    for (key, value) in attributes {
+        let size = get_attribute_size(key, value);
+        if part_size + size > max_size && part_size > 0 {
+            parts.push(Vec::new());
+            part_size = 0;
+        }
+
+        part_size += size;
+        parts.last_mut().unwrap().push((key, value));
+    }
+
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry::KeyValue;
+
+    #[test]
+    fn split_large_attributes() {
+        let attributes: Vec<KeyValue> = (0..10)
+            .map(|i| KeyValue::new(format!("attribute{}", i), "x".repeat(1000)))
+            .collect();
+
+        let parts = split_attributes(&mut attributes.iter().map(|kv| (&kv.key, &kv.value)), 2500);
+        assert_eq!(parts.len(), 5);
+
+        for part in parts.iter() {
+            let size: usize = part.iter().map(|(k, v)| get_attribute_size(k, v)).sum();
+            assert!(size <= 2500);
+        }
+
+        let rejoined: Vec<KeyValue> = parts
+            .into_iter()
+            .flatten()
+            .map(|(k, v)| KeyValue::new(k.clone(), v.clone()))
+            .collect();
+        assert_eq!(rejoined, attributes);
+    }
+
+    #[test]
+    fn split_oversized_attribute() {
+        let attributes = [
+            KeyValue::new("small", 1),
+            KeyValue::new("huge", "x".repeat(5000)),
+            KeyValue::new("small2", 2),
+        ];
+
+        let parts = split_attributes(&mut attributes.iter().map(|kv| (&kv.key, &kv.value)), 100);
+        assert_eq!(parts.len(), 3);
+        assert_eq!(parts[1][0].0.as_str(), "huge");
+
+        let parts = split_attributes(&mut std::iter::empty(), 100);
+        assert_eq!(parts.len(), 1);
+        assert!(parts[0].is_empty());
+    }
+}
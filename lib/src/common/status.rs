@@ -0,0 +1,85 @@
+use opentelemetry::trace::Status;
+use opentelemetry_sdk::trace::EvictedHashMap;
+use std::borrow::Cow;
+
+/// The span attribute that [`get_status_message`] preserves as the message of an Ok span.
+pub(crate) const OK_DESCRIPTION_ATTRIBUTE: &str = "status.description";
+
+/// The level for a span's end event, based on its status.
+/// Error spans use the Error level, so they can be collected by sessions that only enable errors.
+pub(crate) fn get_status_level(status: &Status) -> u8 {
+    match status {
+        Status::Ok => 4,           // Informational
+        Status::Error { .. } => 2, // Error
+        Status::Unset => 5,        // Verbose
+    }
+}
+
+/// The value of the `Status` field for a span's status, or `None` if the status is unset.
+pub(crate) fn get_status_name(status: &Status) -> Option<&'static str> {
+    match status {
+        Status::Ok => Some("Ok"),
+        Status::Error { .. } => Some("Error"),
+        Status::Unset => None,
+    }
+}
+
+/// The value of the `StatusMessage` field for a span: the description of an Error status.
+/// The spec drops descriptions of Ok statuses, so with `preserve_ok_description`,
+/// an Ok span's `status.description` attribute is used instead, if it has one.
+pub(crate) fn get_status_message<'a>(
+    status: &'a Status,
+    attributes: &'a EvictedHashMap,
+    preserve_ok_description: bool,
+) -> Option<Cow<'a, str>> {
+    match status {
+        Status::Error { description } => Some(Cow::Borrowed(description.as_ref())),
+        Status::Ok if preserve_ok_description => attributes
+            .iter()
+            .find(|(key, _)| key.as_str() == OK_DESCRIPTION_ATTRIBUTE)
+            .map(|(_, value)| value.as_str()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry::KeyValue;
+
+    #[test]
+    fn status_levels() {
+        assert_eq!(get_status_level(&Status::Ok), 4);
+        assert_eq!(get_status_level(&Status::error("failed")), 2);
+        assert_eq!(get_status_level(&Status::Unset), 5);
+    }
+
+    #[test]
+    fn status_names() {
+        assert_eq!(get_status_name(&Status::Ok), Some("Ok"));
+        assert_eq!(get_status_name(&Status::error("failed")), Some("Error"));
+        assert_eq!(get_status_name(&Status::Unset), None);
+    }
+
+    #[test]
+    fn status_messages() {
+        let mut attributes = EvictedHashMap::new(128, 1);
+        assert_eq!(
+            get_status_message(&Status::error("failed"), &attributes, false).as_deref(),
+            Some("failed")
+        );
+        assert_eq!(get_status_message(&Status::Ok, &attributes, true), None);
+
+        attributes.insert(KeyValue::new(OK_DESCRIPTION_ATTRIBUTE, "retried"));
+        assert_eq!(get_status_message(&Status::Ok, &attributes, false), None);
+        assert_eq!(
+            get_status_message(&Status::Ok, &attributes, true).as_deref(),
+            Some("retried")
+        );
+        assert_eq!(get_status_message(&Status::Unset, &attributes, true), None);
+        assert_eq!(
+            get_status_message(&Status::error("failed"), &attributes, true).as_deref(),
+            Some("failed")
+        );
+    }
+}
@@ -0,0 +1,30 @@
+/// The name of the current thread, or its id if the thread has no name.
+pub(crate) fn get_current_thread_name() -> String {
+    let thread = std::thread::current();
+    match thread.name() {
+        Some(name) => name.to_string(),
+        None => format!("{:?}", thread.id()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn thread_name() {
+        let name = std::thread::Builder::new()
+            .name("my_worker".to_string())
+            .spawn(get_current_thread_name)
+            .unwrap()
+            .join()
+            .unwrap();
+        assert_eq!(name, "my_worker");
+
+        let (id, name) =
+            std::thread::spawn(|| (std::thread::current().id(), get_current_thread_name()))
+                .join()
+                .unwrap();
+        assert_eq!(name, format!("{:?}", id));
+    }
+}
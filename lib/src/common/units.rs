@@ -0,0 +1,74 @@
+use opentelemetry::{Key, Value};
+use std::collections::HashMap;
+
+const UNIT_SUFFIX: &str = ".unit";
+
+/// Replace each `<name>.unit` attribute that has a matching `<name>` attribute
+/// with a `<name>@unit` attribute that directly follows the value it describes.
+/// Unit attributes without a matching attribute are left as they are.
+pub(crate) fn annotate_units(
+    attributes: &mut dyn Iterator<Item = (&Key, &Value)>,
+) -> Vec<(Key, Value)> {
+    let attributes: Vec<(&Key, &Value)> = attributes.collect();
+
+    let mut units: HashMap<&str, &Value> = attributes
+        .iter()
+        .filter_map(|(key, value)| {
+            key.as_str()
+                .strip_suffix(UNIT_SUFFIX)
+                .map(|name| (name, *value))
+        })
+        .collect();
+    units.retain(|name, _| attributes.iter().any(|(key, _)| key.as_str() == *name));
+
+    let mut annotated = Vec::with_capacity(attributes.len());
+    for (key, value) in attributes.iter() {
+        let is_paired_unit = key
+            .as_str()
+            .strip_suffix(UNIT_SUFFIX)
+            .map_or(false, |name| units.contains_key(name));
+        if is_paired_unit {
+            continue;
+        }
+
+        annotated.push(((*key).clone(), (*value).clone()));
+
+        if let Some(unit) = units.get(key.as_str()) {
+            annotated.push((Key::new(format!("{}@unit", key.as_str())), (*unit).clone()));
+        }
+    }
+
+    annotated
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry::KeyValue;
+
+    #[test]
+    fn unit_annotations() {
+        let attributes = [
+            KeyValue::new("latency", 15),
+            KeyValue::new("size.unit", "bytes"),
+            KeyValue::new("latency.unit", "ms"),
+            KeyValue::new("other", "value"),
+        ];
+
+        let annotated = annotate_units(&mut attributes.iter().map(|kv| (&kv.key, &kv.value)));
+        let annotated: Vec<(&str, String)> = annotated
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.to_string()))
+            .collect();
+
+        assert_eq!(
+            annotated,
+            [
+                ("latency", "15".to_string()),
+                ("latency@unit", "ms".to_string()),
+                ("size.unit", "bytes".to_string()),
+                ("other", "value".to_string()),
+            ]
+        );
+    }
+}
@@ -1,6 +1,10 @@
 #![allow(unused_imports, unused_mut, unused_variables)]
 
 use crate::{exporter_traits::*, common::{json, activities::*, EtwSpan, *}};
+use crate::common::{
+    attributes, common_schema, dropped::DroppedCounts, durations::SpanDuration, events, floats,
+    msgpack, split, status, threads, units,
+};
 use chrono::{Datelike, Timelike};
 use opentelemetry::Array;
 use opentelemetry::{
@@ -8,15 +12,17 @@ use opentelemetry::{
     Key, Value,
 };
 use opentelemetry_sdk::export::trace::{ExportResult, SpanData};
+use opentelemetry_sdk::trace::EvictedQueue;
 use std::borrow::Cow;
 use std::cell::RefCell;
 use std::io::{Cursor, Write};
 use std::mem::MaybeUninit;
+use std::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
 use std::sync::Arc;
 use std::{pin::Pin, time::SystemTime};
 use tracelogging_dynamic::*;
 
-thread_local! {static EBW: RefCell<EtwEventBuilderWrapper> = RefCell::new(EtwEventBuilderWrapper::new());}
+thread_local! {static EBW: RefCell<EventBuilder> = RefCell::new(EventBuilder::new());}
 
 struct Win32SystemTime {
     st: [u16; 8],
@@ -41,14 +47,136 @@ impl From<std::time::SystemTime> for Win32SystemTime {
     }
 }
 
-struct EtwEventBuilderWrapper {
-    eb: EventBuilder,
+/// The fields of a span start or end event, gathered once by the caller so that
+/// each part of a split event is written from the same values.
+#[derive(Clone, Copy)]
+struct SpanEventFields<'a> {
+    name: &'a str,
+    level: Level,
+    keywords: u64,
+    activities: &'a Activities,
+    traceparent: Option<&'a str>,
+    remote_parent: Option<bool>,
+    inline_links: Option<&'a str>,
+    inline_events: Option<&'a str>,
+    thread_name: Option<&'a str>,
+    active_children: Option<u32>,
+    component: Option<&'a str>,
+    attribute_count: Option<u16>,
+    filetime_duration: Option<SpanDuration>,
+    event_time: &'a SystemTime,
+    /// Only set for a single span event, which has both the start and end time of the span.
+    start_time: Option<&'a SystemTime>,
+    span_kind: Option<&'a SpanKind>,
+    status: &'a Status,
+    status_message: Option<&'a str>,
+    span_events: Option<&'a EvictedQueue<Event>>,
+    dropped_counts: Option<&'a DroppedCounts>,
+    event_split: Option<usize>,
+    is_start: bool,
+    add_tags: bool,
+    use_byte_for_bools: bool,
+    arrays_as_json: bool,
+    export_payload_as_json: bool,
+}
+
+/// The fields shared by the span events of a span, each written as its own ETW event.
+#[derive(Clone, Copy)]
+struct SpanEventsFields<'a> {
+    level: Level,
+    keywords: u64,
+    activities: &'a Activities,
+    span_start_time: &'a SystemTime,
+    component: Option<&'a str>,
+    use_byte_for_bools: bool,
+    arrays_as_json: bool,
+    export_payload_as_json: bool,
+    per_event_keywords: bool,
+}
+
+fn utf16_from_utf8(value: &[u8]) -> Vec<u16> {
+    String::from_utf8_lossy(value).encode_utf16().collect()
+}
+
+// Builds an event on the thread's EventBuilder with the options of the exporter writing it.
+struct EtwEventBuilderWrapper<'a, C: KeywordLevelProvider> {
+    eb: &'a mut EventBuilder,
+    exporter_config: &'a ExporterConfig<C>,
+    // The name, level and keywords of the event being built, only kept when there is an event tap.
+    event_meta: Option<(String, u8, u64)>,
 }
 
-impl EtwEventBuilderWrapper {
-    pub fn new() -> EtwEventBuilderWrapper {
+impl<'a, C: KeywordLevelProvider> EtwEventBuilderWrapper<'a, C> {
+    fn new(eb: &'a mut EventBuilder, exporter_config: &'a ExporterConfig<C>) -> Self {
         EtwEventBuilderWrapper {
-            eb: EventBuilder::new(),
+            eb,
+            exporter_config,
+            event_meta: None,
+        }
+    }
+
+    // Shadows EventBuilder::reset, so that every event is written to the configured channel
+    // with the configured event tags.
+    fn reset(&mut self, name: &str, level: Level, keyword: u64, event_tag: u32) -> &mut Self {
+        self.eb.reset(
+            name,
+            level,
+            keyword,
+            event_tag | self.exporter_config.get_event_tags(),
+        );
+        self.eb.channel(
+            self.exporter_config
+                .get_etw_channel()
+                .map_or(Channel::TraceLogging, Channel::from_int),
+        );
+        self.event_meta = self
+            .exporter_config
+            .get_event_tap()
+            .map(|_| (name.to_owned(), level.as_int(), keyword));
+        self
+    }
+
+    // Shadows EventBuilder::write, so that the event tap is called for every written event.
+    fn write(
+        &mut self,
+        provider: &Provider,
+        activity_id: Option<&Guid>,
+        related_id: Option<&Guid>,
+    ) -> u32 {
+        let win32err = self.eb.write(provider, activity_id, related_id);
+        if win32err == 0 {
+            if let (Some(event_tap), Some((name, level, keyword))) =
+                (self.exporter_config.get_event_tap(), &self.event_meta)
+            {
+                event_tap(&EventMeta {
+                    name,
+                    level: *level,
+                    keyword: *keyword,
+                });
+            }
+        }
+        win32err
+    }
+
+    fn get_string_out_type(&self, key: &Key) -> OutType {
+        if json::is_json_string_key(
+            key,
+            self.exporter_config
+                .get_json_string_suffix()
+                .map(Arc::as_ref),
+        ) {
+            OutType::Json
+        } else {
+            match attributes::find_string_out_type(
+                key,
+                self.exporter_config
+                    .get_string_outtype_map()
+                    .map(Arc::as_ref),
+            ) {
+                Some(StringOutType::Xml) => OutType::Xml,
+                Some(StringOutType::Json) => OutType::Json,
+                None => OutType::Utf8,
+            }
         }
     }
 
@@ -73,8 +201,17 @@ impl EtwEventBuilderWrapper {
         field_value: impl AsRef<[u8]>,
         field_tag: u32,
     ) -> &mut Self {
-        self.eb
-            .add_str8(field_name, field_value, OutType::Utf8, field_tag);
+        if self.exporter_config.get_utf16_strings() {
+            self.eb.add_str16(
+                field_name,
+                utf16_from_utf8(field_value.as_ref()),
+                OutType::Default,
+                field_tag,
+            );
+        } else {
+            self.eb
+                .add_str8(field_name, field_value, OutType::Utf8, field_tag);
+        }
         self
     }
 
@@ -82,10 +219,102 @@ impl EtwEventBuilderWrapper {
         &mut self,
         attribs: &mut dyn Iterator<Item = (&Key, &Value)>,
         use_byte_for_bools: bool,
+        arrays_as_json: bool,
+    ) {
+        // Copied out of self, so that the closure below can borrow self
+        let exporter_config = self.exporter_config;
+        let attribute_filter = exporter_config.get_attribute_filter().map(Arc::as_ref);
+        let value_redaction = exporter_config.get_value_redaction();
+        let attribs = &mut attributes::filter_attributes(attribs, attribute_filter);
+
+        attributes::with_redacted_values(attribs, value_redaction, |attribs| {
+            if self.exporter_config.get_unit_annotations() {
+                let annotated = units::annotate_units(attribs);
+                self.add_attribute_fields(
+                    &mut annotated.iter().map(|(key, value)| (key, value)),
+                    use_byte_for_bools,
+                    arrays_as_json,
+                );
+            } else {
+                self.add_attribute_fields(attribs, use_byte_for_bools, arrays_as_json);
+            }
+        })
+    }
+
+    // Adds the struct that holds a span event's attributes, or its payload field.
+    // Attributes that don't fit in a struct are logged as top-level fields instead.
+    fn add_attributes_struct(
+        &mut self,
+        attribs: &mut dyn Iterator<Item = (&Key, &Value)>,
+        is_payload: bool,
+    ) {
+        let field_count = if is_payload {
+            1
+        } else {
+            attributes::get_attribute_field_count(
+                attribs,
+                self.exporter_config.get_attribute_filter().map(Arc::as_ref),
+                self.exporter_config.get_value_redaction(),
+                self.exporter_config.get_float_sanitization(),
+                self.exporter_config.get_max_array_length(),
+            )
+        };
+
+        if field_count <= common_schema::MAX_STRUCT_FIELDS {
+            self.add_struct("Attributes", field_count as u8, 0);
+        }
+    }
+
+    // The attributes of a JSON or MessagePack payload, after the attribute filter and
+    // value redaction.
+    #[cfg(any(feature = "json", feature = "msgpack"))]
+    fn with_payload_attributes<R>(
+        &self,
+        attribs: &mut dyn Iterator<Item = (&Key, &Value)>,
+        f: impl FnOnce(&mut dyn Iterator<Item = (&Key, &Value)>) -> R,
+    ) -> R {
+        attributes::with_redacted_values(
+            &mut attributes::filter_attributes(
+                attribs,
+                self.exporter_config.get_attribute_filter().map(Arc::as_ref),
+            ),
+            self.exporter_config.get_value_redaction(),
+            f,
+        )
+    }
+
+    fn add_attribute_fields(
+        &mut self,
+        attribs: &mut dyn Iterator<Item = (&Key, &Value)>,
+        use_byte_for_bools: bool,
+        arrays_as_json: bool,
     ) {
         for attrib in attribs {
-            let field_name = &attrib.0.to_string();
-            match attrib.1 {
+            if self.exporter_config.get_float_sanitization() && floats::is_non_finite_f64(attrib.1)
+            {
+                continue;
+            }
+
+            let field_name = &attributes::get_field_name(
+                attrib.0,
+                self.exporter_config.get_attribute_prefix().map(Arc::as_ref),
+            );
+            let value = floats::round_floats(attrib.1, self.exporter_config.get_float_rounding());
+            let (value, truncated) =
+                arrays::truncate_array(value, self.exporter_config.get_max_array_length());
+
+            #[cfg(feature = "json")]
+            if arrays_as_json {
+                if let Some(json_string) = json::get_array_as_json(&value) {
+                    self.add_str8(field_name, &json_string, OutType::Json, 0);
+                    if truncated {
+                        self.add_truncation_marker(field_name, use_byte_for_bools);
+                    }
+                    continue;
+                }
+            }
+
+            match value.as_ref() {
                 Value::Bool(b) => {
                     if use_byte_for_bools {
                         self.add_u8(field_name, *b as u8, OutType::Boolean, 0);
@@ -100,7 +329,26 @@ impl EtwEventBuilderWrapper {
                     self.add_f64(field_name, *f, OutType::Signed, 0);
                 }
                 Value::String(s) => {
-                    self.add_str8(field_name, &s.to_string(), OutType::Utf8, 0);
+                    let out_type = self.get_string_out_type(attrib.0);
+                    if out_type != OutType::Utf8 {
+                        self.add_str8(field_name, s.as_str(), out_type, 0);
+                    } else if let Some(number) = self
+                        .exporter_config
+                        .get_numeric_string_coercion()
+                        .then(|| coercion::parse_numeric_string(s.as_str()))
+                        .flatten()
+                    {
+                        match number {
+                            coercion::NumericString::I64(i) => {
+                                self.add_i64(field_name, i, OutType::Signed, 0);
+                            }
+                            coercion::NumericString::F64(f) => {
+                                self.add_f64(field_name, f, OutType::Signed, 0);
+                            }
+                        }
+                    } else {
+                        self.add_string(field_name, s.as_str(), 0);
+                    }
                 }
                 Value::Array(array) => match array {
                     Array::Bool(v) => {
@@ -127,15 +375,38 @@ impl EtwEventBuilderWrapper {
                         self.add_f64_sequence(field_name, v.iter(), OutType::Signed, 0);
                     }
                     Array::String(v) => {
-                        self.add_str8_sequence(
-                            field_name,
-                            v.iter().map(|s| s.to_string()),
-                            OutType::Utf8,
-                            0,
-                        );
+                        if self.exporter_config.get_utf16_strings() {
+                            self.add_str16_sequence(
+                                field_name,
+                                v.iter().map(|s| utf16_from_utf8(s.as_str().as_bytes())),
+                                OutType::Default,
+                                0,
+                            );
+                        } else {
+                            self.add_str8_sequence(
+                                field_name,
+                                v.iter().map(|s| s.to_string()),
+                                OutType::Utf8,
+                                0,
+                            );
+                        }
                     }
                 },
             }
+
+            if truncated {
+                self.add_truncation_marker(field_name, use_byte_for_bools);
+            }
+        }
+    }
+
+    // Follows an array field that was cut short by the maximum array length.
+    fn add_truncation_marker(&mut self, field_name: &str, use_byte_for_bools: bool) {
+        let marker_name = format!("{}{}", field_name, arrays::TRUNCATED_SUFFIX);
+        if use_byte_for_bools {
+            self.add_u8(&marker_name, 1, OutType::Boolean, 0);
+        } else {
+            self.add_bool32(&marker_name, 1, OutType::Boolean, 0);
         }
     }
 
@@ -149,29 +420,47 @@ impl EtwEventBuilderWrapper {
         span_timestamp: &SystemTime,
         links: &mut dyn Iterator<Item = &Link>,
         use_byte_for_bools: bool,
+        arrays_as_json: bool,
     ) -> ExportResult {
         for link in links {
             self.reset(event_name, level, keywords, EVENT_TAG_IGNORE_EVENT_TIME);
             self.opcode(Opcode::Info);
 
+            let field_tags = FIELD_TAG_IS_REAL_EVENT_TIME | self.exporter_config.get_field_tags();
             self.add_filetime(
                 "otel_event_time",
                 win_filetime_from_systemtime!(span_timestamp),
                 OutType::DateTimeUtc,
-                FIELD_TAG_IS_REAL_EVENT_TIME,
+                field_tags,
             );
-            self.add_win32_systemtime("time", &(*span_timestamp).into(), 0);
+            if !self.exporter_config.get_compact_event_timestamps() {
+                self.add_win32_systemtime("time", &(*span_timestamp).into(), 0);
+            }
 
-            self.add_str8(
+            self.add_string(
                 "Link",
                 std::fmt::format(format_args!("{:16x}", link.span_context.span_id())),
-                OutType::Utf8,
                 0,
             );
+            self.add_string(
+                "LinkTraceId",
+                std::fmt::format(format_args!("{:32x}", link.span_context.trace_id())),
+                0,
+            );
+
+            if self.exporter_config.get_link_attr_count() {
+                self.add_u16(
+                    "LinkAttrCount",
+                    attributes::get_link_attribute_count(link),
+                    OutType::Unsigned,
+                    0,
+                );
+            }
 
             self.add_attributes_to_event(
                 &mut link.attributes.iter().map(|kv| (&kv.key, &kv.value)),
                 use_byte_for_bools,
+                arrays_as_json,
             );
 
             let win32err = self.write(
@@ -195,51 +484,93 @@ impl EtwEventBuilderWrapper {
     fn write_span_events(
         &mut self,
         tlg_provider: &Pin<&Provider>,
-        level: Level,
-        keywords: u64,
-        activities: &Activities,
+        fields: &SpanEventsFields,
         events: &mut dyn Iterator<Item = &Event>,
-        use_byte_for_bools: bool,
-        export_payload_as_json: bool,
     ) -> ExportResult {
+        let SpanEventsFields {
+            level,
+            keywords,
+            activities,
+            span_start_time,
+            component,
+            use_byte_for_bools,
+            arrays_as_json,
+            export_payload_as_json,
+            per_event_keywords,
+        } = *fields;
+        let mut limit = events::EventLimit::new(self.exporter_config.get_max_events_emitted());
+
         for event in events {
+            let keywords = if per_event_keywords {
+                let keywords = events::get_per_event_keywords(event, keywords);
+                if !tlg_provider.enabled(level, keywords) {
+                    continue;
+                }
+                keywords
+            } else {
+                keywords
+            };
+
+            if !limit.try_take() {
+                continue;
+            }
+
             self.reset(&event.name, level, keywords, EVENT_TAG_IGNORE_EVENT_TIME);
             self.opcode(Opcode::Info);
 
+            let field_tags = FIELD_TAG_IS_REAL_EVENT_TIME | self.exporter_config.get_field_tags();
             self.add_filetime(
                 "otel_event_time",
                 win_filetime_from_systemtime!(event.timestamp),
                 OutType::DateTimeUtc,
-                FIELD_TAG_IS_REAL_EVENT_TIME,
+                field_tags,
             );
-            self.add_win32_systemtime("time", &event.timestamp.into(), 0);
+            if !self.exporter_config.get_compact_event_timestamps() {
+                self.add_win32_systemtime("time", &event.timestamp.into(), 0);
+            }
+
+            // The event's activity id already identifies the span it belongs to
+            if !self.exporter_config.get_activity_only_correlation() {
+                self.add_string("SpanId", activities.span_id, 0);
 
-            self.add_str8("SpanId", &activities.span_id, OutType::Utf8, 0);
+                if !activities.parent_span_id.is_empty() {
+                    self.add_string("ParentId", activities.parent_span_id, 0);
+                }
 
-            if !activities.parent_span_id.is_empty() {
-                self.add_str8("ParentId", &activities.parent_span_id, OutType::Utf8, 0);
+                self.add_string("TraceId", activities.trace_id_name, 0);
             }
 
-            self.add_str8("TraceId", &activities.trace_id_name, OutType::Utf8, 0);
+            if self.exporter_config.get_event_offsets() {
+                self.add_u64(
+                    "OffsetNs",
+                    durations::get_offset_ns(span_start_time, &event.timestamp),
+                    OutType::Unsigned,
+                    0,
+                );
+            }
 
-            let mut added = false;
+            if let Some(component) = component {
+                self.add_string("Component", component, 0);
+            }
 
-            #[cfg(feature = "json")]
-            if export_payload_as_json {
-                let json_string = json::get_attributes_as_json(
-                    &mut event.attributes.iter().map(|kv| (&kv.key, &kv.value)),
-                );
-                self.add_str8("Payload", &json_string, OutType::Json, 0);
-                added = true;
+            if let Some(service_instance_id) = self.exporter_config.get_service_instance_id() {
+                self.add_string("ServiceInstanceId", &**service_instance_id, 0);
             }
 
-            if !added {
-                self.add_attributes_to_event(
-                    &mut event.attributes.iter().map(|kv| (&kv.key, &kv.value)),
-                    use_byte_for_bools,
+            if self.exporter_config.get_grouped_event_attributes() {
+                self.add_attributes_struct(
+                    &mut events::get_event_attributes(event, per_event_keywords),
+                    export_payload_as_json || self.exporter_config.get_export_as_msgpack(),
                 );
             }
 
+            self.add_payload(
+                &mut events::get_event_attributes(event, per_event_keywords),
+                use_byte_for_bools,
+                arrays_as_json,
+                export_payload_as_json,
+            );
+
             let win32err = self.write(
                 tlg_provider,
                 Some(Guid::from_bytes_be(&activities.activity_id)).as_ref(),
@@ -255,33 +586,150 @@ impl EtwEventBuilderWrapper {
             }
         }
 
+        if limit.omitted() > 0 {
+            self.reset("EventsTruncated", level, keywords, 0);
+            self.opcode(Opcode::Info);
+
+            self.add_string("SpanId", activities.span_id, 0);
+            self.add_string("TraceId", activities.trace_id_name, 0);
+            self.add_u64("OmittedCount", limit.omitted(), OutType::Default, 0);
+
+            let win32err = self.write(
+                tlg_provider,
+                Some(Guid::from_bytes_be(&activities.activity_id)).as_ref(),
+                activities
+                    .parent_activity_id
+                    .as_ref()
+                    .map(Guid::from_bytes_be)
+                    .as_ref(),
+            );
+
+            if win32err != 0 {
+                return Err(TraceError::ExportFailed(Box::new(Win32Error { win32err })));
+            }
+        }
+
         Ok(())
     }
 
-    #[allow(clippy::too_many_arguments)]
     fn write_span_event(
         &mut self,
         tlg_provider: &Pin<&tracelogging_dynamic::Provider>,
-        name: &str,
-        level: Level,
-        keywords: u64,
-        activities: &Activities,
-        event_time: &SystemTime,
-        span_kind: Option<&SpanKind>,
-        status: &Status,
+        fields: &SpanEventFields,
+        attributes: &mut dyn Iterator<Item = (&Key, &Value)>,
+    ) -> ExportResult {
+        match fields.event_split {
+            Some(max_size) if !fields.is_start => {
+                let parts = split::split_attributes(attributes, max_size);
+                let part_count = parts.len() as u32;
+
+                for (index, part) in parts.into_iter().enumerate() {
+                    // The span's links, events and counts are only logged with the first part
+                    let part_fields = if index == 0 {
+                        *fields
+                    } else {
+                        SpanEventFields {
+                            inline_links: None,
+                            inline_events: None,
+                            span_events: None,
+                            dropped_counts: None,
+                            ..*fields
+                        }
+                    };
+                    self.write_span_event_part(
+                        tlg_provider,
+                        &part_fields,
+                        &mut part.into_iter(),
+                        if part_count > 1 {
+                            Some((index as u32 + 1, part_count))
+                        } else {
+                            None
+                        },
+                    )?;
+                }
+
+                Ok(())
+            }
+            _ => self.write_span_event_part(tlg_provider, fields, attributes, None),
+        }
+    }
+
+    fn add_payload(
+        &mut self,
         attributes: &mut dyn Iterator<Item = (&Key, &Value)>,
-        is_start: bool,
-        add_tags: bool,
         use_byte_for_bools: bool,
+        arrays_as_json: bool,
         export_payload_as_json: bool,
+    ) {
+        let mut added = false;
+
+        #[cfg(feature = "json")]
+        if export_payload_as_json {
+            let json_string =
+                self.with_payload_attributes(attributes, json::get_attributes_as_json);
+            self.add_str8("Payload", &json_string, OutType::Json, 0);
+            added = true;
+        }
+
+        #[cfg(feature = "msgpack")]
+        if !added && self.exporter_config.get_export_as_msgpack() {
+            let blob = self.with_payload_attributes(attributes, msgpack::get_attributes_as_msgpack);
+            self.add_binary("Payload", &blob, OutType::Default, 0);
+            added = true;
+        }
+
+        if !added {
+            self.add_attributes_to_event(attributes, use_byte_for_bools, arrays_as_json);
+        }
+    }
+
+    fn write_span_event_part(
+        &mut self,
+        tlg_provider: &Pin<&tracelogging_dynamic::Provider>,
+        fields: &SpanEventFields,
+        attributes: &mut dyn Iterator<Item = (&Key, &Value)>,
+        part: Option<(u32, u32)>,
     ) -> ExportResult {
+        let SpanEventFields {
+            name,
+            level,
+            keywords,
+            activities,
+            traceparent,
+            remote_parent,
+            inline_links,
+            inline_events,
+            thread_name,
+            active_children,
+            component,
+            attribute_count,
+            filetime_duration,
+            event_time,
+            start_time,
+            span_kind,
+            status,
+            status_message,
+            span_events,
+            dropped_counts,
+            event_split: _,
+            is_start,
+            add_tags,
+            use_byte_for_bools,
+            arrays_as_json,
+            export_payload_as_json,
+        } = *fields;
         let (event_tags, field_tags) = if add_tags {
-            (EVENT_TAG_IGNORE_EVENT_TIME, FIELD_TAG_IS_REAL_EVENT_TIME)
+            (
+                EVENT_TAG_IGNORE_EVENT_TIME,
+                FIELD_TAG_IS_REAL_EVENT_TIME | self.exporter_config.get_field_tags(),
+            )
         } else {
-            (0, 0)
+            (0, self.exporter_config.get_field_tags())
         };
         let (opcode, time_field_name) = if is_start {
             (Opcode::Start, "StartTime")
+        } else if start_time.is_some() {
+            (Opcode::Info, "EndTime")
         } else {
             (Opcode::Stop, "EndTime")
         };
@@ -295,7 +743,46 @@ impl EtwEventBuilderWrapper {
             OutType::DateTimeUtc,
             field_tags,
         );
+
+        // A single span event has both the start and end time of the span
+        if let Some(start_time) = start_time {
+            self.add_win32_systemtime("StartTime", &(*start_time).into(), 0);
+        }
         self.add_win32_systemtime(time_field_name, &(*event_time).into(), 0);
+        let duration = start_time.map(|start_time| SpanDuration::new(start_time, event_time));
+        if let Some(duration) = duration {
+            self.add_u64("DurationMs", duration.as_millis(), OutType::Unsigned, 0);
+        }
+
+        if let Some(filetime_duration) = filetime_duration {
+            self.add_u64(
+                "Duration100ns",
+                filetime_duration.as_100ns(),
+                OutType::Unsigned,
+                0,
+            );
+        }
+
+        if duration
+            .or(filetime_duration)
+            .map_or(false, |d| d.clock_skew)
+        {
+            if use_byte_for_bools {
+                self.add_u8("ClockSkew", 1, OutType::Boolean, 0);
+            } else {
+                self.add_bool32("ClockSkew", 1, OutType::Boolean, 0);
+            }
+        }
+
+        // Some consumers expect the attributes at the start of the payload
+        if self.exporter_config.get_attributes_first() {
+            self.add_payload(
+                attributes,
+                use_byte_for_bools,
+                arrays_as_json,
+                export_payload_as_json,
+            );
+        }
 
         if let Some(sk) = span_kind {
             self.add_string(
@@ -311,29 +798,106 @@ impl EtwEventBuilderWrapper {
             );
         }
 
-        if let Status::Error { description } = &status {
-            self.add_string("StatusMessage", description.to_string(), 0);
-        };
+        if is_start && self.exporter_config.get_include_status_on_start() {
+            if let Some(status_name) = status::get_status_name(status) {
+                self.add_string("Status", status_name, 0);
+            }
+        }
+
+        if let Some(status_message) = status_message {
+            self.add_string("StatusMessage", status_message, 0);
+        }
 
-        self.add_str8("SpanId", &activities.span_id, OutType::Utf8, 0);
+        self.add_string("SpanId", activities.span_id, 0);
 
         if !activities.parent_span_id.is_empty() {
-            self.add_str8("ParentId", &activities.parent_span_id, OutType::Utf8, 0);
+            self.add_string("ParentId", activities.parent_span_id, 0);
         }
 
-        self.add_str8("TraceId", &activities.trace_id_name, OutType::Utf8, 0);
+        self.add_string("TraceId", activities.trace_id_name, 0);
 
-        let mut added = false;
+        if let Some(traceparent) = traceparent {
+            self.add_str8("traceparent", traceparent, OutType::Utf8, 0);
+        }
 
-        #[cfg(feature = "json")]
-        if export_payload_as_json {
-            let json_string = json::get_attributes_as_json(attributes);
-            self.add_str8("Payload", &json_string, OutType::Json, 0);
-            added = true;
+        if let Some(remote_parent) = remote_parent {
+            if use_byte_for_bools {
+                self.add_u8("IsRemoteParent", remote_parent as u8, OutType::Boolean, 0);
+            } else {
+                self.add_bool32("IsRemoteParent", remote_parent as i32, OutType::Boolean, 0);
+            }
         }
 
-        if !added {
-            self.add_attributes_to_event(attributes, use_byte_for_bools);
+        if let Some(inline_links) = inline_links {
+            self.add_str8("Links", inline_links, OutType::Json, 0);
+        }
+
+        if let Some(inline_events) = inline_events {
+            self.add_str8("Events", inline_events, OutType::Json, 0);
+        }
+
+        if let Some(thread_name) = thread_name {
+            self.add_string("ThreadName", thread_name, 0);
+        }
+
+        if let Some(active_children) = active_children {
+            self.add_u32("ActiveChildren", active_children, OutType::Unsigned, 0);
+        }
+
+        if let Some(component) = component {
+            self.add_string("Component", component, 0);
+        }
+
+        if let Some(service_instance_id) = self.exporter_config.get_service_instance_id() {
+            self.add_string("ServiceInstanceId", &**service_instance_id, 0);
+        }
+
+        if let Some(attribute_count) = attribute_count {
+            self.add_u16("AttrCount", attribute_count, OutType::Unsigned, 0);
+        }
+
+        if let Some((part, part_count)) = part {
+            self.add_u32("Part", part, OutType::Unsigned, 0);
+            self.add_u32("PartCount", part_count, OutType::Unsigned, 0);
+        }
+
+        if let Some(span_events) = span_events {
+            self.add_u32(
+                "EventCount",
+                events::get_event_count(span_events),
+                OutType::Unsigned,
+                0,
+            );
+            self.add_str8(
+                "EventNames",
+                events::get_event_names(span_events, export_payload_as_json),
+                if export_payload_as_json {
+                    OutType::Json
+                } else {
+                    OutType::Utf8
+                },
+                0,
+            );
+        }
+
+        if let Some(dropped_counts) = dropped_counts {
+            self.add_u32(
+                "DroppedAttributes",
+                dropped_counts.attributes,
+                OutType::Unsigned,
+                0,
+            );
+            self.add_u32("DroppedEvents", dropped_counts.events, OutType::Unsigned, 0);
+            self.add_u32("DroppedLinks", dropped_counts.links, OutType::Unsigned, 0);
+        }
+
+        if !self.exporter_config.get_attributes_first() {
+            self.add_payload(
+                attributes,
+                use_byte_for_bools,
+                arrays_as_json,
+                export_payload_as_json,
+            );
         }
 
         let win32err = self.write(
@@ -353,7 +917,7 @@ impl EtwEventBuilderWrapper {
         Ok(())
     }
 
-    fn write_common_schema_span<'a, C>(
+    fn write_common_schema_span<'b, A>(
         &mut self,
         tlg_provider: &Pin<&Provider>,
         name: &str,
@@ -362,10 +926,13 @@ impl EtwEventBuilderWrapper {
         span_data: &SpanData,
         span_context: &SpanContext,
         export_payload_as_json: bool,
-        _attributes: C,
+        include_counts: bool,
+        use_byte_for_bools: bool,
+        arrays_as_json: bool,
+        _attributes: A,
     ) -> ExportResult
     where
-        C: IntoIterator<Item = (&'a Key, &'a Value)>,
+        A: IntoIterator<Item = (&'b Key, &'b Value)>,
     {
         // Avoid allocations for these fixed-length strings
 
@@ -392,17 +959,53 @@ impl EtwEventBuilderWrapper {
         // let exts = json::extract_common_schema_parta_exts(attributes);
 
         self.add_u16("__csver__", 0x0401, OutType::Signed, 0);
-        self.add_struct("PartA", 2 /* + exts.len() as u8*/, 0);
+        let parta_field_count = common_schema::get_parta_field_count(
+            self.exporter_config.get_app_extension(),
+            self.exporter_config.get_sdk_extension(),
+        );
+
+        self.add_struct("PartA", parta_field_count /* + exts.len() as u8*/, 0);
         {
             let time: String = chrono::DateTime::to_rfc3339(
                 &chrono::DateTime::<chrono::Utc>::from(span_data.end_time),
             );
             self.add_str8("time", time, OutType::Utf8, 0);
 
-            self.add_struct("ext_dt", 2, 0);
+            let trace_flags = self.exporter_config.get_common_schema_trace_flags();
+            self.add_struct(
+                "ext_dt",
+                common_schema::get_ext_dt_field_count(trace_flags),
+                0,
+            );
             {
                 self.add_str8("traceId", &trace_id, OutType::Utf8, 0);
                 self.add_str8("spanId", &span_id, OutType::Utf8, 0);
+                if trace_flags {
+                    self.add_str8(
+                        "traceFlags",
+                        common_schema::get_trace_flags(span_context),
+                        OutType::Utf8,
+                        0,
+                    );
+                }
+            }
+
+            if let Some(app_extension) = self.exporter_config.get_app_extension() {
+                self.add_struct("ext_app", 2, 0);
+                {
+                    self.add_str8("id", app_extension.id.as_ref(), OutType::Utf8, 0);
+                    self.add_str8("ver", app_extension.ver.as_ref(), OutType::Utf8, 0);
+                }
+            }
+
+            if self.exporter_config.get_sdk_extension() {
+                let (lib_name, lib_ver) =
+                    common_schema::get_sdk_extension(&span_data.instrumentation_lib);
+                self.add_struct("ext_sdk", 2, 0);
+                {
+                    self.add_str8("libName", lib_name, OutType::Utf8, 0);
+                    self.add_str8("libVer", lib_ver, OutType::Utf8, 0);
+                }
             }
 
             // for ext in exts {
@@ -426,23 +1029,25 @@ impl EtwEventBuilderWrapper {
         // }
 
         let mut status_message: Cow<str> = Cow::default();
-        let mut partb_field_count = 5u8;
-        if span_data.parent_span_id != SpanId::INVALID {
-            partb_field_count += 1;
-        }
         if let Status::Error { description } = &span_data.status {
-            partb_field_count += 1;
             status_message = Cow::Borrowed(description);
         }
-        // TODO: azureResourceProvider: string
-        if !span_data.links.is_empty() {
-            partb_field_count += 1; // Type is an "array", but really it's just a string with a JSON array
+        let compact = self.exporter_config.get_compact_common_schema();
+        let mut promoted_fields = if self.exporter_config.get_semantic_convention_promotion() {
+            common_schema::get_promoted_fields(span_data)
+        } else {
+            Vec::new()
+        };
+        if compact {
+            promoted_fields.retain(|field| !common_schema::is_empty_value(field.value));
         }
+        let partb_fields = common_schema::PartBFields::new(span_data, include_counts, compact);
+        let partb_field_count = partb_fields.field_count() + promoted_fields.len() as u8;
 
         self.add_struct("PartB", partb_field_count, 0);
         {
             self.add_str8("_typeName", "Span", OutType::Utf8, 0);
-            if span_data.parent_span_id != SpanId::INVALID {
+            if partb_fields.parent_id {
                 self.add_str8(
                     "parentId",
                     &span_data.parent_span_id.to_string(),
@@ -451,19 +1056,21 @@ impl EtwEventBuilderWrapper {
                 );
             }
             self.add_str8("name", name, OutType::Utf8, 0);
-            self.add_u8(
-                "kind",
-                match span_data.span_kind {
-                    SpanKind::Internal => 0u8,
-                    SpanKind::Server => 1,
-                    SpanKind::Client => 2,
-                    SpanKind::Producer => 3,
-                    SpanKind::Consumer => 4,
-                },
-                OutType::Unsigned,
-                0,
-            );
-            self.add_str8(
+            if partb_fields.kind {
+                self.add_u8(
+                    "kind",
+                    match span_data.span_kind {
+                        SpanKind::Internal => 0u8,
+                        SpanKind::Server => 1,
+                        SpanKind::Client => 2,
+                        SpanKind::Producer => 3,
+                        SpanKind::Consumer => 4,
+                    },
+                    OutType::Unsigned,
+                    0,
+                );
+            }
+            self.add_str8(
                 "startTime",
                 &chrono::DateTime::to_rfc3339(&chrono::DateTime::<chrono::Utc>::from(
                     span_data.end_time,
@@ -480,11 +1087,11 @@ impl EtwEventBuilderWrapper {
                 OutType::Boolean,
                 0,
             );
-            if !status_message.is_empty() {
+            if partb_fields.status_message {
                 self.add_str8("statusMessage", status_message.as_ref(), OutType::Utf8, 0);
             }
             // TODO: azureResourceProvider: string
-            if !span_data.links.is_empty() {
+            if partb_fields.links {
                 let mut links = String::with_capacity(2 + (78 * span_data.links.len()));
                 links += "[";
                 for link in span_data.links.iter() {
@@ -498,28 +1105,89 @@ impl EtwEventBuilderWrapper {
 
                 self.add_str8("links", &links, OutType::Json, 0);
             }
-            // TODO: promote HTTP, Database and Messaging fields
+            let (link_count, event_count) = common_schema::get_partb_counts(span_data);
+            if partb_fields.link_count {
+                self.add_u32("linkCount", link_count, OutType::Unsigned, 0);
+            }
+            if partb_fields.event_count {
+                self.add_u32("eventCount", event_count, OutType::Unsigned, 0);
+            }
+            for field in promoted_fields.iter() {
+                self.add_str8(field.name, field.value.as_str().as_ref(), OutType::Utf8, 0);
+            }
+            // TODO: promote HTTP and Messaging fields
         }
 
-        let partc_field_count = if export_payload_as_json {
-            1u8
-        } else {
-            span_data.attributes.len() as u8
+        let attribute_filter = self.exporter_config.get_attribute_filter();
+        let float_sanitization = self.exporter_config.get_float_sanitization();
+        // Only the attributes that are written as fields are counted
+        let is_partc_attribute = |key: &Key, value: &Value| {
+            let is_skipped = common_schema::is_promoted(&promoted_fields, key)
+                || (float_sanitization && floats::is_non_finite_f64(value))
+                || (compact && common_schema::is_empty_value(value));
+            !is_skipped && attribute_filter.map_or(true, |filter| filter.is_allowed(key.as_str()))
         };
+        let mut partc_attributes = span_data
+            .attributes
+            .iter()
+            .filter(|(key, value)| is_partc_attribute(key, value));
+
+        let max_array_length = self.exporter_config.get_max_array_length();
+        let partc_fields = common_schema::PartCFields::with_field_counts(
+            span_data
+                .attributes
+                .iter()
+                .filter(|(key, value)| is_partc_attribute(key, value))
+                .map(|(_, value)| arrays::get_field_count(value, max_array_length)),
+        );
+        let partc_field_count =
+            if export_payload_as_json || self.exporter_config.get_export_as_msgpack() {
+                1u8
+            } else {
+                partc_fields.field_count()
+            };
 
-        self.add_struct("PartC", partc_field_count, 0);
-        {
-            let mut added = false;
+        // Compact events leave out a PartC struct with no fields
+        if !(compact && partc_field_count == 0) {
+            self.add_struct("PartC", partc_field_count, 0);
+            {
+                let mut added = false;
+
+                #[cfg(feature = "json")]
+                if export_payload_as_json {
+                    let json_string = self.with_payload_attributes(
+                        &mut partc_attributes,
+                        json::get_attributes_as_json,
+                    );
+                    self.add_str8("Payload", &json_string, OutType::Json, 0);
+                    added = true;
+                }
 
-            #[cfg(feature = "json")]
-            if export_payload_as_json {
-                let json_string = json::get_attributes_as_json(&mut span_data.attributes.iter());
-                self.add_str8("Payload", &json_string, OutType::Json, 0);
-                added = true;
-            }
+                #[cfg(feature = "msgpack")]
+                if !added && self.exporter_config.get_export_as_msgpack() {
+                    let blob = self.with_payload_attributes(
+                        &mut partc_attributes,
+                        msgpack::get_attributes_as_msgpack,
+                    );
+                    self.add_binary("Payload", &blob, OutType::Default, 0);
+                    added = true;
+                }
 
-            if !added {
-                self.add_attributes_to_event(&mut span_data.attributes.iter(), true);
+                if !added {
+                    self.add_attributes_to_event(
+                        &mut partc_attributes.by_ref().take(partc_fields.attributes),
+                        use_byte_for_bools,
+                        arrays_as_json,
+                    );
+                    if partc_fields.truncated > 0 {
+                        self.add_u32(
+                            "truncatedAttributes",
+                            partc_fields.truncated,
+                            OutType::Unsigned,
+                            0,
+                        );
+                    }
+                }
             }
         }
 
@@ -533,37 +1201,226 @@ impl EtwEventBuilderWrapper {
     }
 }
 
-impl std::ops::Deref for EtwEventBuilderWrapper {
+impl<C: KeywordLevelProvider> std::ops::Deref for EtwEventBuilderWrapper<'_, C> {
     type Target = EventBuilder;
     fn deref(&self) -> &Self::Target {
-        &self.eb
+        self.eb
     }
 }
 
-impl std::ops::DerefMut for EtwEventBuilderWrapper {
+impl<C: KeywordLevelProvider> std::ops::DerefMut for EtwEventBuilderWrapper<'_, C> {
     fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.eb
+        self.eb
+    }
+}
+
+// The state shared with the provider's enable callback, which only gets a usize context.
+// It is boxed by the exporter so that its address stays valid while the provider is registered.
+pub(crate) struct ProviderLifecycle {
+    // Set once the provider has been pinned, before it is registered.
+    provider: AtomicPtr<Provider>,
+    span_level: Level,
+    span_keywords: u64,
+    event_tap: Option<EventTap>,
+    enabled: AtomicBool,
+}
+
+impl ProviderLifecycle {
+    fn new<C: KeywordLevelProvider>(exporter_config: &ExporterConfig<C>) -> Self {
+        ProviderLifecycle {
+            provider: AtomicPtr::new(std::ptr::null_mut()),
+            span_level: exporter_config.get_span_level().into(),
+            span_keywords: exporter_config.get_span_keywords(),
+            event_tap: exporter_config.get_event_tap().cloned(),
+            enabled: AtomicBool::new(false),
+        }
+    }
+
+    // Logs the ProviderStopping event. The provider is disabled by now, so the event is
+    // written without checking whether it is enabled.
+    fn log_provider_stopping(&self) {
+        let provider = self.provider.load(Ordering::Acquire);
+        if provider.is_null() {
+            return;
+        }
+        let provider = unsafe { &*provider };
+
+        let mut eb = EventBuilder::new();
+        eb.reset("ProviderStopping", self.span_level, self.span_keywords, 0);
+        eb.opcode(Opcode::Info);
+
+        eb.add_str8("ProviderName", provider.name(), OutType::Utf8, 0);
+        eb.add_u32("Pid", std::process::id(), OutType::Default, 0);
+
+        if eb.write(provider, None, None) == 0 {
+            if let Some(event_tap) = &self.event_tap {
+                event_tap(&EventMeta {
+                    name: "ProviderStopping",
+                    level: self.span_level.as_int(),
+                    keyword: self.span_keywords,
+                });
+            }
+        }
+    }
+}
+
+// ETW calls the enable callback with the disable control code when the last session
+// disables the provider. Updates from sessions that remain use the enable control code.
+fn lifecycle_enable_callback(
+    _source_id: &Guid,
+    event_control_code: u32,
+    _level: Level,
+    _match_any_keyword: u64,
+    _match_all_keyword: u64,
+    _filter_data: usize,
+    callback_context: usize,
+) {
+    let lifecycle = unsafe { &*(callback_context as *const ProviderLifecycle) };
+    match event_control_code {
+        0 if lifecycle.enabled.swap(false, Ordering::AcqRel) => lifecycle.log_provider_stopping(),
+        1 => lifecycle.enabled.store(true, Ordering::Release),
+        _ => {}
     }
 }
 
+// Creates and registers the provider, with an enable callback that logs ProviderStopping
+// events if lifecycle events are enabled.
+#[allow(dead_code)]
+pub(crate) fn register_provider<C: KeywordLevelProvider>(
+    provider_name: &str,
+    provider_id: &Guid,
+    options: &mut ProviderOptions,
+    exporter_config: &ExporterConfig<C>,
+) -> (Pin<Arc<Provider>>, Option<Box<ProviderLifecycle>>) {
+    let lifecycle = exporter_config
+        .get_lifecycle_events()
+        .then(|| Box::new(ProviderLifecycle::new(exporter_config)));
+    if let Some(lifecycle) = &lifecycle {
+        options.callback(
+            lifecycle_enable_callback,
+            lifecycle.as_ref() as *const ProviderLifecycle as usize,
+        );
+    }
+
+    let provider = Arc::pin(Provider::new_with_id(provider_name, options, provider_id));
+    if let Some(lifecycle) = &lifecycle {
+        lifecycle.provider.store(
+            provider.as_ref().get_ref() as *const Provider as *mut Provider,
+            Ordering::Release,
+        );
+    }
+    unsafe {
+        provider.as_ref().register();
+    }
+    (provider, lifecycle)
+}
+
 pub(crate) struct EtwEventExporter<C: KeywordLevelProvider> {
     provider: Pin<Arc<Provider>>,
+    // Borrowed by the provider's enable callback until the provider is unregistered on drop.
+    lifecycle: Option<Box<ProviderLifecycle>>,
     exporter_config: ExporterConfig<C>,
     bool_representation: InType,
+    self_description_logged: AtomicBool,
 }
 
 impl<C: KeywordLevelProvider> EtwEventExporter<C> {
     #[allow(dead_code)]
     pub(crate) fn new(
         provider: Pin<Arc<Provider>>,
+        lifecycle: Option<Box<ProviderLifecycle>>,
         exporter_config: ExporterConfig<C>,
         bool_representation: InType,
     ) -> Self {
         // Unfortunately we can't safely share a cached EventBuilder without adding undesirable locking
         EtwEventExporter {
             provider,
+            lifecycle,
             exporter_config,
             bool_representation,
+            self_description_logged: AtomicBool::new(false),
+        }
+    }
+
+    // Logs the ProviderSchema event the first time a span is exported while a session
+    // has the span keywords enabled.
+    fn log_self_description(&self) {
+        let span_keywords = self.exporter_config.get_span_keywords();
+        let span_level = self.exporter_config.get_span_level().into();
+
+        if !self.exporter_config.get_self_description()
+            || self.self_description_logged.load(Ordering::Relaxed)
+            || !self.provider.enabled(span_level, span_keywords)
+            || self.self_description_logged.swap(true, Ordering::Relaxed)
+        {
+            return;
+        }
+
+        EBW.with(|eb| {
+            let mut eb = eb.borrow_mut();
+            let mut ebw = EtwEventBuilderWrapper::new(&mut eb, &self.exporter_config);
+
+            ebw.reset("ProviderSchema", span_level, span_keywords, 0);
+            ebw.opcode(Opcode::Info);
+
+            ebw.add_string("ProviderName", self.provider.name(), 0);
+            ebw.add_string("Version", env!("CARGO_PKG_VERSION"), 0);
+            ebw.add_str8(
+                "Fields",
+                schema::get_provider_schema_json(),
+                OutType::Json,
+                0,
+            );
+
+            let _ = ebw.write(&self.provider, None, None);
+        });
+    }
+
+    // Logs a marker event with a single count field, using the span level and keywords.
+    fn log_count_event(&self, name: &str, field_name: &str, count: u64) -> ExportResult {
+        let span_keywords = self.exporter_config.get_span_keywords();
+        let span_level = self.exporter_config.get_span_level().into();
+
+        if !self.provider.enabled(span_level, span_keywords) {
+            return Ok(());
+        }
+
+        EBW.with(|eb| {
+            let mut eb = eb.borrow_mut();
+            let mut ebw = EtwEventBuilderWrapper::new(&mut eb, &self.exporter_config);
+
+            ebw.reset(name, span_level, span_keywords, 0);
+            ebw.opcode(Opcode::Info);
+
+            ebw.add_u64(field_name, count, OutType::Default, 0);
+
+            let win32err = ebw.write(&self.provider, None, None);
+
+            if win32err != 0 {
+                Err(TraceError::ExportFailed(Box::new(Win32Error { win32err })))
+            } else {
+                Ok(())
+            }
+        })
+    }
+
+    // Counts a span that no session enabled, or logs the spans counted before a session
+    // enabled the provider again.
+    fn track_suppressed_span(&self, enabled: bool) {
+        if !enabled {
+            self.exporter_config.add_suppressed_span();
+        } else if let Some(count) = self.exporter_config.take_suppressed_spans() {
+            let _ = self.log_count_event("SuppressedSpans", "SuppressedCount", count);
+        }
+    }
+}
+
+impl<C: KeywordLevelProvider> Drop for EtwEventExporter<C> {
+    fn drop(&mut self) {
+        // Unregistering waits for any running enable callback, so the lifecycle state
+        // can be freed afterwards.
+        if self.lifecycle.is_some() {
+            self.provider.unregister();
         }
     }
 }
@@ -573,18 +1430,76 @@ impl<C: KeywordLevelProvider> EventExporter for EtwEventExporter<C> {
         self.provider.enabled(level.into(), keyword)
     }
 
+    fn spans_enabled(&self) -> bool {
+        self.enabled(
+            self.exporter_config.get_span_level(),
+            self.exporter_config.get_span_keywords(),
+        )
+    }
+
+    fn log_provider_started(&self) -> ExportResult {
+        let span_keywords = self.exporter_config.get_span_keywords();
+        let span_level = self.exporter_config.get_span_level().into();
+
+        if !self.exporter_config.get_export_startup_event()
+            || !self.provider.enabled(span_level, span_keywords)
+        {
+            return Ok(());
+        }
+
+        EBW.with(|eb| {
+            let mut eb = eb.borrow_mut();
+            let mut ebw = EtwEventBuilderWrapper::new(&mut eb, &self.exporter_config);
+
+            ebw.reset("ProviderStarted", span_level, span_keywords, 0);
+            ebw.opcode(Opcode::Info);
+
+            ebw.add_string("ProviderName", self.provider.name(), 0);
+            ebw.add_string("Version", env!("CARGO_PKG_VERSION"), 0);
+            ebw.add_u32("Pid", std::process::id(), OutType::Default, 0);
+
+            let win32err = ebw.write(&self.provider, None, None);
+
+            if win32err != 0 {
+                Err(TraceError::ExportFailed(Box::new(Win32Error { win32err })))
+            } else {
+                Ok(())
+            }
+        })
+    }
+
+    fn log_spans_dropped(&self, count: u64) -> ExportResult {
+        self.log_count_event("SpansDropped", "DroppedCount", count)
+    }
+
     // Called by the real-time exporter when a span is started
     fn log_span_start<S>(&self, span: &S) -> ExportResult
     where
         S: opentelemetry_api::trace::Span + EtwSpan,
     {
+        if !self.exporter_config.should_export(span.get_span_data()) {
+            return Ok(());
+        }
+
+        self.log_self_description();
+
         if !self.exporter_config.get_export_span_events() {
             // Common schema events are logged at span end
             return Ok(());
         }
 
-        let span_keywords = self.exporter_config.get_span_keywords();
-        let span_level = self.exporter_config.get_span_level().into();
+        let span_keywords = span
+            .get_context_keyword()
+            .map_or(self.exporter_config.get_span_keywords(), |keywords| {
+                self.exporter_config.get_span_keywords() | keywords
+            })
+            | self
+                .exporter_config
+                .get_entry_span_keyword(span.get_span_data(), span.is_remote_parent());
+        let span_level = self
+            .exporter_config
+            .get_span_level_for_kind(&span.get_span_data().span_kind)
+            .into();
 
         if !self.provider.enabled(span_level, span_keywords) {
             return Ok(());
@@ -596,6 +1511,7 @@ impl<C: KeywordLevelProvider> EventExporter for EtwEventExporter<C> {
             _ => panic!("unsupported bool representation"),
         };
         let export_payload_as_json = self.exporter_config.get_export_as_json();
+        let arrays_as_json = self.exporter_config.get_export_arrays_as_json();
 
         let span_context = opentelemetry_api::trace::Span::span_context(span);
 
@@ -605,31 +1521,85 @@ impl<C: KeywordLevelProvider> EventExporter for EtwEventExporter<C> {
             &span_context.span_id(),
             &span_data.parent_span_id,
             &span_context.trace_id(),
-        );
-
-        EBW.with(|ebw| {
-            let mut ebw = ebw.borrow_mut();
+            self.exporter_config.get_trace_scoped_activities(),
+        )
+        .with_activity_id(span.get_root_activity_id());
+        let status_message = self
+            .exporter_config
+            .get_include_status_on_start()
+            .then(|| {
+                status::get_status_message(
+                    &span_data.status,
+                    &span_data.attributes,
+                    self.exporter_config.get_preserve_ok_description(),
+                )
+            })
+            .flatten();
+        let traceparent = self
+            .exporter_config
+            .get_traceparent_field()
+            .then(|| get_traceparent(span_context));
+        let remote_parent = self
+            .exporter_config
+            .get_remote_flag()
+            .then(|| span.is_remote_parent());
+        let thread_name = self
+            .exporter_config
+            .get_thread_name_field()
+            .then(threads::get_current_thread_name);
+        let component = self
+            .exporter_config
+            .get_component_field()
+            .then_some(span_data.instrumentation_lib.name.as_ref());
+
+        EBW.with(|eb| {
+            let mut eb = eb.borrow_mut();
+            let mut ebw = EtwEventBuilderWrapper::new(&mut eb, &self.exporter_config);
 
             ebw.write_span_event(
                 &self.provider.as_ref(),
-                &span_data.name,
-                span_level,
-                span_keywords,
-                &activities,
-                &span_data.start_time,
-                Some(&span_data.span_kind),
-                &Status::Unset,
+                &SpanEventFields {
+                    name: &span_data.name,
+                    level: span_level,
+                    keywords: span_keywords,
+                    activities: &activities,
+                    traceparent: traceparent.as_deref(),
+                    remote_parent,
+                    inline_links: None,
+                    inline_events: None,
+                    thread_name: thread_name.as_deref(),
+                    active_children: None,
+                    component,
+                    attribute_count: None,
+                    filetime_duration: None,
+                    event_time: &span_data.start_time,
+                    start_time: None,
+                    span_kind: Some(&span_data.span_kind),
+                    // The status is usually unknown when the span starts, unless it was set by the SpanBuilder
+                    status: if self.exporter_config.get_include_status_on_start() {
+                        &span_data.status
+                    } else {
+                        &Status::Unset
+                    },
+                    status_message: status_message.as_deref(),
+                    span_events: None,
+                    dropped_counts: None,
+                    event_split: None,
+                    is_start: true,
+                    add_tags: false,
+                    use_byte_for_bools,
+                    arrays_as_json,
+                    export_payload_as_json,
+                },
                 &mut std::iter::empty(),
-                true,
-                false,
-                use_byte_for_bools,
-                export_payload_as_json,
             )?;
 
             let links_keywords = self.exporter_config.get_links_keywords();
             let links_level = self.exporter_config.get_links_level().into();
 
-            if self.provider.enabled(links_level, links_keywords) {
+            if !self.exporter_config.get_inline_links()
+                && self.provider.enabled(links_level, links_keywords)
+            {
                 ebw.write_span_links(
                     &self.provider.as_ref(),
                     links_level,
@@ -639,6 +1609,7 @@ impl<C: KeywordLevelProvider> EventExporter for EtwEventExporter<C> {
                     &span_data.start_time,
                     &mut span_data.links.iter(),
                     use_byte_for_bools,
+                    arrays_as_json,
                 )?;
             }
 
@@ -651,8 +1622,24 @@ impl<C: KeywordLevelProvider> EventExporter for EtwEventExporter<C> {
     where
         S: opentelemetry_api::trace::Span + EtwSpan,
     {
-        let span_keywords = self.exporter_config.get_span_keywords();
-        let span_level = self.exporter_config.get_span_level().into();
+        if !self.exporter_config.should_export(span.get_span_data()) {
+            return Ok(());
+        }
+
+        self.log_self_description();
+
+        let span_keywords = span
+            .get_context_keyword()
+            .map_or(self.exporter_config.get_span_keywords(), |keywords| {
+                self.exporter_config.get_span_keywords() | keywords
+            })
+            | self
+                .exporter_config
+                .get_entry_span_keyword(span.get_span_data(), span.is_remote_parent());
+        let span_level = self
+            .exporter_config
+            .get_span_level_for_kind(&span.get_span_data().span_kind)
+            .into();
 
         let use_byte_for_bools = match self.bool_representation {
             InType::U8 => true,
@@ -660,36 +1647,132 @@ impl<C: KeywordLevelProvider> EventExporter for EtwEventExporter<C> {
             _ => panic!("unsupported bool representation"),
         };
         let export_payload_as_json = self.exporter_config.get_export_as_json();
+        let arrays_as_json = self.exporter_config.get_export_arrays_as_json();
 
         let span_data = span.get_span_data();
+        let single_span_event = self.exporter_config.get_single_span_event();
+        // The kind is only left off the end event when there is a start event to carry it
+        let end_span_kind = (single_span_event || !self.exporter_config.get_kind_on_start_only())
+            .then_some(&span_data.span_kind);
+        let component = self
+            .exporter_config
+            .get_component_field()
+            .then_some(span_data.instrumentation_lib.name.as_ref());
+        let attribute_count = self
+            .exporter_config
+            .get_attribute_count_field()
+            .then(|| attributes::get_attribute_count(&span_data.attributes));
+        // With status level mapping, the span end event uses the same level as the batch exporter
+        let end_level = if self.exporter_config.get_status_level_mapping() {
+            status::get_status_level(&span_data.status).into()
+        } else {
+            span_level
+        };
+        self.track_suppressed_span(
+            self.provider.enabled(span_level, span_keywords)
+                || self.provider.enabled(end_level, span_keywords),
+        );
 
-        EBW.with(|ebw| {
-            let mut ebw = ebw.borrow_mut();
+        EBW.with(|eb| {
+            let mut eb = eb.borrow_mut();
+            let mut ebw = EtwEventBuilderWrapper::new(&mut eb, &self.exporter_config);
 
-            if self.provider.enabled(span_level, span_keywords)
+            if self.provider.enabled(end_level, span_keywords)
                 && self.exporter_config.get_export_span_events()
             {
                 let activities = Activities::generate(
                     &span_data.span_context.span_id(),
                     &span_data.parent_span_id,
                     &span_data.span_context.trace_id(),
+                    self.exporter_config.get_trace_scoped_activities(),
+                )
+                .with_activity_id(span.get_root_activity_id());
+                let status_message = status::get_status_message(
+                    &span_data.status,
+                    &span_data.attributes,
+                    self.exporter_config.get_preserve_ok_description(),
                 );
+                let traceparent = self
+                    .exporter_config
+                    .get_traceparent_field()
+                    .then(|| get_traceparent(&span_data.span_context));
+                let remote_parent = self
+                    .exporter_config
+                    .get_remote_flag()
+                    .then(|| span.is_remote_parent());
+                let active_children = span.get_active_children();
+                let thread_name = self
+                    .exporter_config
+                    .get_thread_name_field()
+                    .then(threads::get_current_thread_name);
+                let filetime_duration = self
+                    .exporter_config
+                    .get_duration_filetime()
+                    .then(|| SpanDuration::new(&span_data.start_time, &span_data.end_time));
+                // Links are logged as a field of the span end event instead of as separate events
+                let inline_links = if self.exporter_config.get_inline_links() {
+                    json::get_links_as_json(&mut span_data.links.iter())
+                } else {
+                    None
+                };
 
                 ebw.write_span_event(
                     &self.provider.as_ref(),
-                    &span_data.name,
-                    span_level,
-                    span_keywords,
-                    &activities,
-                    &span_data.end_time,
-                    Some(&span_data.span_kind),
-                    &span_data.status,
+                    &SpanEventFields {
+                        name: &span_data.name,
+                        level: end_level,
+                        keywords: span_keywords,
+                        activities: &activities,
+                        traceparent: traceparent.as_deref(),
+                        remote_parent,
+                        inline_links: inline_links.as_deref(),
+                        inline_events: None,
+                        thread_name: thread_name.as_deref(),
+                        active_children,
+                        component,
+                        attribute_count,
+                        filetime_duration,
+                        event_time: &span_data.end_time,
+                        start_time: single_span_event.then_some(&span_data.start_time),
+                        span_kind: end_span_kind,
+                        status: &span_data.status,
+                        status_message: status_message.as_deref(),
+                        span_events: if self.exporter_config.get_export_event_summary() {
+                            Some(&span_data.events)
+                        } else {
+                            None
+                        },
+                        dropped_counts: None,
+                        event_split: self.exporter_config.get_event_split_size(),
+                        is_start: false,
+                        add_tags: false,
+                        use_byte_for_bools,
+                        arrays_as_json,
+                        export_payload_as_json,
+                    },
                     &mut span_data.attributes.iter(),
-                    false,
-                    false,
-                    use_byte_for_bools,
-                    export_payload_as_json,
                 )?;
+
+                // Links are normally logged when the span starts, but no start event was logged
+                let links_keywords = self.exporter_config.get_links_keywords();
+                let links_level = self.exporter_config.get_links_level().into();
+
+                if single_span_event
+                    && inline_links.is_none()
+                    && self.provider.enabled(links_level, links_keywords)
+                {
+                    ebw.write_span_links(
+                        &self.provider.as_ref(),
+                        links_level,
+                        links_keywords,
+                        &activities,
+                        &span_data.name,
+                        &span_data.start_time,
+                        &mut span_data.links.iter(),
+                        use_byte_for_bools,
+                        arrays_as_json,
+                    )?;
+                }
             }
 
             if self.provider.enabled(span_level, span_keywords)
@@ -704,6 +1787,9 @@ impl<C: KeywordLevelProvider> EventExporter for EtwEventExporter<C> {
                     span_data,
                     span.span_context(),
                     export_payload_as_json,
+                    self.exporter_config.get_export_count_fields(),
+                    use_byte_for_bools,
+                    arrays_as_json,
                     attributes,
                 )?;
             }
@@ -717,7 +1803,23 @@ impl<C: KeywordLevelProvider> EventExporter for EtwEventExporter<C> {
     where
         S: opentelemetry_api::trace::Span + EtwSpan,
     {
-        let event_keywords = self.exporter_config.get_event_keywords();
+        if !self.exporter_config.should_export(span.get_span_data()) {
+            return Ok(());
+        }
+
+        self.log_self_description();
+
+        let per_event_keywords = self.exporter_config.get_per_event_keywords();
+        let event_keywords = span
+            .get_context_keyword()
+            .map_or(self.exporter_config.get_event_keywords(), |keywords| {
+                self.exporter_config.get_event_keywords() | keywords
+            });
+        let event_keywords = if per_event_keywords {
+            events::get_per_event_keywords(&event, event_keywords)
+        } else {
+            event_keywords
+        };
         let event_level = self.exporter_config.get_event_level().into();
 
         if !self.provider.enabled(event_level, event_keywords)
@@ -733,16 +1835,24 @@ impl<C: KeywordLevelProvider> EventExporter for EtwEventExporter<C> {
             _ => panic!("unsupported bool representation"),
         };
         let export_payload_as_json = self.exporter_config.get_export_as_json();
+        let arrays_as_json = self.exporter_config.get_export_arrays_as_json();
         let span_data = span.get_span_data();
+        let component = self
+            .exporter_config
+            .get_component_field()
+            .then_some(span_data.instrumentation_lib.name.as_ref());
 
         let activities = Activities::generate(
             &span_data.span_context.span_id(),
             &span_data.parent_span_id,
             &span_data.span_context.trace_id(),
-        );
+            self.exporter_config.get_trace_scoped_activities(),
+        )
+        .with_activity_id(span.get_root_activity_id());
 
-        EBW.with(|ebw| {
-            let mut ebw = ebw.borrow_mut();
+        EBW.with(|eb| {
+            let mut eb = eb.borrow_mut();
+            let mut ebw = EtwEventBuilderWrapper::new(&mut eb, &self.exporter_config);
 
             ebw.reset(
                 &event.name,
@@ -752,40 +1862,60 @@ impl<C: KeywordLevelProvider> EventExporter for EtwEventExporter<C> {
             );
             ebw.opcode(Opcode::Info);
 
+            let field_tags = FIELD_TAG_IS_REAL_EVENT_TIME | self.exporter_config.get_field_tags();
             ebw.add_filetime(
                 "otel_event_time",
                 win_filetime_from_systemtime!(event.timestamp),
                 OutType::DateTimeUtc,
-                FIELD_TAG_IS_REAL_EVENT_TIME,
+                field_tags,
             );
-            ebw.add_win32_systemtime("time", &event.timestamp.into(), 0);
+            if !self.exporter_config.get_compact_event_timestamps() {
+                ebw.add_win32_systemtime("time", &event.timestamp.into(), 0);
+            }
 
-            ebw.add_str8("SpanId", &activities.span_id, OutType::Utf8, 0);
+            // The event's activity id already identifies the span it belongs to
+            if !self.exporter_config.get_activity_only_correlation() {
+                ebw.add_string("SpanId", activities.span_id, 0);
 
-            if !activities.parent_span_id.is_empty() {
-                ebw.add_str8("ParentId", &activities.parent_span_id, OutType::Utf8, 0);
+                if !activities.parent_span_id.is_empty() {
+                    ebw.add_string("ParentId", activities.parent_span_id, 0);
+                }
+
+                ebw.add_string("TraceId", activities.trace_id_name, 0);
             }
 
-            ebw.add_str8("TraceId", &activities.trace_id_name, OutType::Utf8, 0);
+            if self.exporter_config.get_event_offsets() {
+                ebw.add_u64(
+                    "OffsetNs",
+                    durations::get_offset_ns(&span_data.start_time, &event.timestamp),
+                    OutType::Unsigned,
+                    0,
+                );
+            }
 
-            let mut added = false;
+            if let Some(component) = component {
+                ebw.add_string("Component", component, 0);
+            }
 
-            #[cfg(feature = "json")]
-            if export_payload_as_json {
-                let json_string = json::get_attributes_as_json(
-                    &mut event.attributes.iter().map(|kv| (&kv.key, &kv.value)),
-                );
-                ebw.add_str8("Payload", &json_string, OutType::Json, 0);
-                added = true;
+            if let Some(service_instance_id) = self.exporter_config.get_service_instance_id() {
+                ebw.add_string("ServiceInstanceId", &**service_instance_id, 0);
             }
 
-            if !added {
-                ebw.add_attributes_to_event(
-                    &mut event.attributes.iter().map(|kv| (&kv.key, &kv.value)),
-                    use_byte_for_bools,
+            if self.exporter_config.get_grouped_event_attributes() {
+                let msgpack = self.exporter_config.get_export_as_msgpack();
+                ebw.add_attributes_struct(
+                    &mut events::get_event_attributes(&event, per_event_keywords),
+                    export_payload_as_json || msgpack,
                 );
             }
 
+            ebw.add_payload(
+                &mut events::get_event_attributes(&event, per_event_keywords),
+                use_byte_for_bools,
+                arrays_as_json,
+                export_payload_as_json,
+            );
+
             let win32err = ebw.write(
                 &self.provider,
                 Some(Guid::from_bytes_be(&activities.activity_id)).as_ref(),
@@ -806,7 +1936,16 @@ impl<C: KeywordLevelProvider> EventExporter for EtwEventExporter<C> {
 
     // Called by the batch exporter sometime after span is completed
     fn log_span_data(&self, span_data: &SpanData) -> ExportResult {
-        let span_keywords = self.exporter_config.get_span_keywords();
+        if !self.exporter_config.should_export(span_data) {
+            return Ok(());
+        }
+
+        self.log_self_description();
+
+        let span_keywords = self.exporter_config.get_span_keywords()
+            | self
+                .exporter_config
+                .get_entry_span_keyword(span_data, false);
 
         let use_byte_for_bools = match self.bool_representation {
             InType::U8 => true,
@@ -814,15 +1953,27 @@ impl<C: KeywordLevelProvider> EventExporter for EtwEventExporter<C> {
             _ => panic!("unsupported bool representation"),
         };
         let export_payload_as_json = self.exporter_config.get_export_as_json();
-
-        let level = match span_data.status {
-            Status::Ok => Level::Informational,
-            Status::Error { .. } => Level::Error,
-            Status::Unset => Level::Verbose,
-        };
-
-        EBW.with(|ebw| {
-            let mut ebw = ebw.borrow_mut();
+        let arrays_as_json = self.exporter_config.get_export_arrays_as_json();
+        let dropped_counts = DroppedCounts::from_span_data(span_data);
+        let single_span_event = self.exporter_config.get_single_span_event();
+        // The kind is only left off the end event when there is a start event to carry it
+        let end_span_kind = (single_span_event || !self.exporter_config.get_kind_on_start_only())
+            .then_some(&span_data.span_kind);
+        let component = self
+            .exporter_config
+            .get_component_field()
+            .then_some(span_data.instrumentation_lib.name.as_ref());
+        let attribute_count = self
+            .exporter_config
+            .get_attribute_count_field()
+            .then(|| attributes::get_attribute_count(&span_data.attributes));
+
+        let level = status::get_status_level(&span_data.status).into();
+        self.track_suppressed_span(self.provider.enabled(level, span_keywords));
+
+        EBW.with(|eb| {
+            let mut eb = eb.borrow_mut();
+            let mut ebw = EtwEventBuilderWrapper::new(&mut eb, &self.exporter_config);
             let mut err = Ok(());
 
             if self.provider.enabled(level, span_keywords)
@@ -832,78 +1983,151 @@ impl<C: KeywordLevelProvider> EventExporter for EtwEventExporter<C> {
                     &span_data.span_context.span_id(),
                     &span_data.parent_span_id,
                     &span_data.span_context.trace_id(),
+                    self.exporter_config.get_trace_scoped_activities(),
+                );
+                let status_message = status::get_status_message(
+                    &span_data.status,
+                    &span_data.attributes,
+                    self.exporter_config.get_preserve_ok_description(),
                 );
+                let traceparent = self
+                    .exporter_config
+                    .get_traceparent_field()
+                    .then(|| get_traceparent(&span_data.span_context));
+                let filetime_duration = self
+                    .exporter_config
+                    .get_duration_filetime()
+                    .then(|| SpanDuration::new(&span_data.start_time, &span_data.end_time));
+                // Links are logged as a field of the span end event instead of as separate events
+                let inline_links = if self.exporter_config.get_inline_links() {
+                    json::get_links_as_json(&mut span_data.links.iter())
+                } else {
+                    None
+                };
+                // Span events are logged as a field of the span end event instead of as separate events
+                let inline_events = if self.exporter_config.get_inline_events() {
+                    json::get_events_as_json(&mut span_data.events.iter())
+                } else {
+                    None
+                };
+
+                // The span end event shares most of its fields with the start event
+                let start_fields = SpanEventFields {
+                    name: &span_data.name,
+                    level,
+                    keywords: span_keywords,
+                    activities: &activities,
+                    traceparent: traceparent.as_deref(),
+                    remote_parent: None,
+                    inline_links: None,
+                    inline_events: None,
+                    thread_name: None,
+                    active_children: None,
+                    component,
+                    attribute_count: None,
+                    filetime_duration: None,
+                    event_time: &span_data.start_time,
+                    start_time: None,
+                    span_kind: Some(&span_data.span_kind),
+                    status: &span_data.status,
+                    status_message: status_message.as_deref(),
+                    span_events: None,
+                    dropped_counts: None,
+                    event_split: None,
+                    is_start: true,
+                    add_tags: true,
+                    use_byte_for_bools,
+                    arrays_as_json,
+                    export_payload_as_json,
+                };
 
-                err = ebw
-                    .write_span_event(
+                err = if single_span_event {
+                    Ok(())
+                } else {
+                    ebw.write_span_event(
                         &self.provider.as_ref(),
-                        &span_data.name,
-                        level,
-                        span_keywords,
-                        &activities,
-                        &span_data.start_time,
-                        Some(&span_data.span_kind),
-                        &span_data.status,
+                        &start_fields,
                         &mut std::iter::empty(),
-                        true,
-                        true,
-                        use_byte_for_bools,
-                        export_payload_as_json,
                     )
-                    .and_then(|_| {
-                        let event_keywords = self.exporter_config.get_event_keywords();
-                        let event_level = self.exporter_config.get_event_level().into();
-
-                        if self.provider.enabled(event_level, event_keywords) {
-                            ebw.write_span_events(
-                                &self.provider.as_ref(),
-                                event_level,
-                                event_keywords,
-                                &activities,
-                                &mut span_data.events.iter(),
+                }
+                .and_then(|_| {
+                    let event_keywords = self.exporter_config.get_event_keywords();
+                    let event_level = self.exporter_config.get_event_level().into();
+                    let per_event_keywords = self.exporter_config.get_per_event_keywords();
+
+                    // With per-event keywords, each event is checked separately.
+                    if inline_events.is_none()
+                        && (per_event_keywords
+                            || self.provider.enabled(event_level, event_keywords))
+                    {
+                        ebw.write_span_events(
+                            &self.provider.as_ref(),
+                            &SpanEventsFields {
+                                level: event_level,
+                                keywords: event_keywords,
+                                activities: &activities,
+                                span_start_time: &span_data.start_time,
+                                component,
                                 use_byte_for_bools,
+                                arrays_as_json,
                                 export_payload_as_json,
-                            )
-                        } else {
-                            Ok(())
-                        }
-                    })
-                    .and_then(|_| {
-                        let links_keywords = self.exporter_config.get_links_keywords();
-                        let links_level = self.exporter_config.get_links_level().into();
-
-                        if self.provider.enabled(links_level, links_keywords) {
-                            ebw.write_span_links(
-                                &self.provider.as_ref(),
-                                links_level,
-                                links_keywords,
-                                &activities,
-                                &span_data.name,
-                                &span_data.start_time,
-                                &mut span_data.links.iter(),
-                                use_byte_for_bools,
-                            )
-                        } else {
-                            Ok(())
-                        }
-                    })
-                    .and_then(|_| {
-                        ebw.write_span_event(
+                                per_event_keywords,
+                            },
+                            &mut span_data.events.iter(),
+                        )
+                    } else {
+                        Ok(())
+                    }
+                })
+                .and_then(|_| {
+                    let links_keywords = self.exporter_config.get_links_keywords();
+                    let links_level = self.exporter_config.get_links_level().into();
+
+                    if inline_links.is_none() && self.provider.enabled(links_level, links_keywords)
+                    {
+                        ebw.write_span_links(
                             &self.provider.as_ref(),
-                            &span_data.name,
-                            level,
-                            span_keywords,
+                            links_level,
+                            links_keywords,
                             &activities,
-                            &span_data.end_time,
-                            Some(&span_data.span_kind),
-                            &span_data.status,
-                            &mut span_data.attributes.iter(),
-                            false,
-                            true,
+                            &span_data.name,
+                            &span_data.start_time,
+                            &mut span_data.links.iter(),
                             use_byte_for_bools,
-                            export_payload_as_json,
+                            arrays_as_json,
                         )
-                    });
+                    } else {
+                        Ok(())
+                    }
+                })
+                .and_then(|_| {
+                    ebw.write_span_event(
+                        &self.provider.as_ref(),
+                        &SpanEventFields {
+                            inline_links: inline_links.as_deref(),
+                            inline_events: inline_events.as_deref(),
+                            attribute_count,
+                            filetime_duration,
+                            event_time: &span_data.end_time,
+                            start_time: single_span_event.then_some(&span_data.start_time),
+                            span_kind: end_span_kind,
+                            span_events: if self.exporter_config.get_export_event_summary() {
+                                Some(&span_data.events)
+                            } else {
+                                None
+                            },
+                            dropped_counts: if self.exporter_config.get_export_dropped_counts() {
+                                Some(&dropped_counts)
+                            } else {
+                                None
+                            },
+                            event_split: self.exporter_config.get_event_split_size(),
+                            is_start: false,
+                            ..start_fields
+                        },
+                        &mut span_data.attributes.iter(),
+                    )
+                });
             }
 
             if self.provider.enabled(Level::Informational, span_keywords)
@@ -919,6 +2143,9 @@ impl<C: KeywordLevelProvider> EventExporter for EtwEventExporter<C> {
                     span_data,
                     &span_data.span_context,
                     export_payload_as_json,
+                    self.exporter_config.get_export_count_fields(),
+                    use_byte_for_bools,
+                    arrays_as_json,
                     attributes,
                 );
 
@@ -941,9 +2168,46 @@ mod tests {
     const TEST_KEY_INT: Key = Key::from_static_str("int");
     const TEST_KEY_FLOAT: Key = Key::from_static_str("float");
 
+    // The name and InType, including the count flags, of each field of the event in `eb`
+    fn field_in_types(eb: &EventBuilder) -> Vec<(String, u8)> {
+        let debug = format!("{:?}", eb);
+        let meta: Vec<u8> = debug[debug.find("meta: [").unwrap() + "meta: [".len()..]
+            .split(']')
+            .next()
+            .unwrap()
+            .split(", ")
+            .filter(|byte| !byte.is_empty())
+            .map(|byte| byte.parse().unwrap())
+            .collect();
+
+        // Skip the metadata size, the event tag bytes and the event name
+        let tag_len = meta[2..].iter().position(|&b| b & 0x80 == 0).unwrap() + 1;
+        let mut rest = &meta[2 + tag_len..];
+        rest = &rest[rest.iter().position(|&b| b == 0).unwrap() + 1..];
+
+        // Each field's name is followed by its InType, then its optional OutType and tag
+        // bytes, each chained to the next by the high bit
+        let mut fields = Vec::new();
+        while !rest.is_empty() {
+            let name_len = rest.iter().position(|&b| b == 0).unwrap();
+            let mut len = name_len + 1;
+            fields.push((
+                String::from_utf8_lossy(&rest[..name_len]).into_owned(),
+                rest[len] & 0x7F,
+            ));
+            while rest[len] & 0x80 != 0 {
+                len += 1;
+            }
+            rest = &rest[len + 1..];
+        }
+        fields
+    }
+
     #[test]
     fn add_attributes() {
-        let mut ebw = EtwEventBuilderWrapper::new();
+        let exporter_config = ExporterConfig::default();
+        let mut eb = EventBuilder::new();
+        let mut ebw = EtwEventBuilderWrapper::new(&mut eb, &exporter_config);
 
         let attribs = vec![
             TEST_KEY_STR.string("is cool"),
@@ -952,12 +2216,286 @@ mod tests {
             TEST_KEY_FLOAT.f64(7.1),
         ];
 
-        ebw.add_attributes_to_event(&mut attribs.iter().map(|kv| (&kv.key, &kv.value)), false);
+        ebw.add_attributes_to_event(
+            &mut attribs.iter().map(|kv| (&kv.key, &kv.value)),
+            false,
+            false,
+        );
+    }
+
+    #[test]
+    fn utf16_strings() {
+        let value = "is cool \u{2713}";
+        assert_eq!(
+            String::from_utf16(&utf16_from_utf8(value.as_bytes())).unwrap(),
+            value
+        );
+
+        let exporter_config = ExporterConfig {
+            utf16_strings: true,
+            ..ExporterConfig::default()
+        };
+        let mut eb = EventBuilder::new();
+        let mut ebw = EtwEventBuilderWrapper::new(&mut eb, &exporter_config);
+        ebw.reset("Event", Level::Informational, 1, 0);
+
+        let attribs = [
+            TEST_KEY_STR.string(value),
+            TEST_KEY_STR.array(vec![StringValue::from(value)]),
+        ];
+
+        ebw.add_attributes_to_event(
+            &mut attribs.iter().map(|kv| (&kv.key, &kv.value)),
+            false,
+            false,
+        );
+
+        let str16 = InType::Str16.as_int();
+        assert_eq!(
+            field_in_types(&ebw),
+            [
+                ("str".to_owned(), str16),
+                ("str".to_owned(), str16 | InType::VariableCountFlag),
+            ]
+        );
+    }
+
+    #[test]
+    fn json_string_detection() {
+        let mut eb = EventBuilder::new();
+        let exporter_config = ExporterConfig::default();
+        let ebw = EtwEventBuilderWrapper::new(&mut eb, &exporter_config);
+        assert_eq!(
+            ebw.get_string_out_type(&Key::new("payload.json")),
+            OutType::Utf8
+        );
+
+        let exporter_config = ExporterConfig {
+            json_string_suffix: Some(Arc::from(".json")),
+            ..ExporterConfig::default()
+        };
+        let ebw = EtwEventBuilderWrapper::new(&mut eb, &exporter_config);
+        assert_eq!(
+            ebw.get_string_out_type(&Key::new("payload.json")),
+            OutType::Json
+        );
+        assert_eq!(ebw.get_string_out_type(&Key::new("payload")), OutType::Utf8);
+    }
+
+    #[test]
+    fn string_outtype_map() {
+        let mut eb = EventBuilder::new();
+        let exporter_config = ExporterConfig::default();
+        let ebw = EtwEventBuilderWrapper::new(&mut eb, &exporter_config);
+        assert_eq!(
+            ebw.get_string_out_type(&Key::new("request.xml")),
+            OutType::Utf8
+        );
+
+        let exporter_config = ExporterConfig {
+            string_outtype_map: Some(Arc::from([
+                (Arc::from(".xml"), StringOutType::Xml),
+                (Arc::from(".body"), StringOutType::Json),
+            ])),
+            ..ExporterConfig::default()
+        };
+        let ebw = EtwEventBuilderWrapper::new(&mut eb, &exporter_config);
+        assert_eq!(
+            ebw.get_string_out_type(&Key::new("request.xml")),
+            OutType::Xml
+        );
+        assert_eq!(
+            ebw.get_string_out_type(&Key::new("response.body")),
+            OutType::Json
+        );
+        // There is no OutType for URIs, so only mapped suffixes change the OutType
+        assert_eq!(
+            ebw.get_string_out_type(&Key::new("link.uri")),
+            OutType::Utf8
+        );
+    }
+
+    #[test]
+    fn channel() {
+        let channel = |exporter_config: &ExporterConfig<_>| {
+            let mut eb = EventBuilder::new();
+            EtwEventBuilderWrapper::new(&mut eb, exporter_config).reset(
+                "Event",
+                Level::Informational,
+                1,
+                0,
+            );
+            let debug = format!("{:?}", eb);
+            debug[debug.find("channel: ").unwrap()..]
+                .split(',')
+                .next()
+                .unwrap()
+                .to_owned()
+        };
+
+        let mut exporter_config = ExporterConfig::default();
+        assert_eq!(
+            channel(&exporter_config),
+            format!("channel: {:?}", Channel::TraceLogging)
+        );
+
+        exporter_config.etw_channel = Some(16);
+        assert_eq!(
+            channel(&exporter_config),
+            format!("channel: {:?}", Channel::from_int(16))
+        );
+    }
+
+    #[test]
+    fn bool_representation() {
+        let provider = Box::pin(Provider::new("bool_representation", &Provider::options()));
+        let activities = Activities::generate(
+            &SpanId::from_bytes(1u64.to_be_bytes()),
+            &SpanId::INVALID,
+            &opentelemetry::trace::TraceId::from_bytes(1u128.to_be_bytes()),
+            false,
+        );
+
+        let mut attributes = opentelemetry_sdk::trace::EvictedHashMap::new(1, 1);
+        attributes.insert(TEST_KEY_BOOL.bool(true));
+        let span_data = SpanData {
+            span_context: SpanContext::empty_context(),
+            parent_span_id: SpanId::INVALID,
+            span_kind: SpanKind::Internal,
+            name: "span".into(),
+            start_time: SystemTime::now(),
+            end_time: SystemTime::now(),
+            attributes,
+            events: EvictedQueue::new(0),
+            links: EvictedQueue::new(0),
+            status: Status::Unset,
+            resource: Cow::Owned(opentelemetry_sdk::Resource::empty()),
+            instrumentation_lib: opentelemetry::InstrumentationLibrary::new("test", None, None),
+        };
+
+        // Both the real-time and the Common Schema events take the bool representation
+        // from the same setting, rather than Common Schema always using a byte.
+        let exporter_config = ExporterConfig::default();
+        for use_byte_for_bools in [true, false] {
+            let mut eb = EventBuilder::new();
+            let mut ebw = EtwEventBuilderWrapper::new(&mut eb, &exporter_config);
+            let bool_in_type = if use_byte_for_bools {
+                InType::U8
+            } else {
+                InType::Bool32
+            };
+            let bool_field = (TEST_KEY_BOOL.as_str().to_owned(), bool_in_type.as_int());
+
+            let _ = ebw.write_span_event(
+                &provider.as_ref(),
+                &SpanEventFields {
+                    name: &span_data.name,
+                    level: Level::Informational,
+                    keywords: 1,
+                    activities: &activities,
+                    traceparent: None,
+                    remote_parent: None,
+                    inline_links: None,
+                    inline_events: None,
+                    thread_name: None,
+                    active_children: None,
+                    component: None,
+                    attribute_count: None,
+                    filetime_duration: None,
+                    event_time: &span_data.end_time,
+                    start_time: None,
+                    span_kind: Some(&span_data.span_kind),
+                    status: &span_data.status,
+                    status_message: None,
+                    span_events: None,
+                    dropped_counts: None,
+                    event_split: None,
+                    is_start: false,
+                    add_tags: true,
+                    use_byte_for_bools,
+                    arrays_as_json: false,
+                    export_payload_as_json: false,
+                },
+                &mut span_data.attributes.iter(),
+            );
+            assert!(field_in_types(&ebw).contains(&bool_field));
+
+            let _ = ebw.write_common_schema_span(
+                &provider.as_ref(),
+                &span_data.name,
+                Level::Informational,
+                1,
+                &span_data,
+                &span_data.span_context,
+                false,
+                false,
+                use_byte_for_bools,
+                false,
+                span_data.attributes.iter(),
+            );
+            assert!(field_in_types(&ebw).contains(&bool_field));
+        }
+    }
+
+    #[test]
+    fn provider_stopping() {
+        let stopping = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let tap_stopping = stopping.clone();
+        let exporter_config = ExporterConfig {
+            lifecycle_events: true,
+            event_tap: Some(Arc::new(move |meta: &EventMeta| {
+                if meta.name == "ProviderStopping" {
+                    tap_stopping.fetch_add(1, Ordering::Relaxed);
+                }
+            })),
+            ..ExporterConfig::default()
+        };
+
+        let (provider, lifecycle) = register_provider(
+            "provider_stopping",
+            &Guid::from_name("provider_stopping"),
+            &mut Provider::options(),
+            &exporter_config,
+        );
+        let lifecycle = lifecycle.expect("lifecycle events are enabled");
+        let context = lifecycle.as_ref() as *const ProviderLifecycle as usize;
+        let callback = |event_control_code| {
+            lifecycle_enable_callback(
+                &Guid::zero(),
+                event_control_code,
+                Level::Verbose,
+                0,
+                0,
+                0,
+                context,
+            )
+        };
+
+        // Disabling a provider that no session enabled isn't a transition to disabled
+        callback(0);
+        assert_eq!(stopping.load(Ordering::Relaxed), 0);
+
+        callback(1);
+        callback(1);
+        callback(0);
+        assert_eq!(stopping.load(Ordering::Relaxed), 1);
+
+        callback(0);
+        assert_eq!(stopping.load(Ordering::Relaxed), 1);
+
+        drop(EtwEventExporter::new(
+            provider,
+            Some(lifecycle),
+            exporter_config,
+            InType::Bool32,
+        ));
     }
 
     #[test]
     fn add_attribute_sequences() {
-        let mut ebw = EtwEventBuilderWrapper::new();
+        let exporter_config = ExporterConfig::default();
+        let mut eb = EventBuilder::new();
+        let mut ebw = EtwEventBuilderWrapper::new(&mut eb, &exporter_config);
 
         let attribs = vec![
             TEST_KEY_STR.array(vec![StringValue::from("is cool")]),
@@ -966,6 +2504,10 @@ mod tests {
             TEST_KEY_FLOAT.array(vec![7.1, 0.9, -1.3]),
         ];
 
-        ebw.add_attributes_to_event(&mut attribs.iter().map(|kv| (&kv.key, &kv.value)), true);
+        ebw.add_attributes_to_event(
+            &mut attribs.iter().map(|kv| (&kv.key, &kv.value)),
+            true,
+            false,
+        );
     }
 }
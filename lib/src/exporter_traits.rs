@@ -1,4 +1,12 @@
-use crate::common::EtwSpan;
+use crate::common::{attributes::AttributeFilter, common_schema::AppExtension, EtwSpan};
+use opentelemetry::{
+    trace::{SpanId, SpanKind},
+    Value,
+};
+use opentelemetry_sdk::export::trace::SpanData;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::SystemTime;
 
 /// Implement this trait to provide an override for
 /// event keywords or levels.
@@ -7,6 +15,11 @@ use crate::common::EtwSpan;
 /// [`tracelogging::Level::Informational`].
 /// Events attached to the span are logged with keyword 0x10 and [`tracelogging::Level::Verbose`].
 /// Span Links are logged as events with keyword 0x100 and [`tracelogging::Level::Verbose`].
+///
+/// A keyword of 0 matches any session that enabled the provider at the event's level,
+/// regardless of the keywords the session enabled.
+/// On Linux, an event with keyword 0 is written to the keyword 0 tracepoint if it is enabled,
+/// and otherwise to an enabled tracepoint for one of the other configured keywords at that level.
 pub trait KeywordLevelProvider: Send + Sync {
     /// The keyword(s) to use for Span start/stop events.
     fn get_span_keywords(&self) -> u64;
@@ -17,10 +30,76 @@ pub trait KeywordLevelProvider: Send + Sync {
 
     /// The level to use for Span start/stop events.
     fn get_span_level(&self) -> u8;
+    /// The level to use for the start/stop events of a Span with the given kind.
+    /// Defaults to [`get_span_level`](KeywordLevelProvider::get_span_level) for every kind.
+    /// This only applies to real-time span events. Batched span events, Common Schema events
+    /// and, with status level mapping, real-time span end events use a level based on the
+    /// span's status instead.
+    fn get_span_level_for_kind(&self, kind: &SpanKind) -> u8 {
+        let _ = kind;
+        self.get_span_level()
+    }
     /// The keyword(s) to use for Span Event events.
     fn get_event_level(&self) -> u8;
     /// The keyword(s) to use for Span Link events.
     fn get_links_level(&self) -> u8;
+
+    /// Additional keyword(s) that are combined with the Span start/stop keyword(s).
+    /// An ETW session can set MatchAllKeyword to require both sets of keywords,
+    /// rather than only matching any one of them.
+    fn get_span_secondary_keywords(&self) -> u64 {
+        0
+    }
+    /// Additional keyword(s) that are combined with the Span Event keyword(s).
+    fn get_event_secondary_keywords(&self) -> u64 {
+        0
+    }
+    /// Additional keyword(s) that are combined with the Span Link keyword(s).
+    fn get_links_secondary_keywords(&self) -> u64 {
+        0
+    }
+}
+
+pub(crate) type ExportFilter = Arc<dyn Fn(&SpanData) -> bool + Send + Sync>;
+
+pub(crate) type EventTap = Arc<dyn Fn(&EventMeta) + Send + Sync>;
+
+/// Key suffixes and the OutType of the string attributes whose key ends in them, in match order.
+pub(crate) type StringOutTypeMap = Arc<[(Arc<str>, StringOutType)]>;
+
+pub(crate) type ValueRedaction = Arc<dyn Fn(&str, &Value) -> Option<Value> + Send + Sync>;
+
+/// Checks whether span events are enabled, without keeping the exporter alive.
+pub(crate) type SpansEnabled = Arc<dyn Fn() -> bool + Send + Sync>;
+
+/// Implement this trait to control the times that are recorded for spans and span events
+/// where the instrumentation doesn't supply them, such as to make them predictable in tests.
+pub trait Clock: Send + Sync {
+    /// The current time.
+    fn now(&self) -> SystemTime;
+}
+
+/// The [`Clock`] that reads the system time, which is used by default.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// The metadata of an event written by the exporter, passed to the callback set with
+/// [`ExporterBuilder::with_event_tap`](crate::spans::ExporterBuilder::with_event_tap).
+#[derive(Clone, Copy, Debug)]
+pub struct EventMeta<'a> {
+    /// The name of the event.
+    pub name: &'a str,
+    /// The level of the event.
+    pub level: u8,
+    /// The keywords of the event. For Linux user_events, these are the keywords of
+    /// the tracepoint that the event was written to.
+    pub keyword: u64,
 }
 
 pub(crate) struct ExporterConfig<T: KeywordLevelProvider> {
@@ -28,6 +107,69 @@ pub(crate) struct ExporterConfig<T: KeywordLevelProvider> {
     pub(crate) json: bool,
     pub(crate) common_schema: bool,
     pub(crate) etw_activities: bool,
+    pub(crate) event_summary: bool,
+    pub(crate) event_split: Option<usize>,
+    pub(crate) count_fields: bool,
+    pub(crate) arrays_as_json: bool,
+    pub(crate) dropped_counts: bool,
+    pub(crate) utf16_strings: bool,
+    pub(crate) json_string_suffix: Option<Arc<str>>,
+    pub(crate) export_filter: Option<ExportFilter>,
+    pub(crate) etw_channel: Option<u8>,
+    pub(crate) per_event_keywords: bool,
+    pub(crate) startup_event: bool,
+    pub(crate) lifecycle_events: bool,
+    pub(crate) app_extension: Option<AppExtension>,
+    pub(crate) single_span_event: bool,
+    pub(crate) status_level_mapping: bool,
+    pub(crate) thread_activity_scope: bool,
+    pub(crate) unit_annotations: bool,
+    pub(crate) trace_scoped_activities: bool,
+    pub(crate) semantic_convention_promotion: bool,
+    pub(crate) float_sanitization: bool,
+    pub(crate) traceparent_field: bool,
+    pub(crate) remote_flag: bool,
+    pub(crate) float_rounding: Option<u8>,
+    pub(crate) inline_links: bool,
+    pub(crate) thread_name_field: bool,
+    pub(crate) attribute_prefix: Option<Arc<str>>,
+    pub(crate) max_events_emitted: Option<usize>,
+    pub(crate) include_status_on_start: bool,
+    pub(crate) event_dedup: bool,
+    pub(crate) time_format: TimeFormat,
+    pub(crate) duration_filetime: bool,
+    pub(crate) msgpack: bool,
+    pub(crate) root_activity_from_context: bool,
+    pub(crate) sdk_extension: bool,
+    pub(crate) event_tap: Option<EventTap>,
+    pub(crate) self_description: bool,
+    pub(crate) attribute_filter: Option<Arc<AttributeFilter>>,
+    pub(crate) value_redaction: Option<ValueRedaction>,
+    pub(crate) child_tracking: bool,
+    pub(crate) compact_common_schema: bool,
+    pub(crate) preserve_ok_description: bool,
+    pub(crate) event_offsets: bool,
+    pub(crate) event_tags: u32,
+    pub(crate) field_tags: u32,
+    pub(crate) max_array_length: Option<usize>,
+    pub(crate) numeric_string_coercion: bool,
+    pub(crate) kind_on_start_only: bool,
+    pub(crate) activity_only_correlation: bool,
+    pub(crate) clock: Option<Arc<dyn Clock>>,
+    pub(crate) component_field: bool,
+    pub(crate) attribute_count_field: bool,
+    pub(crate) string_outtype_map: Option<StringOutTypeMap>,
+    pub(crate) service_instance_id: Option<Arc<str>>,
+    pub(crate) attributes_first: bool,
+    pub(crate) common_schema_trace_flags: bool,
+    pub(crate) suppressed_spans: Arc<AtomicU64>,
+    pub(crate) suppressed_spans_event: bool,
+    pub(crate) grouped_event_attributes: bool,
+    pub(crate) link_attr_count: bool,
+    pub(crate) compact_event_timestamps: bool,
+    pub(crate) default_tracer_name: Option<Arc<str>>,
+    pub(crate) entry_span_keyword: u64,
+    pub(crate) inline_events: bool,
 }
 
 pub(crate) struct DefaultKeywordLevelProvider;
@@ -64,6 +206,150 @@ impl KeywordLevelProvider for DefaultKeywordLevelProvider {
     }
 }
 
+/// The default levels with keywords read from the `OTEL_ETW_KEYWORDS` environment variable.
+pub(crate) struct EnvKeywordLevelProvider {
+    pub(crate) span_keywords: u64,
+    pub(crate) event_keywords: u64,
+    pub(crate) links_keywords: u64,
+}
+
+impl EnvKeywordLevelProvider {
+    /// Parse up to three comma separated keywords, in decimal or `0x` prefixed hexadecimal,
+    /// for spans, span events and span links. Missing or invalid values use the default keywords.
+    pub(crate) fn from_env_value(value: &str) -> Self {
+        fn parse_keywords(value: &str) -> Option<u64> {
+            let value = value.trim();
+            match value
+                .strip_prefix("0x")
+                .or_else(|| value.strip_prefix("0X"))
+            {
+                Some(hex) => u64::from_str_radix(hex, 16).ok(),
+                None => value.parse().ok(),
+            }
+        }
+
+        let mut keywords = value.split(',').map(parse_keywords);
+        let mut next_or = |default: u64| keywords.next().flatten().unwrap_or(default);
+
+        EnvKeywordLevelProvider {
+            span_keywords: next_or(DefaultKeywordLevelProvider.get_span_keywords()),
+            event_keywords: next_or(DefaultKeywordLevelProvider.get_event_keywords()),
+            links_keywords: next_or(DefaultKeywordLevelProvider.get_links_keywords()),
+        }
+    }
+}
+
+impl KeywordLevelProvider for EnvKeywordLevelProvider {
+    #[inline(always)]
+    fn get_span_keywords(&self) -> u64 {
+        self.span_keywords
+    }
+
+    #[inline(always)]
+    fn get_event_keywords(&self) -> u64 {
+        self.event_keywords
+    }
+
+    #[inline(always)]
+    fn get_links_keywords(&self) -> u64 {
+        self.links_keywords
+    }
+
+    #[inline(always)]
+    fn get_span_level(&self) -> u8 {
+        DefaultKeywordLevelProvider.get_span_level()
+    }
+
+    #[inline(always)]
+    fn get_event_level(&self) -> u8 {
+        DefaultKeywordLevelProvider.get_event_level()
+    }
+
+    #[inline(always)]
+    fn get_links_level(&self) -> u8 {
+        DefaultKeywordLevelProvider.get_links_level()
+    }
+}
+
+/// A [`KeywordLevelProvider`] for overriding only some of the keywords and levels,
+/// to pass to [`crate::spans::ExporterBuilder::with_custom_keywords_levels`].
+/// Each value that is `None` uses the default keywords or level.
+///
+/// ```
+/// # use opentelemetry_etw_user_events::{KeywordLevelProvider, SimpleKeywordLevels};
+/// let kwl = SimpleKeywordLevels {
+///     span_keywords: Some(0x4),
+///     ..Default::default()
+/// };
+/// assert_eq!(kwl.get_span_keywords(), 0x4);
+/// assert_eq!(kwl.get_event_keywords(), 0x10);
+/// ```
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SimpleKeywordLevels {
+    pub span_keywords: Option<u64>,
+    pub event_keywords: Option<u64>,
+    pub links_keywords: Option<u64>,
+    pub span_level: Option<u8>,
+    pub event_level: Option<u8>,
+    pub links_level: Option<u8>,
+    pub span_secondary_keywords: Option<u64>,
+    pub event_secondary_keywords: Option<u64>,
+    pub links_secondary_keywords: Option<u64>,
+}
+
+impl KeywordLevelProvider for SimpleKeywordLevels {
+    #[inline(always)]
+    fn get_span_keywords(&self) -> u64 {
+        self.span_keywords
+            .unwrap_or_else(|| DefaultKeywordLevelProvider.get_span_keywords())
+    }
+
+    #[inline(always)]
+    fn get_event_keywords(&self) -> u64 {
+        self.event_keywords
+            .unwrap_or_else(|| DefaultKeywordLevelProvider.get_event_keywords())
+    }
+
+    #[inline(always)]
+    fn get_links_keywords(&self) -> u64 {
+        self.links_keywords
+            .unwrap_or_else(|| DefaultKeywordLevelProvider.get_links_keywords())
+    }
+
+    #[inline(always)]
+    fn get_span_level(&self) -> u8 {
+        self.span_level
+            .unwrap_or_else(|| DefaultKeywordLevelProvider.get_span_level())
+    }
+
+    #[inline(always)]
+    fn get_event_level(&self) -> u8 {
+        self.event_level
+            .unwrap_or_else(|| DefaultKeywordLevelProvider.get_event_level())
+    }
+
+    #[inline(always)]
+    fn get_links_level(&self) -> u8 {
+        self.links_level
+            .unwrap_or_else(|| DefaultKeywordLevelProvider.get_links_level())
+    }
+
+    #[inline(always)]
+    fn get_span_secondary_keywords(&self) -> u64 {
+        self.span_secondary_keywords.unwrap_or_default()
+    }
+
+    #[inline(always)]
+    fn get_event_secondary_keywords(&self) -> u64 {
+        self.event_secondary_keywords.unwrap_or_default()
+    }
+
+    #[inline(always)]
+    fn get_links_secondary_keywords(&self) -> u64 {
+        self.links_secondary_keywords.unwrap_or_default()
+    }
+}
+
 impl KeywordLevelProvider for Box<dyn KeywordLevelProvider> {
     #[inline(always)]
     fn get_span_keywords(&self) -> u64 {
@@ -85,6 +371,11 @@ impl KeywordLevelProvider for Box<dyn KeywordLevelProvider> {
         self.as_ref().get_span_level()
     }
 
+    #[inline(always)]
+    fn get_span_level_for_kind(&self, kind: &SpanKind) -> u8 {
+        self.as_ref().get_span_level_for_kind(kind)
+    }
+
     #[inline(always)]
     fn get_event_level(&self) -> u8 {
         self.as_ref().get_event_level()
@@ -94,22 +385,39 @@ impl KeywordLevelProvider for Box<dyn KeywordLevelProvider> {
     fn get_links_level(&self) -> u8 {
         self.as_ref().get_links_level()
     }
+
+    #[inline(always)]
+    fn get_span_secondary_keywords(&self) -> u64 {
+        self.as_ref().get_span_secondary_keywords()
+    }
+
+    #[inline(always)]
+    fn get_event_secondary_keywords(&self) -> u64 {
+        self.as_ref().get_event_secondary_keywords()
+    }
+
+    #[inline(always)]
+    fn get_links_secondary_keywords(&self) -> u64 {
+        self.as_ref().get_links_secondary_keywords()
+    }
 }
 
+// The exporters get their keywords from the config, so the secondary keywords are combined here
+// and every event is written with the combined keywords.
 impl<T: KeywordLevelProvider> KeywordLevelProvider for ExporterConfig<T> {
     #[inline(always)]
     fn get_span_keywords(&self) -> u64 {
-        self.kwl.get_span_keywords()
+        self.kwl.get_span_keywords() | self.kwl.get_span_secondary_keywords()
     }
 
     #[inline(always)]
     fn get_event_keywords(&self) -> u64 {
-        self.kwl.get_event_keywords()
+        self.kwl.get_event_keywords() | self.kwl.get_event_secondary_keywords()
     }
 
     #[inline(always)]
     fn get_links_keywords(&self) -> u64 {
-        self.kwl.get_links_keywords()
+        self.kwl.get_links_keywords() | self.kwl.get_links_secondary_keywords()
     }
 
     #[inline(always)]
@@ -117,6 +425,11 @@ impl<T: KeywordLevelProvider> KeywordLevelProvider for ExporterConfig<T> {
         self.kwl.get_span_level()
     }
 
+    #[inline(always)]
+    fn get_span_level_for_kind(&self, kind: &SpanKind) -> u8 {
+        self.kwl.get_span_level_for_kind(kind)
+    }
+
     #[inline(always)]
     fn get_event_level(&self) -> u8 {
         self.kwl.get_event_level()
@@ -143,12 +456,359 @@ impl<T: KeywordLevelProvider> ExporterConfig<T> {
     pub(crate) fn get_export_span_events(&self) -> bool {
         self.etw_activities
     }
+
+    #[inline(always)]
+    pub(crate) fn get_export_event_summary(&self) -> bool {
+        self.event_summary
+    }
+
+    #[inline(always)]
+    pub(crate) fn get_event_split_size(&self) -> Option<usize> {
+        self.event_split
+    }
+
+    #[inline(always)]
+    pub(crate) fn get_export_count_fields(&self) -> bool {
+        self.count_fields
+    }
+
+    #[inline(always)]
+    pub(crate) fn get_export_arrays_as_json(&self) -> bool {
+        self.arrays_as_json
+    }
+
+    #[inline(always)]
+    pub(crate) fn get_export_dropped_counts(&self) -> bool {
+        self.dropped_counts
+    }
+
+    #[inline(always)]
+    pub(crate) fn get_utf16_strings(&self) -> bool {
+        self.utf16_strings
+    }
+
+    #[inline(always)]
+    pub(crate) fn get_json_string_suffix(&self) -> Option<&Arc<str>> {
+        self.json_string_suffix.as_ref()
+    }
+
+    #[inline(always)]
+    pub(crate) fn get_etw_channel(&self) -> Option<u8> {
+        self.etw_channel
+    }
+
+    #[inline(always)]
+    pub(crate) fn get_per_event_keywords(&self) -> bool {
+        self.per_event_keywords
+    }
+
+    #[inline(always)]
+    pub(crate) fn get_export_startup_event(&self) -> bool {
+        self.startup_event
+    }
+
+    #[inline(always)]
+    pub(crate) fn get_lifecycle_events(&self) -> bool {
+        self.lifecycle_events
+    }
+
+    #[inline(always)]
+    pub(crate) fn get_app_extension(&self) -> Option<&AppExtension> {
+        self.app_extension.as_ref()
+    }
+
+    #[inline(always)]
+    pub(crate) fn get_single_span_event(&self) -> bool {
+        self.single_span_event
+    }
+
+    #[inline(always)]
+    pub(crate) fn get_status_level_mapping(&self) -> bool {
+        self.status_level_mapping
+    }
+
+    #[inline(always)]
+    pub(crate) fn get_thread_activity_scope(&self) -> bool {
+        self.thread_activity_scope
+    }
+
+    #[inline(always)]
+    pub(crate) fn get_unit_annotations(&self) -> bool {
+        self.unit_annotations
+    }
+
+    #[inline(always)]
+    pub(crate) fn get_trace_scoped_activities(&self) -> bool {
+        self.trace_scoped_activities
+    }
+
+    #[inline(always)]
+    pub(crate) fn get_semantic_convention_promotion(&self) -> bool {
+        self.semantic_convention_promotion
+    }
+
+    #[inline(always)]
+    pub(crate) fn get_float_sanitization(&self) -> bool {
+        self.float_sanitization
+    }
+
+    #[inline(always)]
+    pub(crate) fn get_traceparent_field(&self) -> bool {
+        self.traceparent_field
+    }
+
+    #[inline(always)]
+    pub(crate) fn get_remote_flag(&self) -> bool {
+        self.remote_flag
+    }
+
+    #[inline(always)]
+    pub(crate) fn get_float_rounding(&self) -> Option<u8> {
+        self.float_rounding
+    }
+
+    #[inline(always)]
+    pub(crate) fn get_inline_links(&self) -> bool {
+        self.inline_links
+    }
+
+    #[inline(always)]
+    pub(crate) fn get_thread_name_field(&self) -> bool {
+        self.thread_name_field
+    }
+
+    #[inline(always)]
+    pub(crate) fn get_attribute_prefix(&self) -> Option<&Arc<str>> {
+        self.attribute_prefix.as_ref()
+    }
+
+    #[inline(always)]
+    pub(crate) fn get_max_events_emitted(&self) -> Option<usize> {
+        self.max_events_emitted
+    }
+
+    #[inline(always)]
+    pub(crate) fn get_include_status_on_start(&self) -> bool {
+        self.include_status_on_start
+    }
+
+    #[inline(always)]
+    pub(crate) fn get_event_dedup(&self) -> bool {
+        self.event_dedup
+    }
+
+    #[inline(always)]
+    pub(crate) fn get_time_format(&self) -> TimeFormat {
+        self.time_format
+    }
+
+    #[inline(always)]
+    pub(crate) fn get_duration_filetime(&self) -> bool {
+        self.duration_filetime
+    }
+
+    #[inline(always)]
+    pub(crate) fn get_export_as_msgpack(&self) -> bool {
+        self.msgpack
+    }
+
+    #[inline(always)]
+    pub(crate) fn get_root_activity_from_context(&self) -> bool {
+        self.root_activity_from_context
+    }
+
+    #[inline(always)]
+    pub(crate) fn get_sdk_extension(&self) -> bool {
+        self.sdk_extension
+    }
+
+    #[inline(always)]
+    pub(crate) fn get_event_tap(&self) -> Option<&EventTap> {
+        self.event_tap.as_ref()
+    }
+
+    #[inline(always)]
+    pub(crate) fn get_self_description(&self) -> bool {
+        self.self_description
+    }
+
+    #[inline(always)]
+    pub(crate) fn get_attribute_filter(&self) -> Option<&Arc<AttributeFilter>> {
+        self.attribute_filter.as_ref()
+    }
+
+    #[inline(always)]
+    pub(crate) fn get_value_redaction(&self) -> Option<&ValueRedaction> {
+        self.value_redaction.as_ref()
+    }
+
+    #[inline(always)]
+    pub(crate) fn get_child_tracking(&self) -> bool {
+        self.child_tracking
+    }
+
+    #[inline(always)]
+    pub(crate) fn get_compact_common_schema(&self) -> bool {
+        self.compact_common_schema
+    }
+
+    #[inline(always)]
+    pub(crate) fn get_preserve_ok_description(&self) -> bool {
+        self.preserve_ok_description
+    }
+
+    #[inline(always)]
+    pub(crate) fn get_event_offsets(&self) -> bool {
+        self.event_offsets
+    }
+
+    #[inline(always)]
+    pub(crate) fn get_event_tags(&self) -> u32 {
+        self.event_tags
+    }
+
+    #[inline(always)]
+    pub(crate) fn get_field_tags(&self) -> u32 {
+        self.field_tags
+    }
+
+    #[inline(always)]
+    pub(crate) fn get_max_array_length(&self) -> Option<usize> {
+        self.max_array_length
+    }
+
+    #[inline(always)]
+    pub(crate) fn get_numeric_string_coercion(&self) -> bool {
+        self.numeric_string_coercion
+    }
+
+    #[inline(always)]
+    pub(crate) fn get_kind_on_start_only(&self) -> bool {
+        self.kind_on_start_only
+    }
+
+    #[inline(always)]
+    pub(crate) fn get_activity_only_correlation(&self) -> bool {
+        self.activity_only_correlation
+    }
+
+    #[inline(always)]
+    pub(crate) fn get_clock(&self) -> Option<&Arc<dyn Clock>> {
+        self.clock.as_ref()
+    }
+
+    #[inline(always)]
+    pub(crate) fn get_component_field(&self) -> bool {
+        self.component_field
+    }
+
+    #[inline(always)]
+    pub(crate) fn get_attribute_count_field(&self) -> bool {
+        self.attribute_count_field
+    }
+
+    #[inline(always)]
+    pub(crate) fn get_string_outtype_map(&self) -> Option<&StringOutTypeMap> {
+        self.string_outtype_map.as_ref()
+    }
+
+    #[inline(always)]
+    pub(crate) fn get_service_instance_id(&self) -> Option<&Arc<str>> {
+        self.service_instance_id.as_ref()
+    }
+
+    #[inline(always)]
+    pub(crate) fn get_attributes_first(&self) -> bool {
+        self.attributes_first
+    }
+
+    #[inline(always)]
+    pub(crate) fn get_common_schema_trace_flags(&self) -> bool {
+        self.common_schema_trace_flags
+    }
+
+    #[inline(always)]
+    pub(crate) fn get_suppressed_spans(&self) -> &Arc<AtomicU64> {
+        &self.suppressed_spans
+    }
+
+    /// Count a span that wasn't exported because no listener enabled its events.
+    pub(crate) fn add_suppressed_span(&self) {
+        self.suppressed_spans.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Take the number of spans suppressed since the last `SuppressedSpans` event,
+    /// if that event is enabled and any spans were suppressed.
+    pub(crate) fn take_suppressed_spans(&self) -> Option<u64> {
+        if !self.suppressed_spans_event {
+            return None;
+        }
+
+        Some(self.suppressed_spans.swap(0, Ordering::Relaxed)).filter(|count| *count != 0)
+    }
+
+    #[inline(always)]
+    pub(crate) fn get_grouped_event_attributes(&self) -> bool {
+        self.grouped_event_attributes
+    }
+
+    #[inline(always)]
+    pub(crate) fn get_link_attr_count(&self) -> bool {
+        self.link_attr_count
+    }
+
+    #[inline(always)]
+    pub(crate) fn get_compact_event_timestamps(&self) -> bool {
+        self.compact_event_timestamps
+    }
+
+    #[inline(always)]
+    pub(crate) fn get_default_tracer_name(&self) -> Option<&Arc<str>> {
+        self.default_tracer_name.as_ref()
+    }
+
+    #[inline(always)]
+    pub(crate) fn get_inline_events(&self) -> bool {
+        self.inline_events
+    }
+
+    #[inline(always)]
+    pub(crate) fn should_export(&self, span_data: &SpanData) -> bool {
+        self.export_filter
+            .as_ref()
+            .map_or(true, |filter| filter(span_data))
+    }
+
+    /// The entry span keyword for a span that is a root Server span or has a remote parent,
+    /// such as the span of an incoming request, or else 0.
+    pub(crate) fn get_entry_span_keyword(
+        &self,
+        span_data: &SpanData,
+        is_remote_parent: bool,
+    ) -> u64 {
+        let is_root_server =
+            span_data.span_kind == SpanKind::Server && span_data.parent_span_id == SpanId::INVALID;
+        if is_remote_parent || is_root_server {
+            self.entry_span_keyword
+        } else {
+            0
+        }
+    }
 }
 
 #[doc(hidden)]
 pub trait EventExporter {
     fn enabled(&self, level: u8, keyword: u64) -> bool;
 
+    // Whether a listener has enabled the level and keywords of span events
+    fn spans_enabled(&self) -> bool;
+
+    // Called when the provider is registered, to log the startup event if it is enabled
+    fn log_provider_started(&self) -> opentelemetry_sdk::export::trace::ExportResult;
+
+    // Called by the bounded batch queue to log how many spans it dropped
+    fn log_spans_dropped(&self, count: u64) -> opentelemetry_sdk::export::trace::ExportResult;
+
     // Called by the real-time exporter when a span is started
     fn log_span_start<S>(&self, span: &S) -> opentelemetry_sdk::export::trace::ExportResult
     where
@@ -190,3 +850,332 @@ pub enum EtwExporterAsyncRuntime {
     #[cfg_attr(docsrs, doc(cfg(feature = "rt-async-std")))]
     AsyncStd,
 }
+
+/// The resolution of the time fields of Linux user_events events, since the Unix epoch.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TimeFormat {
+    /// Seconds, logged with `FieldFormat::Time`.
+    #[default]
+    Seconds,
+    /// Milliseconds, logged as an unsigned integer.
+    Milliseconds,
+    /// Nanoseconds, logged as an unsigned integer.
+    Nanoseconds,
+}
+
+/// The OutType that string attributes are logged with when their key ends in a suffix
+/// passed to [`ExporterBuilder::with_string_outtype_map`](crate::spans::ExporterBuilder::with_string_outtype_map).
+///
+/// TraceLogging has no OutType for URIs or IP addresses, so there is no variant for
+/// keys such as `.uri` or `.ipv4`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StringOutType {
+    /// `OutType::Xml`, or `FieldFormat::StringXml` for Linux user_events.
+    Xml,
+    /// `OutType::Json`, or `FieldFormat::StringJson` for Linux user_events.
+    Json,
+}
+
+#[cfg(test)]
+impl Default for ExporterConfig<DefaultKeywordLevelProvider> {
+    fn default() -> Self {
+        ExporterConfig::with_kwl(DefaultKeywordLevelProvider)
+    }
+}
+
+#[cfg(test)]
+impl<C: KeywordLevelProvider> ExporterConfig<C> {
+    /// The default config, with a custom keyword and level provider.
+    pub(crate) fn with_kwl(kwl: C) -> Self {
+        ExporterConfig {
+            kwl,
+            json: false,
+            common_schema: false,
+            etw_activities: true,
+            event_summary: false,
+            event_split: None,
+            count_fields: false,
+            arrays_as_json: false,
+            dropped_counts: false,
+            utf16_strings: false,
+            json_string_suffix: None,
+            export_filter: None,
+            etw_channel: None,
+            per_event_keywords: false,
+            startup_event: false,
+            lifecycle_events: false,
+            app_extension: None,
+            single_span_event: false,
+            status_level_mapping: false,
+            thread_activity_scope: false,
+            unit_annotations: false,
+            trace_scoped_activities: false,
+            semantic_convention_promotion: false,
+            float_sanitization: false,
+            traceparent_field: false,
+            remote_flag: false,
+            float_rounding: None,
+            inline_links: false,
+            thread_name_field: false,
+            attribute_prefix: None,
+            max_events_emitted: None,
+            include_status_on_start: false,
+            event_dedup: false,
+            time_format: TimeFormat::Seconds,
+            duration_filetime: false,
+            msgpack: false,
+            root_activity_from_context: false,
+            sdk_extension: false,
+            event_tap: None,
+            self_description: false,
+            attribute_filter: None,
+            value_redaction: None,
+            child_tracking: false,
+            compact_common_schema: false,
+            preserve_ok_description: false,
+            event_offsets: false,
+            event_tags: 0,
+            field_tags: 0,
+            max_array_length: None,
+            numeric_string_coercion: false,
+            kind_on_start_only: false,
+            activity_only_correlation: false,
+            clock: None,
+            component_field: false,
+            attribute_count_field: false,
+            string_outtype_map: None,
+            service_instance_id: None,
+            attributes_first: false,
+            common_schema_trace_flags: false,
+            suppressed_spans: Default::default(),
+            suppressed_spans_event: false,
+            grouped_event_attributes: false,
+            link_attr_count: false,
+            compact_event_timestamps: false,
+            default_tracer_name: None,
+            entry_span_keyword: 0,
+            inline_events: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct SecondaryKeywordLevelProvider;
+
+    impl KeywordLevelProvider for SecondaryKeywordLevelProvider {
+        fn get_span_keywords(&self) -> u64 {
+            0x1
+        }
+        fn get_event_keywords(&self) -> u64 {
+            0x10
+        }
+        fn get_links_keywords(&self) -> u64 {
+            0x100
+        }
+        fn get_span_level(&self) -> u8 {
+            4
+        }
+        fn get_event_level(&self) -> u8 {
+            5
+        }
+        fn get_links_level(&self) -> u8 {
+            5
+        }
+        fn get_span_secondary_keywords(&self) -> u64 {
+            0x8000
+        }
+    }
+
+    #[test]
+    fn simple_keyword_levels() {
+        let kwl = SimpleKeywordLevels {
+            span_keywords: Some(0x4),
+            event_level: Some(2),
+            links_secondary_keywords: Some(0x1000),
+            ..Default::default()
+        };
+
+        assert_eq!(kwl.get_span_keywords(), 0x4);
+        assert_eq!(kwl.get_event_level(), 2);
+        assert_eq!(kwl.get_links_secondary_keywords(), 0x1000);
+
+        assert_eq!(kwl.get_event_keywords(), 0x10);
+        assert_eq!(kwl.get_links_keywords(), 0x100);
+        assert_eq!(kwl.get_span_level(), 4);
+        assert_eq!(kwl.get_links_level(), 5);
+        assert_eq!(kwl.get_span_secondary_keywords(), 0);
+        assert_eq!(kwl.get_event_secondary_keywords(), 0);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn export_filter() {
+        use crate::spans::realtime_tracer::RealtimeTracerProvider;
+        use crate::spans::ProviderGroup;
+        use crate::user_events::{self, UserEventsExporter};
+        use opentelemetry_api::trace::{Span, Tracer, TracerProvider};
+        use std::sync::Mutex;
+
+        let filter: ExportFilter = Arc::new(|span_data: &SpanData| span_data.name != "internal");
+        let tapped = Arc::new(Mutex::new(Vec::new()));
+        let tap_events = tapped.clone();
+        let tap: EventTap =
+            Arc::new(move |meta: &EventMeta| tap_events.lock().unwrap().push(meta.name.to_owned()));
+        let config = || ExporterConfig {
+            export_filter: Some(filter.clone()),
+            event_tap: Some(tap.clone()),
+            ..Default::default()
+        };
+
+        let provider = RealtimeTracerProvider::<_, UserEventsExporter<_>>::new(
+            "export_filter_test",
+            &tracelogging_dynamic::Guid::zero(),
+            &ProviderGroup::Unset,
+            opentelemetry_sdk::trace::config(),
+            false,
+            config(),
+        );
+
+        let tracer = provider.tracer("test");
+        let mut internal_span = tracer.start("internal");
+        let mut external_span = tracer.start("external");
+
+        assert!(!config().should_export(internal_span.get_span_data()));
+        assert!(config().should_export(external_span.get_span_data()));
+
+        let internal_data = internal_span.get_span_data().clone();
+        let external_data = external_span.get_span_data().clone();
+        internal_span.end();
+        external_span.end();
+
+        // The realtime exporter only writes the start and end events of the external span
+        assert_eq!(*tapped.lock().unwrap(), ["external", "external"]);
+        tapped.lock().unwrap().clear();
+
+        let mut ue_provider = eventheader_dynamic::Provider::new(
+            "export_filter_test",
+            &eventheader_dynamic::Provider::new_options(),
+        );
+        let registered = user_events::register_eventsets(&mut ue_provider, &config());
+        let exporter = UserEventsExporter::new(ue_provider, registered, config());
+
+        assert!(exporter.log_span_data(&internal_data).is_ok());
+        assert!(tapped.lock().unwrap().is_empty());
+
+        assert!(exporter.log_span_data(&external_data).is_ok());
+        assert_eq!(*tapped.lock().unwrap(), ["external", "external"]);
+    }
+
+    #[test]
+    fn entry_span_keyword() {
+        use crate::spans::realtime_tracer::RealtimeTracerProvider;
+        use crate::spans::ProviderGroup;
+        use opentelemetry_api::trace::{
+            Span, SpanContext, TraceContextExt, TraceFlags, TraceId, TraceState, Tracer,
+            TracerProvider,
+        };
+        use opentelemetry_api::Context;
+
+        let provider = RealtimeTracerProvider::new(
+            "my_provider_name",
+            &tracelogging_dynamic::Guid::from_name("my_provider_name"),
+            &ProviderGroup::Unset,
+            opentelemetry_sdk::trace::config(),
+            false,
+            ExporterConfig::<DefaultKeywordLevelProvider>::default(),
+        );
+        let config = ExporterConfig {
+            entry_span_keyword: 0x8000,
+            ..Default::default()
+        };
+        let entry_span_keyword = |span: &dyn EtwSpan| {
+            config.get_entry_span_keyword(span.get_span_data(), span.is_remote_parent())
+        };
+
+        let tracer = provider.tracer("test");
+        let mut server_span = tracer
+            .span_builder("request")
+            .with_kind(SpanKind::Server)
+            .start(&tracer);
+        assert_eq!(entry_span_keyword(&server_span), 0x8000);
+
+        let root_cx = Context::current_with_span(tracer.start("internal_root"));
+        let mut internal_child = tracer
+            .span_builder("child")
+            .with_kind(SpanKind::Internal)
+            .start_with_context(&tracer, &root_cx);
+        assert_eq!(entry_span_keyword(&internal_child), 0);
+
+        // A child of a remote span is an entry span of any kind
+        let remote_cx = Context::new().with_remote_span_context(SpanContext::new(
+            TraceId::from_bytes([1; 16]),
+            SpanId::from_bytes([1; 8]),
+            TraceFlags::SAMPLED,
+            true,
+            TraceState::default(),
+        ));
+        let mut remote_child = tracer.start_with_context("remote_child", &remote_cx);
+        assert_eq!(entry_span_keyword(&remote_child), 0x8000);
+
+        // No keyword is added unless it is configured
+        let config = ExporterConfig::<DefaultKeywordLevelProvider>::default();
+        assert_eq!(
+            config.get_entry_span_keyword(server_span.get_span_data(), false),
+            0
+        );
+
+        server_span.end();
+        internal_child.end();
+        remote_child.end();
+        root_cx.span().end();
+    }
+
+    #[test]
+    fn secondary_keywords() {
+        let config = ExporterConfig::with_kwl(SecondaryKeywordLevelProvider);
+
+        assert_eq!(config.get_span_keywords(), 0x8001);
+        assert_eq!(config.get_event_keywords(), 0x10);
+        assert_eq!(config.get_links_keywords(), 0x100);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn secondary_keywords_written() {
+        use crate::spans::realtime_tracer::RealtimeTracerProvider;
+        use crate::spans::ProviderGroup;
+        use crate::user_events::UserEventsExporter;
+        use opentelemetry_api::trace::{Tracer, TracerProvider};
+        use std::sync::Mutex;
+
+        let tapped = Arc::new(Mutex::new(Vec::new()));
+        let tap_events = tapped.clone();
+        let config = ExporterConfig {
+            event_tap: Some(Arc::new(move |meta: &EventMeta| {
+                tap_events
+                    .lock()
+                    .unwrap()
+                    .push((meta.name.to_owned(), meta.keyword))
+            })),
+            ..ExporterConfig::with_kwl(SecondaryKeywordLevelProvider)
+        };
+
+        let provider = RealtimeTracerProvider::<_, UserEventsExporter<_>>::new(
+            "secondary_keywords_test",
+            &tracelogging_dynamic::Guid::zero(),
+            &ProviderGroup::Unset,
+            opentelemetry_sdk::trace::config(),
+            false,
+            config,
+        );
+        provider.tracer("test").in_span("span", |_| {});
+
+        // The span start and end events carry the secondary keyword, so a session that
+        // requires both bits receives them.
+        let expected = ("span".to_owned(), 0x8001);
+        assert_eq!(*tapped.lock().unwrap(), [expected.clone(), expected]);
+    }
+}
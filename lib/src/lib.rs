@@ -119,6 +119,8 @@
 //!   - Rust applications can emit a JSON string containing all the attributes by enabling the optional feature
 //!   `json` on the crate and calling [`span_exporter::ExporterBuilder::with_json_payload`] when building
 //!   the exporter. MsgPack encoding is not supported.
+//!   - Rust applications can instead emit the attributes as a binary MsgPack map by enabling the optional feature
+//!     `msgpack` on the crate and calling [`span_exporter::ExporterBuilder::with_msgpack_payload`].
 //! - The C++ exporter supports logs from the the OpenTelemetry Logging API proposal.
 //! This is not (yet) supported by OpenTelemetry-Rust.
 //! - The C++ exporter does not (currently) use opcodes or levels on its ETW events.
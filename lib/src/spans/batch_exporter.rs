@@ -2,45 +2,80 @@ use crate::spans::builder::ProviderGroup;
 #[allow(unused_imports)]
 use crate::etw;
 use crate::exporter_traits::*;
+use crate::common::BatchExportError;
 #[allow(unused_imports)]
 use crate::user_events;
 use futures_util::future::BoxFuture;
 use opentelemetry::sdk::export::trace::{ExportResult, SpanData, SpanExporter};
+use opentelemetry::trace::TraceError;
+#[cfg(any(
+    feature = "rt-tokio",
+    feature = "rt-tokio-current-thread",
+    feature = "rt-async-std"
+))]
+use opentelemetry::trace::TraceResult;
+#[cfg(any(
+    feature = "rt-tokio",
+    feature = "rt-tokio-current-thread",
+    feature = "rt-async-std"
+))]
+use opentelemetry::Context;
+#[cfg(any(
+    feature = "rt-tokio",
+    feature = "rt-tokio-current-thread",
+    feature = "rt-async-std"
+))]
+use opentelemetry_sdk::trace::{BatchSpanProcessor, Span, SpanProcessor, TraceRuntime};
 use std::fmt::Debug;
+#[cfg(any(
+    feature = "rt-tokio",
+    feature = "rt-tokio-current-thread",
+    feature = "rt-async-std"
+))]
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
 
 pub(crate) struct BatchExporter<E: EventExporter + Send + Sync> {
-    ebw: E,
+    // Shared with the checks returned by `spans_enabled`.
+    ebw: Arc<E>,
+}
+
+impl<E: EventExporter + Send + Sync + 'static> BatchExporter<E> {
+    pub(crate) fn spans_enabled(&self) -> SpansEnabled {
+        let ebw = Arc::downgrade(&self.ebw);
+        Arc::new(move || ebw.upgrade().map_or(false, |ebw| ebw.spans_enabled()))
+    }
 }
 
 #[cfg(all(target_os = "windows"))]
 impl<C: KeywordLevelProvider> BatchExporter<etw::EtwEventExporter<C>> {
     pub(crate) fn new(
         provider_name: &str,
-        provider_group: ProviderGroup,
+        provider_id: &tracelogging_dynamic::Guid,
+        provider_group: &ProviderGroup,
         use_byte_for_bools: bool,
         exporter_config: ExporterConfig<C>,
     ) -> Self {
         let mut options = tracelogging_dynamic::Provider::options();
         if let ProviderGroup::Windows(guid) = provider_group {
-            options = *options.group_id(&guid);
+            options = *options.group_id(guid);
         }
 
-        let provider = Arc::pin(tracelogging_dynamic::Provider::new(provider_name, &options));
-        unsafe {
-            provider.as_ref().register();
-        }
-        BatchExporter {
-            ebw: etw::EtwEventExporter::new(
-                provider,
-                exporter_config,
-                if use_byte_for_bools {
-                    tracelogging::InType::U8
-                } else {
-                    tracelogging::InType::Bool32
-                },
-            ),
-        }
+        let (provider, lifecycle) =
+            etw::register_provider(provider_name, provider_id, &mut options, &exporter_config);
+        let ebw = etw::EtwEventExporter::new(
+            provider,
+            lifecycle,
+            exporter_config,
+            if use_byte_for_bools {
+                tracelogging::InType::U8
+            } else {
+                tracelogging::InType::Bool32
+            },
+        );
+        let _ = ebw.log_provider_started();
+
+        BatchExporter { ebw: Arc::new(ebw) }
     }
 }
 
@@ -48,20 +83,22 @@ impl<C: KeywordLevelProvider> BatchExporter<etw::EtwEventExporter<C>> {
 impl<C: KeywordLevelProvider> BatchExporter<user_events::UserEventsExporter<C>> {
     pub(crate) fn new(
         provider_name: &str,
-        provider_group: ProviderGroup,
+        _provider_id: &tracelogging_dynamic::Guid,
+        provider_group: &ProviderGroup,
         _use_byte_for_bools: bool,
         exporter_config: ExporterConfig<C>,
     ) -> Self {
         let mut options = eventheader_dynamic::Provider::new_options();
-        if let ProviderGroup::Linux(ref name) = provider_group {
-            options = *options.group_name(&name);
+        if let ProviderGroup::Linux(name) = provider_group {
+            options = *options.group_name(name);
         }
         let mut provider = eventheader_dynamic::Provider::new(provider_name, &options);
-        user_events::register_eventsets(&mut provider, &exporter_config);
+        let registered = user_events::register_eventsets(&mut provider, &exporter_config);
 
-        BatchExporter {
-            ebw: user_events::UserEventsExporter::new(Arc::new(provider), exporter_config),
-        }
+        let ebw = user_events::UserEventsExporter::new(provider, registered, exporter_config);
+        let _ = ebw.log_provider_started();
+
+        BatchExporter { ebw: Arc::new(ebw) }
     }
 }
 
@@ -73,29 +110,417 @@ impl<E: EventExporter + Send + Sync> Debug for BatchExporter<E> {
 
 impl<E: EventExporter + Send + Sync> SpanExporter for BatchExporter<E> {
     fn export(&mut self, batch: Vec<SpanData>) -> BoxFuture<'static, ExportResult> {
+        let span_count = batch.len();
+        let mut errors = Vec::new();
+
+        // A span that fails to export doesn't keep the rest of the batch from being exported
         for span in batch {
-            let _ = self.ebw.log_span_data(&span);
+            if let Err(err) = self.ebw.log_span_data(&span) {
+                errors.push(err);
+            }
+        }
+
+        let result = if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(TraceError::ExportFailed(Box::new(BatchExportError {
+                span_count,
+                errors,
+            })))
+        };
+
+        Box::pin(std::future::ready(result))
+    }
+}
+
+/// A span exporter that can log a marker event when spans are dropped before being exported.
+#[cfg(any(
+    feature = "rt-tokio",
+    feature = "rt-tokio-current-thread",
+    feature = "rt-async-std"
+))]
+pub(crate) trait SpansDroppedLogger {
+    fn log_spans_dropped(&mut self, count: u64);
+}
+
+#[cfg(any(
+    feature = "rt-tokio",
+    feature = "rt-tokio-current-thread",
+    feature = "rt-async-std"
+))]
+impl<E: EventExporter + Send + Sync> SpansDroppedLogger for BatchExporter<E> {
+    fn log_spans_dropped(&mut self, count: u64) {
+        let _ = self.ebw.log_spans_dropped(count);
+    }
+}
+
+/// The spans that a [`BoundedSpanProcessor`] let into the SDK batch queue and that haven't
+/// reached the exporter yet, and the spans it dropped since the last `SpansDropped` event.
+#[cfg(any(
+    feature = "rt-tokio",
+    feature = "rt-tokio-current-thread",
+    feature = "rt-async-std"
+))]
+#[derive(Debug, Default)]
+pub(crate) struct QueueCounts {
+    queued: AtomicUsize,
+    dropped: AtomicU64,
+}
+
+/// Bounds the number of spans waiting in the SDK batch span processor's queue.
+/// If the async runtime stalls, spans that end once `capacity` spans are waiting are dropped
+/// instead of being queued, and counted for the next `SpansDropped` event.
+#[cfg(any(
+    feature = "rt-tokio",
+    feature = "rt-tokio-current-thread",
+    feature = "rt-async-std"
+))]
+#[derive(Debug)]
+pub(crate) struct BoundedSpanProcessor<P: SpanProcessor> {
+    inner: P,
+    capacity: usize,
+    counts: Arc<QueueCounts>,
+}
+
+#[cfg(any(
+    feature = "rt-tokio",
+    feature = "rt-tokio-current-thread",
+    feature = "rt-async-std"
+))]
+impl<P: SpanProcessor> SpanProcessor for BoundedSpanProcessor<P> {
+    fn on_start(&self, span: &mut Span, cx: &Context) {
+        self.inner.on_start(span, cx);
+    }
+
+    fn on_end(&self, span: SpanData) {
+        // The batch span processor ignores spans that aren't sampled, so they aren't counted
+        if !span.span_context.is_sampled() {
+            return;
         }
 
-        Box::pin(std::future::ready(Ok(())))
+        let capacity = self.capacity;
+        if self
+            .counts
+            .queued
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |queued| {
+                (queued < capacity).then_some(queued + 1)
+            })
+            .is_ok()
+        {
+            self.inner.on_end(span);
+        } else {
+            self.counts.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn force_flush(&self) -> TraceResult<()> {
+        self.inner.force_flush()
+    }
+
+    fn shutdown(&mut self) -> TraceResult<()> {
+        self.inner.shutdown()
+    }
+}
+
+/// Wraps the exporter of a [`BoundedSpanProcessor`]'s batch span processor, to count the spans
+/// that leave the SDK queue and log a `SpansDropped` event for the spans that were dropped.
+#[cfg(any(
+    feature = "rt-tokio",
+    feature = "rt-tokio-current-thread",
+    feature = "rt-async-std"
+))]
+#[derive(Debug)]
+struct QueueCountingExporter<X: SpanExporter + SpansDroppedLogger> {
+    inner: X,
+    counts: Arc<QueueCounts>,
+}
+
+#[cfg(any(
+    feature = "rt-tokio",
+    feature = "rt-tokio-current-thread",
+    feature = "rt-async-std"
+))]
+impl<X: SpanExporter + SpansDroppedLogger> QueueCountingExporter<X> {
+    fn log_spans_dropped(&mut self) {
+        let dropped = self.counts.dropped.swap(0, Ordering::Relaxed);
+        if dropped != 0 {
+            self.inner.log_spans_dropped(dropped);
+        }
+    }
+}
+
+#[cfg(any(
+    feature = "rt-tokio",
+    feature = "rt-tokio-current-thread",
+    feature = "rt-async-std"
+))]
+impl<X: SpanExporter + SpansDroppedLogger> SpanExporter for QueueCountingExporter<X> {
+    fn export(&mut self, batch: Vec<SpanData>) -> BoxFuture<'static, ExportResult> {
+        self.counts.queued.fetch_sub(batch.len(), Ordering::AcqRel);
+        self.log_spans_dropped();
+        self.inner.export(batch)
+    }
+
+    fn shutdown(&mut self) {
+        // The batch span processor exports the queued spans before shutting down the exporter
+        self.log_spans_dropped();
+        self.inner.shutdown();
+    }
+
+    fn force_flush(&mut self) -> BoxFuture<'static, ExportResult> {
+        self.log_spans_dropped();
+        self.inner.force_flush()
+    }
+}
+
+/// Adds a batch span processor for `exporter` to `provider_builder`, with a bounded queue
+/// if `bounded_queue_size` is set.
+#[cfg(any(
+    feature = "rt-tokio",
+    feature = "rt-tokio-current-thread",
+    feature = "rt-async-std"
+))]
+pub(crate) fn with_batch_exporter<X, R>(
+    provider_builder: opentelemetry_sdk::trace::Builder,
+    exporter: X,
+    runtime: R,
+    bounded_queue_size: Option<usize>,
+) -> opentelemetry_sdk::trace::Builder
+where
+    X: SpanExporter + SpansDroppedLogger + 'static,
+    R: TraceRuntime,
+{
+    match bounded_queue_size {
+        Some(capacity) => provider_builder
+            .with_span_processor(bounded_batch_processor(exporter, runtime, capacity)),
+        None => provider_builder.with_batch_exporter(exporter, runtime),
+    }
+}
+
+/// A batch span processor for `exporter`, that lets at most `capacity` spans wait to be exported.
+#[cfg(any(
+    feature = "rt-tokio",
+    feature = "rt-tokio-current-thread",
+    feature = "rt-async-std"
+))]
+pub(crate) fn bounded_batch_processor<X, R>(
+    exporter: X,
+    runtime: R,
+    capacity: usize,
+) -> BoundedSpanProcessor<BatchSpanProcessor<R>>
+where
+    X: SpanExporter + SpansDroppedLogger + 'static,
+    R: TraceRuntime,
+{
+    let counts = Arc::new(QueueCounts::default());
+    let exporter = QueueCountingExporter {
+        inner: exporter,
+        counts: counts.clone(),
+    };
+
+    BoundedSpanProcessor {
+        // The SDK queue has room for every span that's let through, plus a flush or shutdown message
+        inner: BatchSpanProcessor::builder(exporter, runtime)
+            .with_max_queue_size(capacity + 1)
+            .with_max_export_batch_size(capacity)
+            .build(),
+        capacity,
+        counts,
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::common::EtwSpan;
+    use futures_util::FutureExt;
+    use opentelemetry::trace::{SpanContext, SpanId, SpanKind, Status};
+    use opentelemetry::InstrumentationLibrary;
+    use opentelemetry_sdk::trace::{EvictedHashMap, EvictedQueue};
+    use opentelemetry_sdk::Resource;
+    use std::borrow::Cow;
+    use std::sync::Mutex;
+    use std::time::SystemTime;
+
+    // Records how many spans reach it, and how many were reported as dropped.
+    #[cfg(feature = "rt-tokio")]
+    #[derive(Debug, Default, Clone)]
+    struct RecordingExporter {
+        exported: Arc<AtomicUsize>,
+        dropped: Arc<AtomicU64>,
+    }
+
+    #[cfg(feature = "rt-tokio")]
+    impl SpanExporter for RecordingExporter {
+        fn export(&mut self, batch: Vec<SpanData>) -> BoxFuture<'static, ExportResult> {
+            self.exported.fetch_add(batch.len(), Ordering::Relaxed);
+            Box::pin(std::future::ready(Ok(())))
+        }
+    }
+
+    #[cfg(feature = "rt-tokio")]
+    impl SpansDroppedLogger for RecordingExporter {
+        fn log_spans_dropped(&mut self, count: u64) {
+            self.dropped.fetch_add(count, Ordering::Relaxed);
+        }
+    }
+
+    // Fails to export spans named "oversized", and records the names of the others.
+    #[derive(Default)]
+    struct FailingExporter {
+        exported: Mutex<Vec<String>>,
+    }
+
+    impl EventExporter for FailingExporter {
+        fn enabled(&self, _level: u8, _keyword: u64) -> bool {
+            true
+        }
+
+        fn spans_enabled(&self) -> bool {
+            true
+        }
+
+        fn log_provider_started(&self) -> ExportResult {
+            Ok(())
+        }
+
+        fn log_spans_dropped(&self, _count: u64) -> ExportResult {
+            Ok(())
+        }
+
+        fn log_span_start<S>(&self, _span: &S) -> ExportResult
+        where
+            S: opentelemetry_api::trace::Span + EtwSpan,
+        {
+            Ok(())
+        }
+
+        fn log_span_end<S>(&self, _span: &S) -> ExportResult
+        where
+            S: opentelemetry_api::trace::Span + EtwSpan,
+        {
+            Ok(())
+        }
+
+        fn log_span_event<S>(&self, _event: opentelemetry::trace::Event, _span: &S) -> ExportResult
+        where
+            S: opentelemetry_api::trace::Span + EtwSpan,
+        {
+            Ok(())
+        }
+
+        fn log_span_data(&self, span_data: &SpanData) -> ExportResult {
+            if span_data.name == "oversized" {
+                return Err(TraceError::from("event too large"));
+            }
+            self.exported
+                .lock()
+                .unwrap()
+                .push(span_data.name.to_string());
+            Ok(())
+        }
+    }
+
+    fn span_data() -> SpanData {
+        SpanData {
+            span_context: SpanContext::empty_context(),
+            parent_span_id: SpanId::INVALID,
+            span_kind: SpanKind::Internal,
+            name: "span".into(),
+            start_time: SystemTime::now(),
+            end_time: SystemTime::now(),
+            attributes: EvictedHashMap::new(0, 0),
+            events: EvictedQueue::new(0),
+            links: EvictedQueue::new(0),
+            status: Status::Unset,
+            resource: Cow::Owned(Resource::empty()),
+            instrumentation_lib: InstrumentationLibrary::new("test", None, None),
+        }
+    }
+
+    #[test]
+    fn failed_spans_dont_drop_batch() {
+        let ebw = Arc::new(FailingExporter::default());
+        let mut exporter = BatchExporter { ebw: ebw.clone() };
+
+        let batch = ["first", "oversized", "second"]
+            .into_iter()
+            .map(|name| SpanData {
+                name: name.into(),
+                ..span_data()
+            })
+            .collect();
+        let result = exporter.export(batch).now_or_never().unwrap();
+
+        assert_eq!(*ebw.exported.lock().unwrap(), vec!["first", "second"]);
+        match result {
+            Err(TraceError::ExportFailed(err)) => {
+                assert_eq!(
+                    err.to_string(),
+                    "1 of 3 spans failed to export, the first with: event too large"
+                );
+            }
+            _ => panic!("expected the failed span to be reported"),
+        }
+    }
+
+    #[cfg(feature = "rt-tokio")]
+    #[test]
+    fn bounded_queue_drops_spans_while_stalled() {
+        use opentelemetry::trace::{TraceFlags, TraceId, TraceState};
+
+        // The batch span processor's worker can't run until the runtime is driven
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap();
+        let exporter = RecordingExporter::default();
+        let mut processor = {
+            let _guard = runtime.enter();
+            bounded_batch_processor(exporter.clone(), opentelemetry_sdk::runtime::Tokio, 8)
+        };
+
+        let span_context = SpanContext::new(
+            TraceId::from_bytes(1u128.to_be_bytes()),
+            SpanId::from_bytes(1u64.to_be_bytes()),
+            TraceFlags::SAMPLED,
+            false,
+            TraceState::default(),
+        );
+        for _ in 0..100 {
+            processor.on_end(SpanData {
+                span_context: span_context.clone(),
+                ..span_data()
+            });
+        }
+        assert_eq!(processor.counts.queued.load(Ordering::Relaxed), 8);
+        assert_eq!(processor.counts.dropped.load(Ordering::Relaxed), 92);
+
+        // Once the runtime runs again, the queued spans are exported and the drops are reported
+        let (stop, stopped) = futures::channel::oneshot::channel::<()>();
+        let worker = std::thread::spawn(move || {
+            let _ = runtime.block_on(stopped);
+        });
+        processor.force_flush().unwrap();
+        assert_eq!(exporter.exported.load(Ordering::Relaxed), 8);
+        assert_eq!(exporter.dropped.load(Ordering::Relaxed), 92);
+        assert_eq!(processor.counts.queued.load(Ordering::Relaxed), 0);
+
+        processor.shutdown().unwrap();
+        drop(stop);
+        worker.join().unwrap();
+    }
 
     #[test]
     fn create_batch_exporter() {
         let _ = BatchExporter::new(
             "my_provider_name",
-            ProviderGroup::Unset,
+            &tracelogging_dynamic::Guid::from_name("my_provider_name"),
+            &ProviderGroup::Unset,
             true,
-            ExporterConfig::<DefaultKeywordLevelProvider> {
-                kwl: DefaultKeywordLevelProvider,
-                json: false,
+            ExporterConfig {
                 common_schema: true,
-                etw_activities: true,
+                ..Default::default()
             },
         );
     }
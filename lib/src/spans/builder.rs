@@ -1,9 +1,16 @@
 use std::borrow::Cow;
+use std::sync::atomic::AtomicU64;
+use std::sync::{Arc, Mutex};
 
-use crate::spans::{batch_exporter::*, realtime_tracer::*};
+use crate::spans::{batch_exporter::*, provider::*, realtime_tracer::*};
 use crate::exporter_traits::*;
+use crate::common::{attributes::AttributeFilter, common_schema::AppExtension, InstallError};
 use opentelemetry::global::GlobalTracerProvider;
-use opentelemetry_api::{global, trace::TracerProvider};
+use opentelemetry::{Key, Value};
+use opentelemetry_api::{
+    global,
+    trace::{TraceError, TracerProvider},
+};
 use tracelogging_dynamic::Guid;
 
 pub(crate) enum ProviderGroup {
@@ -23,6 +30,71 @@ pub struct ExporterBuilder {
     json: bool,
     emit_common_schema_events: bool,
     emit_realtime_events: bool,
+    emit_event_summary: bool,
+    event_split_size: Option<usize>,
+    emit_count_fields: bool,
+    arrays_as_json: bool,
+    emit_dropped_counts: bool,
+    utf16_strings: bool,
+    json_string_suffix: Option<String>,
+    export_filter: Option<ExportFilter>,
+    etw_channel: Option<u8>,
+    per_event_keywords: bool,
+    startup_event: bool,
+    lifecycle_events: bool,
+    app_extension: Option<AppExtension>,
+    single_span_event: bool,
+    status_level_mapping: bool,
+    thread_activity_scope: bool,
+    unit_annotations: bool,
+    trace_scoped_activities: bool,
+    semantic_convention_promotion: bool,
+    float_sanitization: bool,
+    traceparent_field: bool,
+    remote_flag: bool,
+    float_rounding: Option<u8>,
+    inline_links: bool,
+    thread_name_field: bool,
+    attribute_prefix: Option<String>,
+    max_events_emitted: Option<usize>,
+    include_status_on_start: bool,
+    event_dedup: bool,
+    time_format: TimeFormat,
+    duration_filetime: bool,
+    msgpack: bool,
+    root_activity_from_context: bool,
+    sdk_extension: bool,
+    event_tap: Option<EventTap>,
+    self_description: bool,
+    attribute_filter: AttributeFilter,
+    value_redaction: Option<ValueRedaction>,
+    child_tracking: bool,
+    compact_common_schema: bool,
+    preserve_ok_description: bool,
+    event_offsets: bool,
+    event_tags: u32,
+    field_tags: u32,
+    max_array_length: Option<usize>,
+    numeric_string_coercion: bool,
+    kind_on_start_only: bool,
+    activity_only_correlation: bool,
+    clock: Option<Arc<dyn Clock>>,
+    component_field: bool,
+    attribute_count_field: bool,
+    string_outtype_map: Option<StringOutTypeMap>,
+    service_instance_field: bool,
+    attributes_first: bool,
+    common_schema_trace_flags: bool,
+    suppressed_spans: Arc<AtomicU64>,
+    suppressed_spans_event: bool,
+    grouped_event_attributes: bool,
+    link_attr_count: bool,
+    compact_event_timestamps: bool,
+    default_tracer_name: Option<Arc<str>>,
+    entry_span_keyword: u64,
+    inline_events: bool,
+    bounded_queue_size: Option<usize>,
+    env_config: bool,
     runtime: Option<EtwExporterAsyncRuntime>,
     trace_config: Option<opentelemetry_sdk::trace::Config>,
     exporter_config: Option<Box<dyn KeywordLevelProvider>>,
@@ -30,7 +102,9 @@ pub struct ExporterBuilder {
 
 /// Create an exporter builder. After configuring the builder,
 /// call [`ExporterBuilder::install`] to set it as the
-/// [global tracer provider](https://docs.rs/opentelemetry_api/latest/opentelemetry_api/global/index.html).
+/// [global tracer provider](https://docs.rs/opentelemetry_api/latest/opentelemetry_api/global/index.html),
+/// or call [`ExporterBuilder::build_span_exporter`] to get a tracer provider without
+/// modifying global state.
 pub fn new_exporter(name: &str) -> ExporterBuilder {
     ExporterBuilder {
         provider_name: name.to_owned(),
@@ -40,6 +114,71 @@ pub fn new_exporter(name: &str) -> ExporterBuilder {
         json: false,
         emit_common_schema_events: false,
         emit_realtime_events: true,
+        emit_event_summary: false,
+        event_split_size: None,
+        emit_count_fields: false,
+        arrays_as_json: false,
+        emit_dropped_counts: false,
+        utf16_strings: false,
+        json_string_suffix: None,
+        export_filter: None,
+        etw_channel: None,
+        per_event_keywords: false,
+        startup_event: false,
+        lifecycle_events: false,
+        app_extension: None,
+        single_span_event: false,
+        status_level_mapping: false,
+        thread_activity_scope: false,
+        unit_annotations: false,
+        trace_scoped_activities: false,
+        semantic_convention_promotion: false,
+        float_sanitization: false,
+        traceparent_field: false,
+        remote_flag: false,
+        float_rounding: None,
+        inline_links: false,
+        thread_name_field: false,
+        attribute_prefix: None,
+        max_events_emitted: None,
+        include_status_on_start: false,
+        event_dedup: false,
+        time_format: TimeFormat::Seconds,
+        duration_filetime: false,
+        msgpack: false,
+        root_activity_from_context: false,
+        sdk_extension: false,
+        event_tap: None,
+        self_description: false,
+        attribute_filter: AttributeFilter::default(),
+        value_redaction: None,
+        child_tracking: false,
+        compact_common_schema: false,
+        preserve_ok_description: false,
+        event_offsets: false,
+        event_tags: 0,
+        field_tags: 0,
+        max_array_length: None,
+        numeric_string_coercion: false,
+        kind_on_start_only: false,
+        activity_only_correlation: false,
+        clock: None,
+        component_field: false,
+        attribute_count_field: false,
+        string_outtype_map: None,
+        service_instance_field: false,
+        attributes_first: false,
+        common_schema_trace_flags: false,
+        suppressed_spans: Default::default(),
+        suppressed_spans_event: false,
+        grouped_event_attributes: false,
+        link_attr_count: false,
+        compact_event_timestamps: false,
+        default_tracer_name: None,
+        entry_span_keyword: 0,
+        inline_events: false,
+        bounded_queue_size: None,
+        env_config: false,
         runtime: None,
         trace_config: None,
         exporter_config: None,
@@ -77,6 +216,391 @@ impl ExporterBuilder {
         self
     }
 
+    /// Log string attributes and the string fields added by the exporter as UTF-16
+    /// (InType `win:UnicodeString`) instead of UTF-8.
+    /// Recommended only for compatibility with consumers that do not support UTF-8 strings.
+    /// Common Schema events and JSON payloads are always UTF-8.
+    #[cfg(any(target_os = "windows", doc))]
+    pub fn with_utf16_strings(mut self) -> Self {
+        self.utf16_strings = true;
+        self
+    }
+
+    /// Add the value of a span event's integer `etw.keyword` attribute to the keywords
+    /// of that event, so that events from a single span can be filtered separately.
+    /// The configured span event keywords are always included, and the `etw.keyword`
+    /// attribute is not added to the event payload.
+    ///
+    /// Only available on Windows: Linux user_events tracepoints are registered up front
+    /// for each configured level and keyword pair, so there is no tracepoint to write
+    /// an event with extra keywords to.
+    #[cfg(any(target_os = "windows", doc))]
+    pub fn with_per_event_keywords(mut self) -> Self {
+        self.per_event_keywords = true;
+        self
+    }
+
+    /// Log a `ProviderStarted` event with the provider name, exporter version and process id
+    /// when the provider is registered, so consumers can confirm that they are receiving events.
+    /// The event uses the span level and keywords, and is only received by sessions that
+    /// enabled the provider before it was registered.
+    pub fn with_startup_event(mut self) -> Self {
+        self.startup_event = true;
+        self
+    }
+
+    /// Log a `ProviderStopping` event with the provider name and process id from the
+    /// provider's enable callback, when the last session disables the provider.
+    /// ETW only calls the callback once the session has stopped listening, so the event
+    /// is mostly useful to sessions that enable the provider again, and to the event tap.
+    #[cfg(any(target_os = "windows", doc))]
+    pub fn with_lifecycle_events(mut self) -> Self {
+        self.lifecycle_events = true;
+        self
+    }
+
+    /// Log a `SuppressedSpans` event with the number of spans that weren't exported
+    /// because no session had the provider enabled, the next time a span is exported.
+    /// The count is then reset, so each event only covers the spans since the previous one.
+    /// The count is also available from [`ExporterTracerProvider::suppressed_count`]
+    /// and [`EtwTracer::suppressed_count`].
+    pub fn with_suppressed_spans_event(mut self) -> Self {
+        self.suppressed_spans_event = true;
+        self
+    }
+
+    /// Log a `ProviderSchema` event the first time a span is exported while a session
+    /// has the provider enabled, with the names and types of the built-in span event
+    /// fields as a JSON `Fields` field, so decoders can discover them without documentation.
+    /// The event uses the span level and keywords, and is logged at most once per exporter.
+    pub fn with_self_description(mut self) -> Self {
+        self.self_description = true;
+        self
+    }
+
+    /// Add an `ext_app` struct with the given `id` and `ver` fields to PartA of every
+    /// Common Schema event, for pipelines that group events by application and version.
+    /// Has no effect unless Common Schema events are enabled.
+    pub fn with_app_extension(mut self, app_id: &str, app_ver: &str) -> Self {
+        self.app_extension = Some(AppExtension::new(app_id, app_ver));
+        self
+    }
+
+    /// Add an `ext_sdk` struct with the `libName` and `libVer` of the span's instrumentation
+    /// library to PartA of every Common Schema span event.
+    /// Has no effect unless Common Schema events are enabled.
+    pub fn with_sdk_extension(mut self) -> Self {
+        self.sdk_extension = true;
+        self
+    }
+
+    /// Add a `traceFlags` field with the span context's trace flags, such as `01` for a
+    /// sampled span, to the PartA `ext_dt` struct of every Common Schema span event.
+    /// Has no effect unless Common Schema events are enabled.
+    pub fn with_common_schema_trace_flags(mut self) -> Self {
+        self.common_schema_trace_flags = true;
+        self
+    }
+
+    /// Log a single event for each span when it ends, instead of separate start and end events.
+    /// The event has StartTime, EndTime and DurationMs fields and an Info opcode,
+    /// like the events from the C++ exporter. Span links are also logged when the span ends.
+    /// Has no effect on Common Schema events.
+    pub fn with_single_span_event(mut self) -> Self {
+        self.single_span_event = true;
+        self
+    }
+
+    /// Log the real-time span end event at a level based on the span's status,
+    /// the same as batched span events: Informational for Ok, Error for Error, and Verbose for Unset.
+    /// Without this, real-time span end events use the configured span level.
+    pub fn with_status_level_mapping(mut self) -> Self {
+        self.status_level_mapping = true;
+        self
+    }
+
+    /// Set the span's activity id as the thread's ETW activity id while a real-time span is active,
+    /// so that other ETW events written on the thread, such as from the OS, can be correlated with the span.
+    /// The prior activity id is restored when the span ends. Spans can end in any order:
+    /// if a span ends while a span started after it on the thread is still active, the later
+    /// span restores the activity id from before both of them started when it ends.
+    /// A span that ends on a different thread than it started on leaves a stale entry on the
+    /// thread it started on, and that thread keeps the span's activity id.
+    #[cfg(any(target_os = "windows", doc))]
+    pub fn with_thread_activity_scope(mut self) -> Self {
+        self.thread_activity_scope = true;
+        self
+    }
+
+    /// Log the value of a `<name>.unit` attribute as a `<name>@unit` field that directly
+    /// follows the `<name>` field, instead of as a separate attribute.
+    /// Unit attributes without a matching `<name>` attribute are logged as usual.
+    /// Has no effect on attributes that are logged in a JSON payload.
+    pub fn with_unit_annotations(mut self) -> Self {
+        self.unit_annotations = true;
+        self
+    }
+
+    /// Use the low 8 bytes of the trace id as the high 8 bytes of each span's activity id,
+    /// instead of zeroes. Without this, spans from different traces that happen to have
+    /// the same span id get the same activity id, which makes joins across providers
+    /// on activity id alone ambiguous.
+    pub fn with_trace_scoped_activities(mut self) -> Self {
+        self.trace_scoped_activities = true;
+        self
+    }
+
+    /// Use the activity id stashed in a span's parent context with
+    /// [`RootActivityContextExt::with_root_activity_id`](crate::spans::RootActivityContextExt::with_root_activity_id)
+    /// as the activity id of root spans, such as an incoming request id, for correlation
+    /// with logs from outside the process.
+    /// Child spans keep their derived activity ids, and their related activity id is still
+    /// derived from the span id of the root span.
+    /// This only applies to the realtime exporter.
+    pub fn with_root_activity_from_context(mut self) -> Self {
+        self.root_activity_from_context = true;
+        self
+    }
+
+    /// Add an `ActiveChildren` field to span end events with the number of the span's
+    /// children that had started but not yet ended. Children with a remote parent are
+    /// not counted, and neither are the children of spans past the first few thousand
+    /// that have active children at the same time.
+    pub fn with_child_tracking(mut self) -> Self {
+        self.child_tracking = true;
+        self
+    }
+
+    /// Leave out Common Schema fields that are empty or hold their default value:
+    /// the PartB `kind` of internal spans, zero `linkCount` and `eventCount` fields,
+    /// promoted fields and PartC attributes with an empty string or array value,
+    /// and a PartC struct with no fields.
+    /// Only applies to Common Schema events.
+    pub fn with_compact_common_schema(mut self) -> Self {
+        self.compact_common_schema = true;
+        self
+    }
+
+    /// Log well-known semantic convention attributes as named fields of the
+    /// Common Schema PartB struct, instead of as PartC attributes.
+    /// Currently this promotes `db.system`, `db.name`, and `db.statement` on client spans.
+    /// Only applies to Common Schema events.
+    pub fn with_semantic_convention_promotion(mut self) -> Self {
+        self.semantic_convention_promotion = true;
+        self
+    }
+
+    /// Leave out `f64` attributes whose value is NaN or infinite, instead of logging them
+    /// as typed fields that some event decoders fail on.
+    /// Arrays are logged as usual. In a JSON payload, these values are always logged as `null`.
+    pub fn with_float_sanitization(mut self) -> Self {
+        self.float_sanitization = true;
+        self
+    }
+
+    /// Add a `traceparent` field with the span's W3C trace context
+    /// (`00-<trace id>-<span id>-<trace flags>`) to span start and end events,
+    /// for backends that correlate on a single field instead of separate ids.
+    pub fn with_traceparent_field(mut self) -> Self {
+        self.traceparent_field = true;
+        self
+    }
+
+    /// Round `f64` attributes, and each value of `f64` array attributes, to `decimals`
+    /// decimal places before they are logged. This buckets metric-like values, so that
+    /// downstream aggregation sees fewer distinct values.
+    pub fn with_float_rounding(mut self, decimals: u8) -> Self {
+        self.float_rounding = Some(decimals);
+        self
+    }
+
+    /// Write at most `max_events` of a span's events when the span is exported by the
+    /// batch exporter. If events are left out, an `EventsTruncated` event with the number
+    /// of omitted events in its `OmittedCount` field follows the span's events.
+    pub fn with_max_events_emitted(mut self, max_events: usize) -> Self {
+        self.max_events_emitted = Some(max_events);
+        self
+    }
+
+    /// Log the span's status on the span start event, when the status was already set with
+    /// [`opentelemetry_api::trace::SpanBuilder::with_status`] before the span started.
+    /// A `Status` field with the value `Ok` or `Error` is added to the start event.
+    /// Spans without a status are logged as before.
+    pub fn with_include_status_on_start(mut self) -> Self {
+        self.include_status_on_start = true;
+        self
+    }
+
+    /// Log the value of a span's `status.description` attribute as its `StatusMessage`
+    /// when the span's status is `Ok`. The spec drops the description of an Ok status,
+    /// so this is the only way to keep one for debugging.
+    /// The attribute is logged as usual as well. Has no effect on Common Schema events.
+    pub fn with_preserve_ok_description(mut self) -> Self {
+        self.preserve_ok_description = true;
+        self
+    }
+
+    /// Add an `OffsetNs` field to each span event with the nanoseconds between the start
+    /// of the span and the event, for drawing waterfalls without subtracting timestamps.
+    /// Events timestamped before their span started have an offset of 0.
+    pub fn with_event_offsets(mut self) -> Self {
+        self.event_offsets = true;
+        self
+    }
+
+    /// Add custom tag bits to every event, for pipelines that route events by tag.
+    /// The bits are OR'd into the tags the exporter already uses, such as
+    /// [`EVENT_TAG_IGNORE_EVENT_TIME`](crate::common::EVENT_TAG_IGNORE_EVENT_TIME).
+    /// ETW event tags are 28 bits and EventHeader event tags are 16 bits; higher bits are ignored.
+    pub fn with_event_tags(mut self, tags: u32) -> Self {
+        self.event_tags = tags;
+        self
+    }
+
+    /// Add custom tag bits to the timestamp field of every event. The bits are OR'd into
+    /// [`FIELD_TAG_IS_REAL_EVENT_TIME`](crate::common::FIELD_TAG_IS_REAL_EVENT_TIME)
+    /// where the exporter uses it.
+    /// ETW field tags are 28 bits and EventHeader field tags are 16 bits; higher bits are ignored.
+    pub fn with_field_tags(mut self, tags: u32) -> Self {
+        self.field_tags = tags;
+        self
+    }
+
+    /// Log only the first `max_len` elements of array attributes, followed by a
+    /// `<name>.truncated` field set to true for each array that was cut short.
+    /// Very long arrays can otherwise make an event too large for ETW, which drops it.
+    /// Has no effect on attributes that are logged in a JSON or MessagePack payload.
+    pub fn with_max_array_length(mut self, max_len: usize) -> Self {
+        self.max_array_length = Some(max_len);
+        self
+    }
+
+    /// Log string attributes that are entirely an integer or a finite floating point number,
+    /// such as `"42"`, as `i64` or `f64` fields instead of strings.
+    /// Strings that are logged as JSON are left as is.
+    pub fn with_numeric_string_coercion(mut self) -> Self {
+        self.numeric_string_coercion = true;
+        self
+    }
+
+    /// Log the span kind only on span start events, leaving it off the matching end events.
+    /// Has no effect with [`ExporterBuilder::with_single_span_event`], or on Common Schema events.
+    pub fn with_kind_on_start_only(mut self) -> Self {
+        self.kind_on_start_only = true;
+        self
+    }
+
+    /// Leave the `SpanId`, `ParentId` and `TraceId` fields off span events, which are then
+    /// only correlated with their span by the event's activity id. Span start and end events
+    /// still log these fields. The activity id only includes part of the trace id with
+    /// [`ExporterBuilder::with_trace_scoped_activities`], and none of it otherwise.
+    /// Has no effect on Common Schema events.
+    pub fn with_activity_only_correlation(mut self) -> Self {
+        self.activity_only_correlation = true;
+        self
+    }
+
+    /// Add a `Component` field with the name of the tracer's instrumentation library
+    /// to span start, end and event events, for grouping events by the component that logged them.
+    /// Has no effect on Common Schema events.
+    pub fn with_component_field(mut self) -> Self {
+        self.component_field = true;
+        self
+    }
+
+    /// Add an `AttrCount` field with the number of attributes the span carries
+    /// to span end events, for sizing events without parsing the attributes.
+    /// Has no effect on Common Schema events.
+    pub fn with_attribute_count_field(mut self) -> Self {
+        self.attribute_count_field = true;
+        self
+    }
+
+    /// Log consecutive duplicate span events, with the same name and attributes, only once.
+    /// The event is logged when a different event is added or the span ends, with a
+    /// `RepeatCount` attribute if it was added more than once.
+    /// This only applies to the realtime exporter.
+    pub fn with_event_dedup(mut self) -> Self {
+        self.event_dedup = true;
+        self
+    }
+
+    /// Add a `ThreadName` field with the name of the thread that started or ended the span
+    /// to span start and end events. Unnamed threads use the thread's id instead.
+    /// Spans exported by the batch exporter are logged from another thread, so this only
+    /// applies to the realtime exporter.
+    pub fn with_thread_name_field(mut self) -> Self {
+        self.thread_name_field = true;
+        self
+    }
+
+    /// Add an `IsRemoteParent` field to span start and end events, which is true when the
+    /// span's parent was propagated from another process, such as through an incoming request.
+    /// Only the realtime exporter knows the parent span, so this is ignored by the batch exporter.
+    pub fn with_remote_flag(mut self) -> Self {
+        self.remote_flag = true;
+        self
+    }
+
+    /// Read the provider name and keywords from environment variables when the exporter is built.
+    /// The environment takes precedence over the name passed to [`new_exporter`], which is
+    /// only a default, but not over other builder calls.
+    /// - `OTEL_ETW_PROVIDER_NAME`, or else `OTEL_SERVICE_NAME`, replaces the name passed to
+    ///   [`new_exporter`]. The provider ID is generated from the new name, unless
+    ///   [`ExporterBuilder::with_provider_id`] was called.
+    /// - `OTEL_ETW_KEYWORDS` sets the keywords for spans, span events and span links, as up to
+    ///   three comma separated values, for example `0x1,0x10,0x100`. Missing or invalid values
+    ///   use the default keywords. It is ignored if
+    ///   [`ExporterBuilder::with_custom_keywords_levels`] was called.
+    pub fn with_env_config(mut self) -> Self {
+        self.env_config = true;
+        self
+    }
+
+    /// Only export spans for which `filter` returns true.
+    /// The filter is called with the span's data when the span starts and ends,
+    /// and for each event added to the span, so it should be cheap to evaluate.
+    /// This is independent of the keywords and levels enabled by event consumers,
+    /// and of the span sampling done by the OpenTelemetry SDK.
+    pub fn with_export_filter(
+        mut self,
+        filter: impl Fn(&opentelemetry_sdk::export::trace::SpanData) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.export_filter = Some(Arc::new(filter));
+        self
+    }
+
+    /// Call `tap` after each event is successfully written, with the event's name, level and
+    /// keywords, such as to count the emitted events without a separate ETW or user_events session.
+    /// The callback runs on the thread that writes the event, so it should be cheap.
+    /// Events that no session is listening for are not written, so they are not passed to `tap`.
+    /// The size of the event is not available from the event builders, so it is not included.
+    pub fn with_event_tap(mut self, tap: impl Fn(&EventMeta) + Send + Sync + 'static) -> Self {
+        self.event_tap = Some(Arc::new(tap));
+        self
+    }
+
+    /// Read the current time from `clock` instead of the system time, for the start and end
+    /// times of spans and the times of span events that the instrumentation doesn't supply.
+    /// Only applies to the realtime tracer; batched spans are timed by the OpenTelemetry SDK.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = Some(clock);
+        self
+    }
+
+    /// Call `redaction` with the key and value of each span, event and link attribute before
+    /// it is logged, and log the returned value instead when it returns `Some`, such as to mask
+    /// values that look like credit card numbers. JSON and MessagePack payloads are redacted too.
+    /// Attributes dropped by [`Self::with_attribute_denylist`] or
+    /// [`Self::with_attribute_allowlist`] are not passed to `redaction`.
+    pub fn with_value_redaction(
+        mut self,
+        redaction: impl Fn(&str, &Value) -> Option<Value> + Send + Sync + 'static,
+    ) -> Self {
+        self.value_redaction = Some(Arc::new(redaction));
+        self
+    }
+
     /// Override the default keywords and levels for events.
     /// Provide an implementation of the [`KeywordLevelProvider`] trait that will
     /// return the desired keywords and level values for each type of event.
@@ -101,6 +625,177 @@ impl ExporterBuilder {
         self
     }
 
+    /// For advanced scenarios.
+    /// Encode the event payload as a single MessagePack map, logged as a binary `Payload` field,
+    /// rather than multiple fields. Only needed for interoperability with consumers of
+    /// the C++ exporter's MsgPack payload. [`Self::with_json_payload`] takes precedence.
+    /// Requires the `msgpack` feature to be enabled on the crate.
+    #[cfg(feature = "msgpack")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "msgpack")))]
+    pub fn with_msgpack_payload(mut self) -> Self {
+        self.msgpack = true;
+        self
+    }
+
+    /// Prefix the field names of all span, event and link attributes with `prefix`,
+    /// for example `attr.`, so that an attribute can't collide with a field that the exporter
+    /// itself logs. With the prefix `attr.`, an attribute named `SpanId` is logged as
+    /// `attr.SpanId`, separately from the span's `SpanId` field.
+    pub fn with_attribute_prefix(mut self, prefix: &str) -> Self {
+        self.attribute_prefix = Some(prefix.to_owned());
+        self
+    }
+
+    /// Never log span, event or link attributes with any of the given keys, for example
+    /// `user.email`, including in the JSON or MessagePack payload.
+    /// The denylist takes precedence over [`Self::with_attribute_allowlist`].
+    /// Calling this again adds to the list.
+    pub fn with_attribute_denylist(mut self, keys: &[&str]) -> Self {
+        self.attribute_filter.deny(keys);
+        self
+    }
+
+    /// Only log span, event and link attributes with one of the given keys,
+    /// including in the JSON or MessagePack payload.
+    /// Calling this again adds to the list.
+    pub fn with_attribute_allowlist(mut self, keys: &[&str]) -> Self {
+        self.attribute_filter.allow(keys);
+        self
+    }
+
+    /// Log string attributes whose key ends in `.json` with the JSON OutType
+    /// (`FieldFormat::StringJson` for Linux user_events), so that event consumers
+    /// can pretty-print the value.
+    pub fn with_json_string_detection(self) -> Self {
+        self.with_json_string_detection_suffix(".json")
+    }
+
+    /// Same as [`Self::with_json_string_detection`], but for string attributes whose key
+    /// ends in `suffix` instead of `.json`.
+    pub fn with_json_string_detection_suffix(mut self, suffix: &str) -> Self {
+        self.json_string_suffix = Some(suffix.to_owned());
+        self
+    }
+
+    /// Log string attributes whose key ends in one of the suffixes in `map` with the
+    /// matching OutType (or FieldFormat for Linux user_events), such as `(".xml", StringOutType::Xml)`,
+    /// so that event consumers can pretty-print the value.
+    /// A key that ends in more than one suffix uses the first one in `map`, and keys that
+    /// also match [`Self::with_json_string_detection`] are logged as JSON.
+    ///
+    /// Only the OutTypes in [`StringOutType`] can be mapped. TraceLogging has no OutType
+    /// for URIs or IP addresses, so suffixes such as `.uri` and `.ipv4` are not supported.
+    pub fn with_string_outtype_map(mut self, map: &[(&str, StringOutType)]) -> Self {
+        self.string_outtype_map = Some(
+            map.iter()
+                .map(|(suffix, out_type)| (Arc::from(*suffix), *out_type))
+                .collect(),
+        );
+        self
+    }
+
+    /// Log the resource's `service.instance.id` as a `ServiceInstanceId` field
+    /// on span events, so that events from multiple instances of a service can be told apart.
+    /// The resource is the one from [`Self::with_trace_config`], or the default resource.
+    /// Has no effect on Common Schema events.
+    pub fn with_service_instance_field(mut self) -> Self {
+        self.service_instance_field = true;
+        self
+    }
+
+    /// For advanced scenarios.
+    /// Log the attributes of span start and end events right after the time fields,
+    /// before the span's kind, status, and correlation fields such as `SpanId` and `TraceId`.
+    /// Only needed for consumers that expect the attributes at the start of the payload.
+    /// Has no effect on Common Schema events.
+    pub fn with_attributes_first(mut self) -> Self {
+        self.attributes_first = true;
+        self
+    }
+
+    /// Log the attributes of each span event in an `Attributes` struct, rather than as
+    /// top-level fields, so consumers can tell them apart from the correlation fields.
+    /// A JSON or MessagePack `Payload` field is put in the struct instead.
+    /// Events with more attribute fields than a struct can hold are logged ungrouped.
+    pub fn with_grouped_event_attributes(mut self) -> Self {
+        self.grouped_event_attributes = true;
+        self
+    }
+
+    /// Add a `LinkAttrCount` field with the number of attributes the link carries
+    /// to span link events, for sizing events without parsing the attributes.
+    /// Has no effect on Common Schema events.
+    pub fn with_link_attr_count(mut self) -> Self {
+        self.link_attr_count = true;
+        self
+    }
+
+    /// Log span event and link events with only the `otel_event_time` FILETIME,
+    /// dropping the redundant `time` SYSTEMTIME field to keep these frequent events small.
+    /// Span start and end events are not affected.
+    /// This option has no effect for Linux user_events, which log a single time field.
+    pub fn with_compact_event_timestamps(mut self) -> Self {
+        self.compact_event_timestamps = true;
+        self
+    }
+
+    /// Use `name` for the tracer returned when the exporter is installed and for
+    /// real-time tracers created with an empty name, rather than the exporter's own name.
+    /// The tracer name is logged as the span's component.
+    pub fn with_default_tracer_name(mut self, name: &str) -> Self {
+        self.default_tracer_name = Some(Arc::from(name));
+        self
+    }
+
+    /// Add `keywords` to the span start and end events of entry spans, which are
+    /// root Server spans or spans with a remote parent, so that a session can
+    /// capture only the spans of incoming requests.
+    /// Batch spans don't record whether their parent is remote, so only root Server
+    /// spans are entry spans in batch mode.
+    ///
+    /// Only available on Windows, for the same reason as [`Self::with_per_event_keywords`].
+    #[cfg(any(target_os = "windows", doc))]
+    pub fn with_entry_span_keyword(mut self, keywords: u64) -> Self {
+        self.entry_span_keyword = keywords;
+        self
+    }
+
+    /// For advanced scenarios.
+    /// Log array attributes as JSON strings rather than as sequence fields.
+    /// Some older event consumers cannot decode sequence fields, and drop the whole event.
+    /// Unlike [`Self::with_json_payload`], all other attributes are still logged as typed fields.
+    /// Requires the `json` feature to be enabled on the crate.
+    #[cfg(feature = "json")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+    pub fn with_arrays_as_json(mut self) -> Self {
+        self.arrays_as_json = true;
+        self
+    }
+
+    /// Log the span's links as a `Links` field of the span end event, containing a JSON array
+    /// with the linked trace and span ids and the attributes of each link,
+    /// instead of logging a separate event for each link.
+    /// Requires the `json` feature to be enabled on the crate.
+    #[cfg(feature = "json")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+    pub fn with_inline_links(mut self) -> Self {
+        self.inline_links = true;
+        self
+    }
+
+    /// Log the span's events as an `Events` field of the span end event, containing a JSON array
+    /// with the name, time and attributes of each event,
+    /// instead of logging a separate event for each span event.
+    /// This only applies to the batch exporter, since real-time span events are logged
+    /// when they are added.
+    /// Requires the `json` feature to be enabled on the crate.
+    #[cfg(feature = "json")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+    pub fn with_inline_events(mut self) -> Self {
+        self.inline_events = true;
+        self
+    }
+
     /// For advanced scenarios.
     /// Emit extra events that follow the Common Schema 4.0 mapping.
     /// Recommended only for compatibility with specialized event consumers.
@@ -115,6 +810,13 @@ impl ExporterBuilder {
         self
     }
 
+    /// Add `linkCount` and `eventCount` fields to the PartB of Common Schema span events.
+    /// This option has no effect unless [`Self::with_common_schema_events`] is also called.
+    pub fn with_count_fields(mut self) -> Self {
+        self.emit_count_fields = true;
+        self
+    }
+
     /// For advanced scenarios.
     /// Do not emit realtime events. Use this option in conjunction with
     /// [`Self::with_common_schema_events`] to only emit Common Schema events.
@@ -125,6 +827,54 @@ impl ExporterBuilder {
         self
     }
 
+    /// Add an `EventCount` field and an `EventNames` field to the span end event,
+    /// summarizing the events that were added to the span.
+    /// The event names are a comma-separated list, or a JSON array if
+    /// the JSON payload option is also enabled.
+    /// Span events are still logged individually; sessions that only want the summary
+    /// can leave the span event keyword disabled.
+    pub fn with_event_summary(mut self) -> Self {
+        self.emit_event_summary = true;
+        self
+    }
+
+    /// Add `DroppedAttributes`, `DroppedEvents`, and `DroppedLinks` fields to the span end event,
+    /// with the number of each that were dropped because of the span limits.
+    /// This option only applies to span end events exported from batched span data.
+    pub fn with_dropped_counts(mut self) -> Self {
+        self.emit_dropped_counts = true;
+        self
+    }
+
+    /// For advanced scenarios.
+    /// Split span end events whose attributes would take up more than `max_size_bytes`
+    /// into multiple events, each with a `Part` and `PartCount` field and a subset of the attributes.
+    /// ETW drops events larger than the session's buffer size (64KB at most),
+    /// so this guarantees that all of the span's attributes are delivered.
+    /// The size of each attribute is estimated, so leave some room for the other fields of the event.
+    pub fn with_event_split(mut self, max_size_bytes: usize) -> Self {
+        self.event_split_size = Some(max_size_bytes);
+        self
+    }
+
+    /// Add a `Duration100ns` field with the span's duration in 100ns ticks, the same unit as
+    /// a FILETIME, to ETW span end events. This is more precise than the `DurationMs` field,
+    /// which is only logged when [`ExporterBuilder::with_single_span_event`] is used.
+    #[cfg(any(target_os = "windows", doc))]
+    pub fn with_duration_filetime(mut self) -> Self {
+        self.duration_filetime = true;
+        self
+    }
+
+    /// For advanced scenarios.
+    /// Set the ETW channel for all events, instead of the default TraceLogging channel (11).
+    /// Only needed if the events are consumed by tools that route them based on a manifest's channels.
+    #[cfg(any(target_os = "windows", doc))]
+    pub fn with_channel(mut self, channel: u8) -> Self {
+        self.etw_channel = Some(channel);
+        self
+    }
+
     /// For advanced scenarios.
     /// Set the ETW provider group to join this provider to.
     #[cfg(any(target_os = "windows", doc))]
@@ -141,6 +891,43 @@ impl ExporterBuilder {
         self
     }
 
+    /// Log the time fields of user_events events in `time_format` since the Unix epoch,
+    /// instead of seconds, for event decoders that need a finer resolution.
+    /// Times in milliseconds or nanoseconds are logged as unsigned integers.
+    #[cfg(any(target_os = "linux", doc))]
+    pub fn with_time_format(mut self, time_format: TimeFormat) -> Self {
+        self.time_format = time_format;
+        self
+    }
+
+    /// For advanced scenarios.
+    /// Let at most `max_queued_spans` spans wait in the batch span processor's queue,
+    /// such as when the async runtime stalls. Spans that end while the queue is full are
+    /// dropped, and a `SpansDropped` event with the number of dropped spans is logged
+    /// before the next batch is exported.
+    /// Only applies when an async runtime is set with [`ExporterBuilder::with_async_runtime`].
+    pub fn with_bounded_queue(mut self, max_queued_spans: usize) -> Self {
+        self.bounded_queue_size = Some(max_queued_spans);
+        self
+    }
+
+    /// For advanced scenarios.
+    /// Set the provider group to join this provider to by name, the same way on every platform.
+    /// On Windows, the ETW provider group ID is generated from `name` the same way as provider IDs.
+    /// On Linux, `name` is used as the EventHeader provider group name, so it must be
+    /// lower case ASCII or numeric digits.
+    pub fn with_provider_group_auto(mut self, name: &str) -> Self {
+        #[cfg(target_os = "windows")]
+        {
+            self.provider_group = ProviderGroup::Windows(Guid::from_name(name));
+        }
+        #[cfg(target_os = "linux")]
+        {
+            self.provider_group = ProviderGroup::Linux(Cow::Owned(name.to_owned()));
+        }
+        self
+    }
+
     /// Set which OpenTelemetry-Rust async runtime to use.
     /// See <https://docs.rs/opentelemetry/latest/opentelemetry/index.html#crate-feature-flags>
     /// for more details.
@@ -161,6 +948,14 @@ impl ExporterBuilder {
             panic!("at least one ETW event type must be enabled");
         }
 
+        if let Some(max_size) = self.event_split_size {
+            assert_ne!(max_size, 0, "Event split size must not be zero");
+        }
+
+        if let Some(max_queued_spans) = self.bounded_queue_size {
+            assert_ne!(max_queued_spans, 0, "Bounded queue size must not be zero");
+        }
+
         #[cfg(any(
             feature = "rt-tokio",
             feature = "rt-tokio-current-thread",
@@ -202,49 +997,174 @@ impl ExporterBuilder {
         }
     }
 
-    /// Install the exporter as the
+    fn apply_env_config(&mut self) {
+        if self.env_config {
+            self.apply_env_values(|name| std::env::var(name).ok());
+        }
+    }
+
+    // Applies the environment variables returned by `var`, which tests use instead of
+    // the process environment.
+    fn apply_env_values(&mut self, var: impl Fn(&str) -> Option<String>) {
+        let provider_name = var("OTEL_ETW_PROVIDER_NAME")
+            .or_else(|| var("OTEL_SERVICE_NAME"))
+            .filter(|name| !name.is_empty());
+        if let Some(provider_name) = provider_name {
+            if self.provider_id == Guid::from_name(&self.provider_name) {
+                self.provider_id = Guid::from_name(&provider_name);
+            }
+            self.provider_name = provider_name;
+        }
+
+        if self.exporter_config.is_none() {
+            if let Some(keywords) = var("OTEL_ETW_KEYWORDS") {
+                self.exporter_config =
+                    Some(Box::new(EnvKeywordLevelProvider::from_env_value(&keywords)));
+            }
+        }
+    }
+
+    fn take_trace_config(&mut self) -> opentelemetry_sdk::trace::Config {
+        if let Some(config) = self.trace_config.take() {
+            config
+        } else {
+            opentelemetry_sdk::trace::config()
+        }
+    }
+
+    // The resource can't change once the provider is built, so the id is looked up once here
+    // rather than for every event.
+    fn get_service_instance_id(&self) -> Option<Arc<str>> {
+        if !self.service_instance_field {
+            return None;
+        }
+
+        let key = Key::from_static_str("service.instance.id");
+        let id = match &self.trace_config {
+            Some(config) => config.resource.get(key),
+            None => opentelemetry_sdk::trace::config().resource.get(key),
+        };
+        id.map(|value| Arc::from(value.as_str().as_ref()))
+    }
+
+    fn get_exporter_config<C: KeywordLevelProvider>(&self, kwl: C) -> ExporterConfig<C> {
+        ExporterConfig {
+            kwl,
+            json: self.json,
+            common_schema: self.emit_common_schema_events,
+            etw_activities: self.emit_realtime_events,
+            event_summary: self.emit_event_summary,
+            event_split: self.event_split_size,
+            count_fields: self.emit_count_fields,
+            arrays_as_json: self.arrays_as_json,
+            dropped_counts: self.emit_dropped_counts,
+            utf16_strings: self.utf16_strings,
+            json_string_suffix: self.json_string_suffix.as_deref().map(Arc::from),
+            export_filter: self.export_filter.clone(),
+            etw_channel: self.etw_channel,
+            per_event_keywords: self.per_event_keywords,
+            startup_event: self.startup_event,
+            lifecycle_events: self.lifecycle_events,
+            app_extension: self.app_extension.clone(),
+            single_span_event: self.single_span_event,
+            status_level_mapping: self.status_level_mapping,
+            thread_activity_scope: self.thread_activity_scope,
+            unit_annotations: self.unit_annotations,
+            trace_scoped_activities: self.trace_scoped_activities,
+            semantic_convention_promotion: self.semantic_convention_promotion,
+            float_sanitization: self.float_sanitization,
+            traceparent_field: self.traceparent_field,
+            remote_flag: self.remote_flag,
+            float_rounding: self.float_rounding,
+            inline_links: self.inline_links,
+            thread_name_field: self.thread_name_field,
+            attribute_prefix: self.attribute_prefix.as_deref().map(Arc::from),
+            max_events_emitted: self.max_events_emitted,
+            include_status_on_start: self.include_status_on_start,
+            event_dedup: self.event_dedup,
+            time_format: self.time_format,
+            duration_filetime: self.duration_filetime,
+            msgpack: self.msgpack,
+            root_activity_from_context: self.root_activity_from_context,
+            sdk_extension: self.sdk_extension,
+            event_tap: self.event_tap.clone(),
+            self_description: self.self_description,
+            attribute_filter: (!self.attribute_filter.is_empty())
+                .then(|| Arc::new(self.attribute_filter.clone())),
+            value_redaction: self.value_redaction.clone(),
+            child_tracking: self.child_tracking,
+            compact_common_schema: self.compact_common_schema,
+            preserve_ok_description: self.preserve_ok_description,
+            event_offsets: self.event_offsets,
+            event_tags: self.event_tags,
+            field_tags: self.field_tags,
+            max_array_length: self.max_array_length,
+            numeric_string_coercion: self.numeric_string_coercion,
+            kind_on_start_only: self.kind_on_start_only,
+            activity_only_correlation: self.activity_only_correlation,
+            clock: self.clock.clone(),
+            component_field: self.component_field,
+            attribute_count_field: self.attribute_count_field,
+            string_outtype_map: self.string_outtype_map.clone(),
+            service_instance_id: self.get_service_instance_id(),
+            attributes_first: self.attributes_first,
+            common_schema_trace_flags: self.common_schema_trace_flags,
+            suppressed_spans: self.suppressed_spans.clone(),
+            suppressed_spans_event: self.suppressed_spans_event,
+            grouped_event_attributes: self.grouped_event_attributes,
+            link_attr_count: self.link_attr_count,
+            compact_event_timestamps: self.compact_event_timestamps,
+            default_tracer_name: self.default_tracer_name.clone(),
+            entry_span_keyword: self.entry_span_keyword,
+            inline_events: self.inline_events,
+        }
+    }
+
+    /// Build a tracer provider for the exporter without setting it as the
     /// [global tracer provider](https://docs.rs/opentelemetry_api/latest/opentelemetry_api/global/index.html).
-    pub fn install(
-        mut self,
-    ) -> <GlobalTracerProvider as opentelemetry_api::trace::TracerProvider>::Tracer {
+    /// The caller is responsible for keeping the returned provider alive for as long
+    /// as spans should be exported.
+    pub fn build_span_exporter(mut self) -> ExporterTracerProvider {
+        self.apply_env_config();
         self.validate_config();
 
-        // This will always return a boxed trait object.
-        // Hopefully that won't cause too much of a performance issue, since that is a limitation of the global tracer as well.
+        let settings = ExporterSettings::new(
+            &self.get_exporter_config(DefaultKeywordLevelProvider),
+            self.provider_id,
+        );
 
         // Avoid adding an extra dyn indirection by making sure BatchExporter/RealtimeExporter can be specialized for the keyword provider type.
         // Non-default keyword providers will always be boxed trait objects, but that shouldn't be the common case.
 
         if !self.emit_realtime_events {
-            let provider_builder = match self.runtime {
+            let spans_enabled: SpansEnabled;
+            let provider_builder = match self.runtime.take() {
                 None => {
-                    let provider_builder = match self.exporter_config {
+                    let provider_builder = match self.exporter_config.take() {
                         Some(exporter_config) => {
+                            let exporter = BatchExporter::new(
+                                &self.provider_name,
+                                &self.provider_id,
+                                &self.provider_group,
+                                self.use_byte_for_bools,
+                                self.get_exporter_config(exporter_config),
+                            );
+                            spans_enabled = exporter.spans_enabled();
                             opentelemetry_sdk::trace::TracerProvider::builder()
-                                .with_simple_exporter(BatchExporter::new(
-                                    &self.provider_name,
-                                    self.provider_group,
-                                    self.use_byte_for_bools,
-                                    ExporterConfig {
-                                        kwl: exporter_config,
-                                        json: self.json,
-                                        common_schema: self.emit_common_schema_events,
-                                        etw_activities: self.emit_realtime_events,
-                                    },
-                                ))
+                                .with_simple_exporter(exporter)
                         }
-                        None => opentelemetry_sdk::trace::TracerProvider::builder()
-                            .with_simple_exporter(BatchExporter::new(
+                        None => {
+                            let exporter = BatchExporter::new(
                                 &self.provider_name,
-                                self.provider_group,
+                                &self.provider_id,
+                                &self.provider_group,
                                 self.use_byte_for_bools,
-                                ExporterConfig {
-                                    kwl: DefaultKeywordLevelProvider,
-                                    json: self.json,
-                                    common_schema: self.emit_common_schema_events,
-                                    etw_activities: self.emit_realtime_events,
-                                },
-                            )),
+                                self.get_exporter_config(DefaultKeywordLevelProvider),
+                            );
+                            spans_enabled = exporter.spans_enabled();
+                            opentelemetry_sdk::trace::TracerProvider::builder()
+                                .with_simple_exporter(exporter)
+                        }
                     };
 
                     if let Some(config) = self.trace_config.take() {
@@ -271,38 +1191,39 @@ impl ExporterBuilder {
                         EtwExporterAsyncRuntime::AsyncStd => opentelemetry_sdk::runtime::AsyncStd,
                     };
 
-                    let provider_builder = match self.exporter_config {
+                    let provider_builder = match self.exporter_config.take() {
                         Some(exporter_config) => {
-                            opentelemetry_sdk::trace::TracerProvider::builder().with_batch_exporter(
-                                BatchExporter::new(
-                                    &self.provider_name,
-                                    self.provider_group,
-                                    self.use_byte_for_bools,
-                                    ExporterConfig {
-                                        kwl: exporter_config,
-                                        json: self.json,
-                                        common_schema: self.emit_common_schema_events,
-                                        etw_activities: self.emit_realtime_events,
-                                    },
-                                ),
+                            let exporter = BatchExporter::new(
+                                &self.provider_name,
+                                &self.provider_id,
+                                &self.provider_group,
+                                self.use_byte_for_bools,
+                                self.get_exporter_config(exporter_config),
+                            );
+                            spans_enabled = exporter.spans_enabled();
+                            with_batch_exporter(
+                                opentelemetry_sdk::trace::TracerProvider::builder(),
+                                exporter,
                                 runtime,
+                                self.bounded_queue_size,
                             )
                         }
-                        None => opentelemetry_sdk::trace::TracerProvider::builder()
-                            .with_batch_exporter(
-                                BatchExporter::new(
-                                    &self.provider_name,
-                                    self.provider_group,
-                                    self.use_byte_for_bools,
-                                    ExporterConfig {
-                                        kwl: DefaultKeywordLevelProvider,
-                                        json: self.json,
-                                        common_schema: self.emit_common_schema_events,
-                                        etw_activities: self.emit_realtime_events,
-                                    },
-                                ),
+                        None => {
+                            let exporter = BatchExporter::new(
+                                &self.provider_name,
+                                &self.provider_id,
+                                &self.provider_group,
+                                self.use_byte_for_bools,
+                                self.get_exporter_config(DefaultKeywordLevelProvider),
+                            );
+                            spans_enabled = exporter.spans_enabled();
+                            with_batch_exporter(
+                                opentelemetry_sdk::trace::TracerProvider::builder(),
+                                exporter,
                                 runtime,
-                            ),
+                                self.bounded_queue_size,
+                            )
+                        }
                     };
 
                     if let Some(config) = self.trace_config.take() {
@@ -319,57 +1240,120 @@ impl ExporterBuilder {
                 Some(_) => todo!(), // Unreachable
             };
 
-            let provider = provider_builder.build();
-            let _ = global::set_tracer_provider(provider);
+            ExporterTracerProvider::new(
+                ProviderInner::Batch(Mutex::new(provider_builder.build())),
+                settings,
+                spans_enabled,
+            )
         } else {
-            let otel_config = if let Some(config) = self.trace_config.take() {
-                config
-            } else {
-                opentelemetry_sdk::trace::config()
-            };
-
-            match self.exporter_config {
+            match self.exporter_config.take() {
                 Some(exporter_config) => {
+                    // The exporter config reads the resource from the trace config,
+                    // so it's built before the trace config is taken
+                    let exporter_config = self.get_exporter_config(exporter_config);
+                    let otel_config = self.take_trace_config();
                     let provider = RealtimeTracerProvider::new(
                         &self.provider_name,
-                        self.provider_group,
+                        &self.provider_id,
+                        &self.provider_group,
                         otel_config,
                         self.use_byte_for_bools,
-                        ExporterConfig {
-                            kwl: exporter_config,
-                            json: self.json,
-                            common_schema: self.emit_common_schema_events,
-                            etw_activities: self.emit_realtime_events,
-                        },
+                        exporter_config,
                     );
-
-                    let _ = global::set_tracer_provider(provider);
+                    let spans_enabled = provider.spans_enabled();
+                    ExporterTracerProvider::new(
+                        ProviderInner::RealtimeCustom(provider),
+                        settings,
+                        spans_enabled,
+                    )
                 }
                 None => {
+                    let exporter_config = self.get_exporter_config(DefaultKeywordLevelProvider);
+                    let otel_config = self.take_trace_config();
                     let provider = RealtimeTracerProvider::new(
                         &self.provider_name,
-                        self.provider_group,
+                        &self.provider_id,
+                        &self.provider_group,
                         otel_config,
                         self.use_byte_for_bools,
-                        ExporterConfig {
-                            kwl: DefaultKeywordLevelProvider,
-                            json: self.json,
-                            common_schema: self.emit_common_schema_events,
-                            etw_activities: self.emit_realtime_events,
-                        },
+                        exporter_config,
                     );
-
-                    let _ = global::set_tracer_provider(provider);
+                    let spans_enabled = provider.spans_enabled();
+                    ExporterTracerProvider::new(
+                        ProviderInner::Realtime(provider),
+                        settings,
+                        spans_enabled,
+                    )
                 }
             }
         }
+    }
 
-        global::tracer_provider().tracer(
+    /// Install the exporter as the
+    /// [global tracer provider](https://docs.rs/opentelemetry_api/latest/opentelemetry_api/global/index.html).
+    /// If a tracer provider from this exporter is already installed, it is replaced
+    /// and the conflict is reported to the global error handler.
+    pub fn install(
+        self,
+    ) -> <GlobalTracerProvider as opentelemetry_api::trace::TracerProvider>::Tracer {
+        // This will always return a boxed trait object.
+        // Hopefully that won't cause too much of a performance issue, since that is a limitation of the global tracer as well.
+        let tracer_name = self.default_tracer_name.clone();
+        if let Err(err) = self.build_span_exporter().set_as_global(true) {
+            global::handle_error(TraceError::Other(Box::new(err)));
+        }
+
+        Self::global_tracer(tracer_name)
+    }
+
+    /// Install the exporter as the
+    /// [global tracer provider](https://docs.rs/opentelemetry_api/latest/opentelemetry_api/global/index.html),
+    /// unless a tracer provider from this exporter is already installed.
+    /// Unlike [`Self::install`], an existing provider is left in place and
+    /// [`InstallError::AlreadyInstalled`] is returned.
+    pub fn try_install(
+        self,
+    ) -> Result<
+        <GlobalTracerProvider as opentelemetry_api::trace::TracerProvider>::Tracer,
+        InstallError,
+    > {
+        let tracer_name = self.default_tracer_name.clone();
+        self.build_span_exporter().set_as_global(false)?;
+
+        Ok(Self::global_tracer(tracer_name))
+    }
+
+    /// Install the exporter as the
+    /// [global tracer provider](https://docs.rs/opentelemetry_api/latest/opentelemetry_api/global/index.html),
+    /// the same as [`Self::install`], and return the global tracer as an [`EtwTracer`].
+    pub fn install_span_exporter(self) -> EtwTracer {
+        let tracer_name = self.default_tracer_name.clone();
+        let provider = self.build_span_exporter();
+        let spans_enabled = provider.spans_enabled();
+        let provider_id = provider.provider_id();
+        let suppressed_spans = provider.suppressed_spans();
+        if let Err(err) = provider.set_as_global(true) {
+            global::handle_error(TraceError::Other(Box::new(err)));
+        }
+
+        EtwTracer::new(
+            Self::global_tracer(tracer_name),
+            spans_enabled,
+            provider_id,
+            suppressed_spans,
+        )
+    }
+
+    fn global_tracer(
+        name: Option<Arc<str>>,
+    ) -> <GlobalTracerProvider as opentelemetry_api::trace::TracerProvider>::Tracer {
+        global::tracer_provider().tracer(match name {
+            Some(name) => Cow::Owned(name.to_string()),
             #[cfg(all(target_os = "windows"))]
-            "opentelemetry-etw",
+            None => Cow::Borrowed("opentelemetry-etw"),
             #[cfg(all(target_os = "linux"))]
-            "opentelemetry-user_events",
-        )
+            None => Cow::Borrowed("opentelemetry-user_events"),
+        })
     }
 }
 
@@ -421,4 +1405,329 @@ mod tests {
     fn install_realtime() {
         new_exporter("my_provider_name").install();
     }
+
+    #[test]
+    fn install_span_exporter() {
+        let builder = new_exporter("install_span_exporter_test");
+        let provider_id = builder.get_provider_id();
+        let tracer = builder.install_span_exporter();
+        assert!(tracer.provider_id() == provider_id);
+        assert_eq!(tracer.in_span("span", |_| 42), 42);
+
+        // The handle doesn't keep a replaced exporter alive
+        new_exporter("install_span_exporter_test").install();
+        assert!(!tracer.is_enabled());
+    }
+
+    #[test]
+    fn install_twice() {
+        new_exporter("my_provider_name").install();
+
+        let result = new_exporter("my_provider_name").try_install();
+        assert!(matches!(result, Err(InstallError::AlreadyInstalled)));
+    }
+
+    #[test]
+    #[cfg(feature = "json")]
+    fn inline_links() {
+        let builder = new_exporter("my_provider_name");
+        assert!(!builder
+            .get_exporter_config(DefaultKeywordLevelProvider)
+            .get_inline_links());
+
+        let builder = builder.with_inline_links();
+        assert!(builder
+            .get_exporter_config(DefaultKeywordLevelProvider)
+            .get_inline_links());
+    }
+
+    #[test]
+    fn exporter_settings() {
+        let provider = new_exporter("my_provider_name")
+            .with_common_schema_events()
+            .build_span_exporter();
+        assert!(provider.exports_common_schema());
+        assert!(provider.exports_realtime());
+        assert!(!provider.exports_json());
+
+        let provider = new_exporter("my_provider_name")
+            .with_common_schema_events()
+            .with_custom_keywords_levels(DefaultKeywordLevelProvider)
+            .build_span_exporter();
+        assert!(provider.exports_realtime());
+
+        // Without span start and end events, spans are batched after they end
+        let provider = new_exporter("my_provider_name")
+            .with_common_schema_events()
+            .without_realtime_events()
+            .build_span_exporter();
+        assert!(provider.exports_common_schema());
+        assert!(!provider.exports_realtime());
+
+        let provider = new_exporter("my_provider_name")
+            .with_common_schema_events()
+            .without_realtime_events()
+            .with_custom_keywords_levels(DefaultKeywordLevelProvider)
+            .build_span_exporter();
+        assert!(!provider.exports_realtime());
+    }
+
+    #[test]
+    fn build_span_exporter_realtime() {
+        use opentelemetry_api::trace::Tracer;
+
+        let provider1 = new_exporter("my_provider_name").build_span_exporter();
+        let provider2 = new_exporter("my_other_provider_name").build_span_exporter();
+
+        provider1.tracer("tracer1").in_span("span1", |_| {});
+        provider2.tracer("tracer2").in_span("span2", |_| {});
+    }
+
+    #[test]
+    fn shutdown_batch() {
+        use opentelemetry_api::trace::{Span, Tracer};
+
+        let provider = new_exporter("my_provider_name")
+            .with_common_schema_events()
+            .without_realtime_events()
+            .build_span_exporter();
+
+        let tracer = provider.tracer("test");
+        assert!(tracer.start("span").is_recording());
+
+        assert!(provider.shutdown().is_empty());
+        assert!(!tracer.start("span").is_recording());
+        assert!(!provider.tracer("test").start("span").is_recording());
+    }
+
+    #[test]
+    #[cfg(target_os = "windows")]
+    fn provider_group_auto_windows() {
+        let builder = new_exporter("my_provider_name").with_provider_group_auto("mygroup");
+        assert!(matches!(
+            builder.provider_group,
+            ProviderGroup::Windows(guid) if guid == Guid::from_name("mygroup")
+        ));
+        builder.validate_config();
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn provider_group_auto_linux() {
+        let builder = new_exporter("my_provider_name").with_provider_group_auto("mygroup");
+        assert!(matches!(
+            &builder.provider_group,
+            ProviderGroup::Linux(name) if name == "mygroup"
+        ));
+        builder.validate_config();
+    }
+
+    #[test]
+    fn scoped_providers() {
+        use opentelemetry_api::trace::Tracer;
+
+        let provider1: ScopedTracerProvider =
+            new_exporter("my_first_library_provider").build_span_exporter();
+        let provider2: ScopedTracerProvider =
+            new_exporter("my_second_library_provider").build_span_exporter();
+
+        provider1.tracer("tracer1").in_span("span1", |_| {});
+        provider2.tracer("tracer2").in_span("span2", |_| {});
+
+        assert!(provider1.provider_id() == Guid::from_name("my_first_library_provider"));
+        assert!(provider2.provider_id() == Guid::from_name("my_second_library_provider"));
+        assert!(provider1.provider_id() != provider2.provider_id());
+    }
+
+    #[test]
+    fn env_config() {
+        let env = |name: &str| match name {
+            "OTEL_ETW_PROVIDER_NAME" => Some("my_env_provider_name".to_owned()),
+            "OTEL_SERVICE_NAME" => Some("my_service_name".to_owned()),
+            "OTEL_ETW_KEYWORDS" => Some("0x2, 32".to_owned()),
+            _ => None,
+        };
+
+        let mut builder = new_exporter("my_provider_name");
+        builder.apply_env_values(env);
+        assert_eq!(builder.provider_name, "my_env_provider_name");
+        assert!(builder.get_provider_id() == Guid::from_name("my_env_provider_name"));
+
+        let kwl = builder.exporter_config.as_ref().unwrap();
+        assert_eq!(kwl.get_span_keywords(), 0x2);
+        assert_eq!(kwl.get_event_keywords(), 32);
+        assert_eq!(kwl.get_links_keywords(), 0x100);
+
+        // The service name is only used without a provider name, and empty names are ignored
+        let mut builder = new_exporter("my_provider_name");
+        builder.apply_env_values(|name| env(name).filter(|_| name != "OTEL_ETW_PROVIDER_NAME"));
+        assert_eq!(builder.provider_name, "my_service_name");
+
+        let mut builder = new_exporter("my_provider_name");
+        builder.apply_env_values(|_| Some(String::new()));
+        assert_eq!(builder.provider_name, "my_provider_name");
+
+        // Other builder calls take precedence over the environment, but the name passed to
+        // new_exporter doesn't
+        let provider_id = Guid::from_name("my_provider_id");
+        let mut builder = new_exporter("my_provider_name")
+            .with_provider_id(provider_id)
+            .with_custom_keywords_levels(DefaultKeywordLevelProvider);
+        builder.apply_env_values(env);
+        assert_eq!(builder.provider_name, "my_env_provider_name");
+        assert!(builder.get_provider_id() == provider_id);
+
+        let kwl = builder.exporter_config.as_ref().unwrap();
+        assert_eq!(kwl.get_span_keywords(), 0x1);
+
+        // The environment is only read after with_env_config
+        let mut builder = new_exporter("my_provider_name");
+        builder.apply_env_config();
+        assert_eq!(builder.provider_name, "my_provider_name");
+    }
+
+    #[test]
+    fn service_instance_field() {
+        let trace_config = || {
+            opentelemetry_sdk::trace::config().with_resource(opentelemetry_sdk::Resource::new([
+                opentelemetry::KeyValue::new("service.instance.id", "instance-1"),
+            ]))
+        };
+
+        let exporter_config = new_exporter("my_provider_name")
+            .with_trace_config(trace_config())
+            .with_service_instance_field()
+            .get_exporter_config(DefaultKeywordLevelProvider);
+        assert_eq!(
+            exporter_config.get_service_instance_id().map(AsRef::as_ref),
+            Some("instance-1")
+        );
+
+        // The id is only logged when the field is enabled
+        let exporter_config = new_exporter("my_provider_name")
+            .with_trace_config(trace_config())
+            .get_exporter_config(DefaultKeywordLevelProvider);
+        assert!(exporter_config.get_service_instance_id().is_none());
+    }
+
+    #[test]
+    fn attributes_first() {
+        assert!(!new_exporter("my_provider_name")
+            .get_exporter_config(DefaultKeywordLevelProvider)
+            .get_attributes_first());
+        assert!(new_exporter("my_provider_name")
+            .with_attributes_first()
+            .get_exporter_config(DefaultKeywordLevelProvider)
+            .get_attributes_first());
+    }
+
+    #[test]
+    fn compact_event_timestamps() {
+        assert!(!new_exporter("my_provider_name")
+            .get_exporter_config(DefaultKeywordLevelProvider)
+            .get_compact_event_timestamps());
+        assert!(new_exporter("my_provider_name")
+            .with_compact_event_timestamps()
+            .get_exporter_config(DefaultKeywordLevelProvider)
+            .get_compact_event_timestamps());
+    }
+
+    #[test]
+    fn app_extension() {
+        let exporter_config = new_exporter("my_provider_name")
+            .with_common_schema_events()
+            .with_app_extension("my_app", "1.2.3")
+            .get_exporter_config(DefaultKeywordLevelProvider);
+
+        let app_extension = exporter_config.get_app_extension().unwrap();
+        assert_eq!(app_extension.id.as_ref(), "my_app");
+        assert_eq!(app_extension.ver.as_ref(), "1.2.3");
+        assert!(!exporter_config.get_sdk_extension());
+
+        let exporter_config = new_exporter("my_provider_name")
+            .with_common_schema_events()
+            .with_sdk_extension()
+            .get_exporter_config(DefaultKeywordLevelProvider);
+        assert!(exporter_config.get_sdk_extension());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn span_level_for_kind() {
+        use opentelemetry_api::trace::{Span, SpanKind, Tracer};
+        use std::sync::Mutex;
+
+        struct KindLevels;
+
+        impl KeywordLevelProvider for KindLevels {
+            fn get_span_keywords(&self) -> u64 {
+                DefaultKeywordLevelProvider.get_span_keywords()
+            }
+            fn get_event_keywords(&self) -> u64 {
+                DefaultKeywordLevelProvider.get_event_keywords()
+            }
+            fn get_links_keywords(&self) -> u64 {
+                DefaultKeywordLevelProvider.get_links_keywords()
+            }
+            fn get_span_level(&self) -> u8 {
+                DefaultKeywordLevelProvider.get_span_level()
+            }
+            fn get_event_level(&self) -> u8 {
+                DefaultKeywordLevelProvider.get_event_level()
+            }
+            fn get_links_level(&self) -> u8 {
+                DefaultKeywordLevelProvider.get_links_level()
+            }
+            fn get_span_level_for_kind(&self, kind: &SpanKind) -> u8 {
+                match kind {
+                    SpanKind::Internal => 5,
+                    _ => self.get_span_level(),
+                }
+            }
+        }
+
+        let tapped = Arc::new(Mutex::new(Vec::new()));
+        let tap_events = tapped.clone();
+        let exporter_config = new_exporter("span_level_for_kind_test")
+            .with_event_tap(move |meta: &EventMeta| {
+                tap_events
+                    .lock()
+                    .unwrap()
+                    .push((meta.name.to_owned(), meta.level))
+            })
+            .get_exporter_config(KindLevels);
+
+        let provider = RealtimeTracerProvider::<_, crate::user_events::UserEventsExporter<_>>::new(
+            "span_level_for_kind_test",
+            &Guid::zero(),
+            &ProviderGroup::Unset,
+            opentelemetry_sdk::trace::config(),
+            false,
+            exporter_config,
+        );
+        let tracer = provider.tracer("test");
+        tracer
+            .span_builder("internal")
+            .with_kind(SpanKind::Internal)
+            .start(&tracer)
+            .end();
+        tracer
+            .span_builder("server")
+            .with_kind(SpanKind::Server)
+            .start(&tracer)
+            .end();
+
+        // Verbose for the Internal span's start and end events, Informational for the Server span's
+        let verbose = ("internal".to_owned(), 5);
+        let informational = ("server".to_owned(), 4);
+        assert_eq!(
+            *tapped.lock().unwrap(),
+            [
+                verbose.clone(),
+                verbose,
+                informational.clone(),
+                informational
+            ]
+        );
+    }
 }
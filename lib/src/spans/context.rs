@@ -0,0 +1,86 @@
+use opentelemetry::Context;
+
+/// Keywords stashed in a [`Context`] by [`KeywordContextExt::with_keyword`].
+#[derive(Clone, Copy, Debug)]
+struct ContextKeyword(u64);
+
+/// Methods for selecting the ETW keywords of the spans started in a [`Context`].
+pub trait KeywordContextExt {
+    /// Returns a copy of this context with `keywords` stashed in it.
+    /// The realtime exporter adds these keywords to the span keywords of spans started
+    /// with this context as their parent context, and to the keywords of their span events,
+    /// so that the spans from a specific code region can be collected separately.
+    ///
+    /// Only available on Windows, for the same reason as
+    /// [`ExporterBuilder::with_per_event_keywords`](crate::spans::ExporterBuilder::with_per_event_keywords).
+    #[cfg(any(target_os = "windows", doc))]
+    fn with_keyword(&self, keywords: u64) -> Self;
+
+    /// The keywords stashed in this context by [`Self::with_keyword`], if any.
+    fn keyword(&self) -> Option<u64>;
+}
+
+impl KeywordContextExt for Context {
+    #[cfg(any(target_os = "windows", doc))]
+    fn with_keyword(&self, keywords: u64) -> Self {
+        self.with_value(ContextKeyword(keywords))
+    }
+
+    fn keyword(&self) -> Option<u64> {
+        self.get::<ContextKeyword>().map(|keyword| keyword.0)
+    }
+}
+
+/// An activity id stashed in a [`Context`] by [`RootActivityContextExt::with_root_activity_id`].
+#[derive(Clone, Copy, Debug)]
+struct ContextRootActivity([u8; 16]);
+
+/// Methods for correlating the spans started in a [`Context`] with an external id.
+pub trait RootActivityContextExt {
+    /// Returns a copy of this context with `activity_id` stashed in it, such as an incoming
+    /// request id, as the big-endian bytes of a GUID.
+    /// When the exporter was built with
+    /// [`ExporterBuilder::with_root_activity_from_context`](crate::spans::ExporterBuilder::with_root_activity_from_context),
+    /// the realtime exporter uses it as the activity id of root spans started with this context
+    /// as their parent context, instead of the id derived from the span id.
+    /// Child spans keep their derived activity ids.
+    fn with_root_activity_id(&self, activity_id: [u8; 16]) -> Self;
+
+    /// The activity id stashed in this context by [`Self::with_root_activity_id`], if any.
+    fn root_activity_id(&self) -> Option<[u8; 16]>;
+}
+
+impl RootActivityContextExt for Context {
+    fn with_root_activity_id(&self, activity_id: [u8; 16]) -> Self {
+        self.with_value(ContextRootActivity(activity_id))
+    }
+
+    fn root_activity_id(&self) -> Option<[u8; 16]> {
+        self.get::<ContextRootActivity>().map(|activity| activity.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(target_os = "windows")]
+    fn context_keyword() {
+        assert_eq!(Context::new().keyword(), None);
+
+        let cx = Context::new().with_keyword(0x10);
+        assert_eq!(cx.keyword(), Some(0x10));
+        assert_eq!(cx.with_keyword(0x20).keyword(), Some(0x20));
+    }
+
+    #[test]
+    fn context_root_activity() {
+        assert_eq!(Context::new().root_activity_id(), None);
+
+        let cx = Context::new().with_root_activity_id([1; 16]);
+        assert_eq!(cx.root_activity_id(), Some([1; 16]));
+        #[cfg(target_os = "windows")]
+        assert_eq!(cx.with_keyword(0x20).root_activity_id(), Some([1; 16]));
+    }
+}
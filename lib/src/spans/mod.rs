@@ -1,5 +1,11 @@
 mod builder;
 mod batch_exporter;
+mod context;
 pub mod realtime_tracer;
+mod propagation;
+mod provider;
 
 pub use builder::*;
+pub use context::*;
+pub use propagation::*;
+pub use provider::*;
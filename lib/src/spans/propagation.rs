@@ -0,0 +1,57 @@
+use opentelemetry::{
+    trace::{SpanContext, TraceContextExt},
+    Context,
+};
+
+/// Format a span context as a [W3C `traceparent`](https://www.w3.org/TR/trace-context/#traceparent-header)
+/// header value. Returns `None` if the span context is not valid.
+pub fn format_traceparent(span_context: &SpanContext) -> Option<String> {
+    if !span_context.is_valid() {
+        return None;
+    }
+
+    Some(format!(
+        "00-{:032x}-{:016x}-{:02x}",
+        span_context.trace_id(),
+        span_context.span_id(),
+        span_context.trace_flags().to_u8()
+    ))
+}
+
+/// Get the [W3C `traceparent`](https://www.w3.org/TR/trace-context/#traceparent-header)
+/// header value for the currently active span, for manually propagating the span's context
+/// to outgoing requests.
+/// Returns `None` if there is no active span.
+pub fn current_traceparent() -> Option<String> {
+    format_traceparent(Context::current().span().span_context())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spans::new_exporter;
+    use opentelemetry_api::trace::{Tracer, TracerProvider};
+
+    #[test]
+    fn traceparent() {
+        assert_eq!(current_traceparent(), None);
+
+        let provider = new_exporter("my_provider_name").build_span_exporter();
+        let tracer = provider.tracer("test");
+
+        tracer.in_span("span", |cx| {
+            let traceparent = current_traceparent().unwrap();
+            let span_context = cx.span().span_context().clone();
+
+            let parts: Vec<&str> = traceparent.split('-').collect();
+            assert_eq!(parts.len(), 4);
+            assert_eq!(parts[0], "00");
+            assert_eq!(parts[1], format!("{:032x}", span_context.trace_id()));
+            assert_eq!(parts[2], format!("{:016x}", span_context.span_id()));
+            assert_eq!(parts[3], "01");
+            assert!(traceparent
+                .chars()
+                .all(|c| c == '-' || c.is_ascii_digit() || ('a'..='f').contains(&c)));
+        });
+    }
+}
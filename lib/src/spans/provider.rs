@@ -0,0 +1,448 @@
+use crate::common::InstallError;
+use crate::exporter_traits::*;
+use crate::spans::realtime_tracer::*;
+#[allow(unused_imports)]
+use crate::{etw, user_events};
+use opentelemetry::{
+    global::{self, BoxedSpan, BoxedTracer},
+    trace::{SpanBuilder, SpanContext, Status, TraceError, Tracer},
+    Context, KeyValue,
+};
+use std::borrow::Cow;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+use tracelogging_dynamic::Guid;
+
+#[cfg(target_os = "windows")]
+pub(crate) type PlatformEventExporter<C> = etw::EtwEventExporter<C>;
+#[cfg(target_os = "linux")]
+pub(crate) type PlatformEventExporter<C> = user_events::UserEventsExporter<C>;
+
+pub(crate) type RealtimeProvider<C> = RealtimeTracerProvider<C, PlatformEventExporter<C>>;
+
+/// A tracer from the provider most recently set as the global tracer provider.
+/// Tracers don't keep their provider alive, so this is used to detect whether
+/// the provider is still installed or has since been shut down.
+static GLOBAL_TRACER: Mutex<Option<ExporterTracer>> = Mutex::new(None);
+
+pub(crate) enum ProviderInner {
+    Realtime(RealtimeProvider<DefaultKeywordLevelProvider>),
+    RealtimeCustom(RealtimeProvider<Box<dyn KeywordLevelProvider>>),
+    // Shutdown replaces the SDK provider, since dropping it is the only way to shut down its exporter
+    Batch(Mutex<opentelemetry_sdk::trace::TracerProvider>),
+}
+
+/// A tracer provider that instrumentation holds directly, instead of going through the
+/// global tracer provider. Each one registers its own ETW or user_events provider,
+/// so libraries in the same process can log to differently named providers.
+/// Create one by calling [`ExporterBuilder::build_span_exporter`].
+pub type ScopedTracerProvider = ExporterTracerProvider;
+
+/// A tracer provider for this exporter that has not been set as the
+/// [global tracer provider](https://docs.rs/opentelemetry_api/latest/opentelemetry_api/global/index.html).
+/// Create one by calling [`ExporterBuilder::build_span_exporter`].
+///
+/// The ETW or user_events provider is registered for as long as this value
+/// (and any spans created from it) are alive.
+pub struct ExporterTracerProvider {
+    provider: ProviderInner,
+    settings: ExporterSettings,
+    spans_enabled: SpansEnabled,
+}
+
+/// The parts of the exporter's configuration that can be inspected
+/// from an [`ExporterTracerProvider`].
+pub(crate) struct ExporterSettings {
+    common_schema: bool,
+    json: bool,
+    provider_id: Guid,
+    suppressed_spans: Arc<AtomicU64>,
+}
+
+impl ExporterSettings {
+    pub(crate) fn new<C: KeywordLevelProvider>(
+        exporter_config: &ExporterConfig<C>,
+        provider_id: Guid,
+    ) -> Self {
+        ExporterSettings {
+            provider_id,
+            common_schema: exporter_config.get_export_common_schema_event(),
+            json: exporter_config.get_export_as_json(),
+            suppressed_spans: exporter_config.get_suppressed_spans().clone(),
+        }
+    }
+}
+
+impl ExporterTracerProvider {
+    pub(crate) fn new(
+        provider: ProviderInner,
+        settings: ExporterSettings,
+        spans_enabled: SpansEnabled,
+    ) -> Self {
+        ExporterTracerProvider {
+            provider,
+            settings,
+            spans_enabled,
+        }
+    }
+
+    /// Whether spans are exported as Common Schema events.
+    pub fn exports_common_schema(&self) -> bool {
+        self.settings.common_schema
+    }
+
+    /// Whether span start and end events are exported as spans start and end,
+    /// rather than batched after each span ends.
+    pub fn exports_realtime(&self) -> bool {
+        matches!(
+            self.provider,
+            ProviderInner::Realtime(_) | ProviderInner::RealtimeCustom(_)
+        )
+    }
+
+    /// Whether span attributes are exported as a JSON payload.
+    pub fn exports_json(&self) -> bool {
+        self.settings.json
+    }
+
+    /// The ID of the ETW provider that this tracer provider registered.
+    pub fn provider_id(&self) -> Guid {
+        self.settings.provider_id
+    }
+
+    /// The number of spans that weren't exported because no session had the provider enabled.
+    /// This is reset each time a `SuppressedSpans` event is logged, if
+    /// [`ExporterBuilder::with_suppressed_spans_event`](crate::spans::ExporterBuilder::with_suppressed_spans_event)
+    /// is set.
+    pub fn suppressed_count(&self) -> u64 {
+        self.settings.suppressed_spans.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn spans_enabled(&self) -> SpansEnabled {
+        self.spans_enabled.clone()
+    }
+
+    pub(crate) fn suppressed_spans(&self) -> Arc<AtomicU64> {
+        self.settings.suppressed_spans.clone()
+    }
+
+    /// Stop exporting spans and unregister the ETW or user_events provider.
+    /// For batched spans, this exports any spans that are still queued first, and
+    /// returns the errors that occurred while doing so.
+    pub fn shutdown(&self) -> Vec<TraceError> {
+        match &self.provider {
+            ProviderInner::Realtime(provider) => provider.shutdown(),
+            ProviderInner::RealtimeCustom(provider) => provider.shutdown(),
+            ProviderInner::Batch(provider) => {
+                let mut provider = provider
+                    .lock()
+                    .unwrap_or_else(|poisoned| poisoned.into_inner());
+                let errors = provider
+                    .force_flush()
+                    .into_iter()
+                    .filter_map(|result| result.err())
+                    .collect();
+
+                // Dropping the SDK provider shuts down the exporter, and tracers created from it stop recording.
+                // It's replaced with one that never samples so tracers created afterwards don't record either.
+                *provider = opentelemetry_sdk::trace::TracerProvider::builder()
+                    .with_config(
+                        opentelemetry_sdk::trace::config()
+                            .with_sampler(opentelemetry_sdk::trace::Sampler::AlwaysOff),
+                    )
+                    .build();
+
+                errors
+            }
+        }
+    }
+
+    /// Set the wrapped provider as the global tracer provider.
+    /// The concrete provider type is handed to the global so that spans are only boxed once.
+    ///
+    /// If a provider from this exporter is still installed, [`InstallError::AlreadyInstalled`]
+    /// is returned. The existing provider is only replaced if `replace_existing` is true.
+    pub(crate) fn set_as_global(self, replace_existing: bool) -> Result<(), InstallError> {
+        let mut global_tracer = GLOBAL_TRACER
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        let result = match global_tracer.as_ref() {
+            Some(tracer) if tracer.is_provider_alive() => Err(InstallError::AlreadyInstalled),
+            _ => Ok(()),
+        };
+
+        if result.is_err() && !replace_existing {
+            return result;
+        }
+
+        *global_tracer = Some(opentelemetry_api::trace::TracerProvider::tracer(
+            &self,
+            "opentelemetry-etw-user_events",
+        ));
+
+        match self.provider {
+            ProviderInner::Realtime(provider) => {
+                let _ = global::set_tracer_provider(provider);
+            }
+            ProviderInner::RealtimeCustom(provider) => {
+                let _ = global::set_tracer_provider(provider);
+            }
+            ProviderInner::Batch(provider) => {
+                let _ = global::set_tracer_provider(
+                    provider
+                        .into_inner()
+                        .unwrap_or_else(|poisoned| poisoned.into_inner()),
+                );
+            }
+        }
+
+        result
+    }
+}
+
+impl opentelemetry_api::trace::TracerProvider for ExporterTracerProvider {
+    type Tracer = ExporterTracer;
+
+    fn versioned_tracer(
+        &self,
+        name: impl Into<Cow<'static, str>>,
+        version: Option<&'static str>,
+        schema_url: Option<&'static str>,
+    ) -> Self::Tracer {
+        ExporterTracer(match &self.provider {
+            ProviderInner::Realtime(provider) => {
+                TracerInner::Realtime(provider.versioned_tracer(name, version, schema_url))
+            }
+            ProviderInner::RealtimeCustom(provider) => {
+                TracerInner::RealtimeCustom(provider.versioned_tracer(name, version, schema_url))
+            }
+            ProviderInner::Batch(provider) => TracerInner::Batch(
+                provider
+                    .lock()
+                    .unwrap_or_else(|poisoned| poisoned.into_inner())
+                    .versioned_tracer(name, version, schema_url),
+            ),
+        })
+    }
+}
+
+/// The global tracer returned by [`ExporterBuilder::install_span_exporter`], together with
+/// a handle to the installed exporter, so its state can be checked without downcasting.
+/// The handle doesn't keep the exporter alive.
+pub struct EtwTracer {
+    tracer: BoxedTracer,
+    spans_enabled: SpansEnabled,
+    provider_id: Guid,
+    suppressed_spans: Arc<AtomicU64>,
+}
+
+impl EtwTracer {
+    pub(crate) fn new(
+        tracer: BoxedTracer,
+        spans_enabled: SpansEnabled,
+        provider_id: Guid,
+        suppressed_spans: Arc<AtomicU64>,
+    ) -> Self {
+        EtwTracer {
+            tracer,
+            spans_enabled,
+            provider_id,
+            suppressed_spans,
+        }
+    }
+
+    /// Start a span, run `f` with it as the current span, and end the span.
+    /// Unlike [`Tracer::in_span`], this doesn't need the trait to be in scope.
+    pub fn in_span<T, F, N>(&self, name: N, f: F) -> T
+    where
+        F: FnOnce(Context) -> T,
+        N: Into<Cow<'static, str>>,
+    {
+        self.tracer.in_span(name, f)
+    }
+
+    /// Whether a listener has enabled the level and keywords of span events.
+    /// This is false once the exporter has been shut down or replaced as the global
+    /// tracer provider, since its spans are no longer exported.
+    pub fn is_enabled(&self) -> bool {
+        (self.spans_enabled)()
+    }
+
+    /// The ID of the ETW provider that the installed exporter registered.
+    pub fn provider_id(&self) -> Guid {
+        self.provider_id
+    }
+
+    /// The number of spans that weren't exported because no session had the provider enabled.
+    /// See [`ExporterTracerProvider::suppressed_count`].
+    pub fn suppressed_count(&self) -> u64 {
+        self.suppressed_spans.load(Ordering::Relaxed)
+    }
+}
+
+impl Tracer for EtwTracer {
+    type Span = BoxedSpan;
+
+    fn build_with_context(&self, builder: SpanBuilder, parent_cx: &Context) -> Self::Span {
+        self.tracer.build_with_context(builder, parent_cx)
+    }
+}
+
+enum TracerInner {
+    Realtime(RealtimeTracer<PlatformEventExporter<DefaultKeywordLevelProvider>>),
+    RealtimeCustom(RealtimeTracer<PlatformEventExporter<Box<dyn KeywordLevelProvider>>>),
+    Batch(opentelemetry_sdk::trace::Tracer),
+}
+
+/// A tracer created by an [`ExporterTracerProvider`].
+pub struct ExporterTracer(TracerInner);
+
+impl ExporterTracer {
+    fn is_provider_alive(&self) -> bool {
+        match &self.0 {
+            TracerInner::Realtime(tracer) => tracer.is_provider_alive(),
+            TracerInner::RealtimeCustom(tracer) => tracer.is_provider_alive(),
+            TracerInner::Batch(tracer) => tracer.provider().is_some(),
+        }
+    }
+}
+
+impl opentelemetry_api::trace::Tracer for ExporterTracer {
+    type Span = ExporterSpan;
+
+    fn build_with_context(&self, builder: SpanBuilder, parent_cx: &Context) -> Self::Span {
+        ExporterSpan(match &self.0 {
+            TracerInner::Realtime(tracer) => {
+                SpanInner::Realtime(tracer.build_with_context(builder, parent_cx))
+            }
+            TracerInner::RealtimeCustom(tracer) => {
+                SpanInner::RealtimeCustom(tracer.build_with_context(builder, parent_cx))
+            }
+            TracerInner::Batch(tracer) => {
+                SpanInner::Batch(tracer.build_with_context(builder, parent_cx))
+            }
+        })
+    }
+}
+
+enum SpanInner {
+    Realtime(RealtimeSpan<PlatformEventExporter<DefaultKeywordLevelProvider>>),
+    RealtimeCustom(RealtimeSpan<PlatformEventExporter<Box<dyn KeywordLevelProvider>>>),
+    Batch(opentelemetry_sdk::trace::Span),
+}
+
+/// A span created by an [`ExporterTracer`].
+pub struct ExporterSpan(SpanInner);
+
+impl ExporterSpan {
+    fn as_span(&self) -> &dyn ObjectSafeSpan {
+        match &self.0 {
+            SpanInner::Realtime(span) => span,
+            SpanInner::RealtimeCustom(span) => span,
+            SpanInner::Batch(span) => span,
+        }
+    }
+
+    fn as_span_mut(&mut self) -> &mut dyn ObjectSafeSpan {
+        match &mut self.0 {
+            SpanInner::Realtime(span) => span,
+            SpanInner::RealtimeCustom(span) => span,
+            SpanInner::Batch(span) => span,
+        }
+    }
+}
+
+/// The object-safe subset of [`opentelemetry_api::trace::Span`], so that
+/// [`ExporterSpan`] only needs to match on its span type once per call.
+trait ObjectSafeSpan {
+    fn add_event_with_timestamp(
+        &mut self,
+        name: Cow<'static, str>,
+        timestamp: SystemTime,
+        attributes: Vec<KeyValue>,
+    );
+    fn span_context(&self) -> &SpanContext;
+    fn is_recording(&self) -> bool;
+    fn set_attribute(&mut self, attribute: KeyValue);
+    fn set_status(&mut self, status: Status);
+    fn update_name(&mut self, new_name: Cow<'static, str>);
+    fn end_with_timestamp(&mut self, timestamp: SystemTime);
+}
+
+impl<T: opentelemetry_api::trace::Span> ObjectSafeSpan for T {
+    fn add_event_with_timestamp(
+        &mut self,
+        name: Cow<'static, str>,
+        timestamp: SystemTime,
+        attributes: Vec<KeyValue>,
+    ) {
+        opentelemetry_api::trace::Span::add_event_with_timestamp(self, name, timestamp, attributes)
+    }
+
+    fn span_context(&self) -> &SpanContext {
+        opentelemetry_api::trace::Span::span_context(self)
+    }
+
+    fn is_recording(&self) -> bool {
+        opentelemetry_api::trace::Span::is_recording(self)
+    }
+
+    fn set_attribute(&mut self, attribute: KeyValue) {
+        opentelemetry_api::trace::Span::set_attribute(self, attribute)
+    }
+
+    fn set_status(&mut self, status: Status) {
+        opentelemetry_api::trace::Span::set_status(self, status)
+    }
+
+    fn update_name(&mut self, new_name: Cow<'static, str>) {
+        opentelemetry_api::trace::Span::update_name(self, new_name)
+    }
+
+    fn end_with_timestamp(&mut self, timestamp: SystemTime) {
+        opentelemetry_api::trace::Span::end_with_timestamp(self, timestamp)
+    }
+}
+
+impl opentelemetry_api::trace::Span for ExporterSpan {
+    fn add_event_with_timestamp<T>(
+        &mut self,
+        name: T,
+        timestamp: SystemTime,
+        attributes: Vec<KeyValue>,
+    ) where
+        T: Into<Cow<'static, str>>,
+    {
+        self.as_span_mut()
+            .add_event_with_timestamp(name.into(), timestamp, attributes)
+    }
+
+    fn span_context(&self) -> &SpanContext {
+        self.as_span().span_context()
+    }
+
+    fn is_recording(&self) -> bool {
+        self.as_span().is_recording()
+    }
+
+    fn set_attribute(&mut self, attribute: KeyValue) {
+        self.as_span_mut().set_attribute(attribute)
+    }
+
+    fn set_status(&mut self, status: Status) {
+        self.as_span_mut().set_status(status)
+    }
+
+    fn update_name<T>(&mut self, new_name: T)
+    where
+        T: Into<Cow<'static, str>>,
+    {
+        self.as_span_mut().update_name(new_name.into())
+    }
+
+    fn end_with_timestamp(&mut self, timestamp: SystemTime) {
+        self.as_span_mut().end_with_timestamp(timestamp)
+    }
+}
@@ -1,14 +1,22 @@
 use crate::spans::builder::ProviderGroup;
+use crate::spans::{KeywordContextExt, RootActivityContextExt};
 #[allow(unused_imports)]
 use crate::etw;
 use crate::exporter_traits::*;
-use crate::common::EtwSpan;
+use crate::common::{
+    activities::Activities,
+    activity_stack::ActivityStack,
+    children::{ChildTracker, MAX_TRACKED_PARENTS},
+    registry::ProviderRegistration,
+    EtwSpan,
+};
 #[allow(unused_imports)]
 use crate::user_events;
 use opentelemetry::InstrumentationLibrary;
 use opentelemetry::{
     trace::{
-        Event, SpanBuilder, SpanContext, SpanId, SpanKind, TraceContextExt, TraceFlags, TraceState,
+        Event, SpanBuilder, SpanContext, SpanId, SpanKind, TraceContextExt, TraceError, TraceFlags,
+        TraceState,
     },
     Context,
 };
@@ -18,27 +26,150 @@ use opentelemetry_sdk::{
     trace::{EvictedHashMap, EvictedQueue},
 };
 use std::borrow::Cow;
-use std::sync::{atomic::*, Arc, Weak};
+use std::cell::{Cell, RefCell};
+use std::marker::PhantomData;
+use std::sync::{atomic::*, Arc, Mutex, Weak};
 use std::time::SystemTime;
 #[allow(unused_imports)]
 use tracelogging_dynamic::*;
 
+/// The span event attribute with the number of times a deduplicated event was added in a row.
+const REPEAT_COUNT_KEY: &str = "RepeatCount";
+
+thread_local! {
+    // The number of live SuppressGuards on this thread.
+    static SUPPRESS_DEPTH: Cell<usize> = const { Cell::new(0) };
+    // The activity ids replaced by thread activity scoped spans on this thread.
+    static ACTIVITY_STACK: RefCell<ActivityStack<Guid>> = const { RefCell::new(ActivityStack::new()) };
+}
+
+fn is_export_suppressed() -> bool {
+    SUPPRESS_DEPTH.with(|depth| depth.get() > 0)
+}
+
+/// Suppresses real-time span events on the current thread until it is dropped.
+/// Returned by [`RealtimeTracerProvider::suppress`].
+#[must_use = "span events are only suppressed while the guard is held"]
+pub struct SuppressGuard {
+    // The guard has to be dropped on the thread it suppresses.
+    _not_send: PhantomData<*const ()>,
+}
+
+impl Drop for SuppressGuard {
+    fn drop(&mut self) {
+        SUPPRESS_DEPTH.with(|depth| depth.set(depth.get() - 1));
+    }
+}
+
+/// Span behavior that depends on how the exporter was configured.
+#[derive(Default)]
+pub(crate) struct RealtimeSpanOptions {
+    /// Keep a name-only copy of each span event in the span data so it can be summarized
+    /// or counted at span end.
+    pub(crate) track_event_names: bool,
+    /// Only log a single event for the span when it ends.
+    pub(crate) single_span_event: bool,
+    /// Set the span's activity id as the thread's ETW activity id until the span ends.
+    pub(crate) thread_activity_scope: bool,
+    /// Include the trace id in the span's activity id.
+    pub(crate) trace_scoped_activities: bool,
+    /// Hold back each span event until a different event is added or the span ends,
+    /// so that consecutive duplicates are logged once with a repeat count.
+    pub(crate) event_dedup: bool,
+    /// Use the activity id stashed in the parent context as the activity id of root spans.
+    pub(crate) root_activity_from_context: bool,
+    /// Count each span's unended children, to log with the span's end event.
+    pub(crate) child_tracker: Option<ChildTracker>,
+    /// Read the current time from this clock instead of the system time.
+    pub(crate) clock: Option<Arc<dyn Clock>>,
+    /// The name of tracers that are created without one.
+    pub(crate) default_tracer_name: Option<Arc<str>>,
+}
+
+impl RealtimeSpanOptions {
+    pub(crate) fn new<C: KeywordLevelProvider>(exporter_config: &ExporterConfig<C>) -> Self {
+        RealtimeSpanOptions {
+            track_event_names: exporter_config.get_export_event_summary()
+                || (exporter_config.get_export_common_schema_event()
+                    && exporter_config.get_export_count_fields()),
+            single_span_event: exporter_config.get_single_span_event(),
+            thread_activity_scope: exporter_config.get_thread_activity_scope(),
+            trace_scoped_activities: exporter_config.get_trace_scoped_activities(),
+            event_dedup: exporter_config.get_event_dedup(),
+            root_activity_from_context: exporter_config.get_root_activity_from_context(),
+            child_tracker: if exporter_config.get_child_tracking() {
+                Some(ChildTracker::new(MAX_TRACKED_PARENTS))
+            } else {
+                None
+            },
+            clock: exporter_config.get_clock().cloned(),
+            default_tracer_name: exporter_config.get_default_tracer_name().cloned(),
+        }
+    }
+
+    fn now(&self) -> SystemTime {
+        self.clock
+            .as_ref()
+            .map_or_else(SystemTime::now, |clock| clock.now())
+    }
+}
+
+// The values that a span takes from the context it was started with, stashed there by
+// `KeywordContextExt` and `RootActivityContextExt`.
+struct ParentContextValues {
+    keyword: Option<u64>,
+    root_activity_id: Option<[u8; 16]>,
+}
+
+impl ParentContextValues {
+    fn from_context(cx: &Context) -> Self {
+        ParentContextValues {
+            keyword: cx.keyword(),
+            root_activity_id: cx.root_activity_id(),
+        }
+    }
+}
+
 pub struct RealtimeSpan<E: EventExporter> {
     event_exporter: Weak<E>,
     span_data: SpanData,
     ended: AtomicBool,
+    span_options: Arc<RealtimeSpanOptions>,
+    set_thread_activity: bool,
+    remote_parent: bool,
+    context_keyword: Option<u64>,
+    root_activity_id: Option<[u8; 16]>,
+    // The last span event and the number of times it was added in a row, when deduplicating.
+    last_event: Option<(Event, u32)>,
+    // Whether the span was counted as an active child of its parent.
+    counted_by_parent: bool,
+    active_children: Option<u32>,
 }
 
 impl<E: EventExporter> RealtimeSpan<E> {
     fn build(
         builder: SpanBuilder,
         otel_config: Weak<opentelemetry_sdk::trace::Config>,
+        span_options: Arc<RealtimeSpanOptions>,
         event_exporter: Weak<E>,
         parent_span: Option<SpanRef>,
+        parent_values: ParentContextValues,
         instrumentation_lib: InstrumentationLibrary,
     ) -> Self {
+        let ParentContextValues {
+            keyword: context_keyword,
+            root_activity_id,
+        } = parent_values;
+        let remote_parent = parent_span
+            .as_ref()
+            .map_or(false, |s| s.span_context().is_remote());
         let parent_span_id =
             parent_span.map_or_else(|| SpanId::INVALID, |s| s.span_context().span_id());
+        // Only root spans take the activity id from the context, since it is inherited by
+        // the contexts of their children.
+        let root_activity_id = root_activity_id.filter(|_| {
+            span_options.root_activity_from_context && parent_span_id == SpanId::INVALID
+        });
         let strong = otel_config.upgrade();
         let otel_config = if let Some(config) = &strong {
             config.as_ref()
@@ -75,9 +206,17 @@ impl<E: EventExporter> RealtimeSpan<E> {
                 links: EvictedQueue::new(otel_config.span_limits.max_links_per_span),
                 status: builder.status,
                 resource: otel_config.resource.clone(), // TODO: This clone is really inefficient
-                instrumentation_lib,                    // This is never used
+                instrumentation_lib,
             },
             ended: AtomicBool::new(false),
+            set_thread_activity: false,
+            remote_parent,
+            context_keyword,
+            root_activity_id,
+            last_event: None,
+            counted_by_parent: false,
+            active_children: None,
+            span_options,
         };
 
         for attribute in attributes {
@@ -100,16 +239,81 @@ impl<E: EventExporter> RealtimeSpan<E> {
     }
 
     fn start(&mut self) {
-        self.span_data.start_time = SystemTime::now();
+        self.span_data.start_time = self.span_options.now();
         self.span_data.end_time = self.span_data.start_time; // The spec requires this, even though it doesn't make sense.
 
-        if let Some(event_exporter) = self.event_exporter.upgrade() {
+        if self.span_options.thread_activity_scope {
+            let activities = Activities::generate(
+                &self.span_data.span_context.span_id(),
+                &self.span_data.parent_span_id,
+                &self.span_data.span_context.trace_id(),
+                self.span_options.trace_scoped_activities,
+            )
+            .with_activity_id(self.root_activity_id);
+            let prior_activity_id = Provider::set_current_thread_activity_id(&Guid::from_bytes_be(
+                &activities.activity_id,
+            ));
+            ACTIVITY_STACK.with(|stack| {
+                stack
+                    .borrow_mut()
+                    .push(self.span_data.span_context.span_id(), prior_activity_id)
+            });
+            self.set_thread_activity = true;
+        }
+
+        // Remote parents never end in this process, so there is nothing to count for them.
+        if let Some(child_tracker) = &self.span_options.child_tracker {
+            if self.span_data.parent_span_id != SpanId::INVALID && !self.remote_parent {
+                self.counted_by_parent = child_tracker.child_started(self.span_data.parent_span_id);
+            }
+        }
+
+        if self.span_options.single_span_event {
+            return;
+        }
+
+        if let Some(event_exporter) = self.exporter() {
             let _ = event_exporter.log_span_start(self);
         }
     }
+
+    // The exporter to log the span's events with, unless export is suppressed on this thread.
+    fn exporter(&self) -> Option<Arc<E>> {
+        if is_export_suppressed() {
+            None
+        } else {
+            self.event_exporter.upgrade()
+        }
+    }
+
+    fn log_span_event(&self, event: Event) {
+        if let Some(event_exporter) = self.exporter() {
+            let _ = event_exporter.log_span_event(event, self);
+        }
+    }
+
+    // Log the held back span event, with a repeat count if it was added more than once.
+    fn flush_last_event(&mut self) {
+        if let Some((mut event, count)) = self.last_event.take() {
+            if count > 1 {
+                event
+                    .attributes
+                    .push(opentelemetry::KeyValue::new(REPEAT_COUNT_KEY, count as i64));
+            }
+            self.log_span_event(event);
+        }
+    }
 }
 
 impl<E: EventExporter> opentelemetry_api::trace::Span for RealtimeSpan<E> {
+    fn add_event<N>(&mut self, name: N, attributes: Vec<opentelemetry::KeyValue>)
+    where
+        N: Into<std::borrow::Cow<'static, str>>,
+    {
+        let timestamp = self.span_options.now();
+        self.add_event_with_timestamp(name, timestamp, attributes);
+    }
+
     fn add_event_with_timestamp<N>(
         &mut self,
         name: N,
@@ -120,9 +324,34 @@ impl<E: EventExporter> opentelemetry_api::trace::Span for RealtimeSpan<E> {
     {
         let event = Event::new(name, timestamp, attributes, 0);
 
-        if let Some(event_exporter) = self.event_exporter.upgrade() {
-            let _ = event_exporter.log_span_event(event, self);
+        if self.span_options.track_event_names {
+            self.span_data.events.extend(std::iter::once(Event::new(
+                event.name.clone(),
+                event.timestamp,
+                Vec::new(),
+                0,
+            )));
         }
+
+        if self.span_options.event_dedup {
+            if let Some((last_event, count)) = &mut self.last_event {
+                if last_event.name == event.name && last_event.attributes == event.attributes {
+                    *count += 1;
+                    return;
+                }
+            }
+
+            self.flush_last_event();
+            self.last_event = Some((event, 1));
+            return;
+        }
+
+        self.log_span_event(event);
+    }
+
+    fn end(&mut self) {
+        let timestamp = self.span_options.now();
+        self.end_with_timestamp(timestamp);
     }
 
     fn end_with_timestamp(&mut self, timestamp: std::time::SystemTime) {
@@ -132,9 +361,32 @@ impl<E: EventExporter> opentelemetry_api::trace::Span for RealtimeSpan<E> {
         let already_ended = self.ended.swap(true, Ordering::Acquire);
 
         if !already_ended {
-            if let Some(event_exporter) = self.event_exporter.upgrade() {
+            self.flush_last_event();
+
+            if let Some(child_tracker) = &self.span_options.child_tracker {
+                self.active_children =
+                    Some(child_tracker.active_children(self.span_data.span_context.span_id()));
+            }
+
+            if let Some(event_exporter) = self.exporter() {
                 let _ = event_exporter.log_span_end(self);
             }
+
+            if self.counted_by_parent {
+                if let Some(child_tracker) = &self.span_options.child_tracker {
+                    child_tracker.child_ended(self.span_data.parent_span_id);
+                }
+            }
+
+            // Spans started within this one that are still active restore the prior id when they end instead.
+            if self.set_thread_activity {
+                let span_id = self.span_data.span_context.span_id();
+                if let Some(prior_activity_id) =
+                    ACTIVITY_STACK.with(|stack| stack.borrow_mut().pop(span_id))
+                {
+                    Provider::set_current_thread_activity_id(&prior_activity_id);
+                }
+            }
         }
     }
 
@@ -178,10 +430,27 @@ impl<E: EventExporter> EtwSpan for RealtimeSpan<E> {
     fn get_span_data(&self) -> &SpanData {
         &self.span_data
     }
+
+    fn is_remote_parent(&self) -> bool {
+        self.remote_parent
+    }
+
+    fn get_context_keyword(&self) -> Option<u64> {
+        self.context_keyword
+    }
+
+    fn get_root_activity_id(&self) -> Option<[u8; 16]> {
+        self.root_activity_id
+    }
+
+    fn get_active_children(&self) -> Option<u32> {
+        self.active_children
+    }
 }
 
 pub struct RealtimeTracer<E: EventExporter> {
     otel_config: Weak<opentelemetry_sdk::trace::Config>,
+    span_options: Arc<RealtimeSpanOptions>,
     event_exporter: Weak<E>,
     instrumentation_lib: InstrumentationLibrary,
 }
@@ -189,15 +458,22 @@ pub struct RealtimeTracer<E: EventExporter> {
 impl<E: EventExporter> RealtimeTracer<E> {
     fn new(
         otel_config: Weak<opentelemetry_sdk::trace::Config>,
+        span_options: Arc<RealtimeSpanOptions>,
         event_exporter: Weak<E>,
         instrumentation_lib: InstrumentationLibrary,
     ) -> Self {
         RealtimeTracer {
             otel_config,
+            span_options,
             event_exporter,
             instrumentation_lib,
         }
     }
+
+    /// Whether the provider that created this tracer has not been dropped yet.
+    pub(crate) fn is_provider_alive(&self) -> bool {
+        self.event_exporter.strong_count() > 0
+    }
 }
 
 impl<E: EventExporter> opentelemetry_api::trace::Tracer for RealtimeTracer<E> {
@@ -213,8 +489,10 @@ impl<E: EventExporter> opentelemetry_api::trace::Tracer for RealtimeTracer<E> {
         let mut span = RealtimeSpan::build(
             builder,
             self.otel_config.clone(),
+            self.span_options.clone(),
             self.event_exporter.clone(),
             parent_span,
+            ParentContextValues::from_context(parent_cx),
             self.instrumentation_lib.clone(),
         );
         span.start();
@@ -222,43 +500,64 @@ impl<E: EventExporter> opentelemetry_api::trace::Tracer for RealtimeTracer<E> {
     }
 }
 
+// Record the provider's registration, reporting it to the global error handler
+// if the same provider is already registered in this process.
+fn register_provider(key: String) -> ProviderRegistration {
+    let (registration, err) = ProviderRegistration::new(key);
+    if let Some(err) = err {
+        opentelemetry::global::handle_error(TraceError::Other(Box::new(err)));
+    }
+    registration
+}
+
 pub struct RealtimeTracerProvider<C: KeywordLevelProvider, E: EventExporter> {
     otel_config: Arc<opentelemetry_sdk::trace::Config>,
-    event_exporter: Arc<E>,
+    span_options: Arc<RealtimeSpanOptions>,
+    // Taken by shutdown, so that tracers and spans stop finding the exporter.
+    event_exporter: Mutex<Option<Arc<E>>>,
     _x: core::marker::PhantomData<C>,
+    _registration: Option<ProviderRegistration>,
 }
 
 #[cfg(all(target_os = "windows"))]
 impl<C: KeywordLevelProvider> RealtimeTracerProvider<C, etw::EtwEventExporter<C>> {
     pub(crate) fn new(
         provider_name: &str,
-        provider_group: ProviderGroup,
+        provider_id: &Guid,
+        provider_group: &ProviderGroup,
         otel_config: opentelemetry_sdk::trace::Config,
         use_byte_for_bools: bool,
         exporter_config: ExporterConfig<C>,
     ) -> Self {
         let mut options = Provider::options();
         if let ProviderGroup::Windows(guid) = provider_group {
-            options = *options.group_id(&guid);
+            options = *options.group_id(guid);
         }
 
-        let provider = Arc::pin(Provider::new(provider_name, &options));
-        unsafe {
-            provider.as_ref().register();
-        }
+        let (provider, lifecycle) =
+            etw::register_provider(provider_name, provider_id, &mut options, &exporter_config);
+        let registration =
+            register_provider(String::from_utf8_lossy(&provider_id.to_utf8_bytes()).into_owned());
+
+        let span_options = Arc::new(RealtimeSpanOptions::new(&exporter_config));
+        let event_exporter = Arc::new(etw::EtwEventExporter::new(
+            provider,
+            lifecycle,
+            exporter_config,
+            if use_byte_for_bools {
+                InType::U8
+            } else {
+                InType::Bool32
+            },
+        ));
+        let _ = event_exporter.log_provider_started();
 
         RealtimeTracerProvider {
             otel_config: Arc::new(otel_config),
-            event_exporter: Arc::new(etw::EtwEventExporter::new(
-                provider,
-                exporter_config,
-                if use_byte_for_bools {
-                    InType::U8
-                } else {
-                    InType::Bool32
-                },
-            )),
+            span_options,
+            event_exporter: Mutex::new(Some(event_exporter)),
             _x: core::marker::PhantomData,
+            _registration: Some(registration),
         }
     }
 }
@@ -267,26 +566,93 @@ impl<C: KeywordLevelProvider> RealtimeTracerProvider<C, etw::EtwEventExporter<C>
 impl<C: KeywordLevelProvider> RealtimeTracerProvider<C, user_events::UserEventsExporter<C>> {
     pub(crate) fn new(
         provider_name: &str,
-        provider_group: ProviderGroup,
+        _provider_id: &Guid,
+        provider_group: &ProviderGroup,
         otel_config: opentelemetry_sdk::trace::Config,
         _use_byte_for_bools: bool,
         exporter_config: ExporterConfig<C>,
     ) -> Self {
         let mut options = eventheader_dynamic::Provider::new_options();
-        if let ProviderGroup::Linux(ref name) = provider_group {
-            options = *options.group_name(&name);
+        if let ProviderGroup::Linux(name) = provider_group {
+            options = *options.group_name(name);
         }
         let mut provider = eventheader_dynamic::Provider::new(provider_name, &options);
-        user_events::register_eventsets(&mut provider, &exporter_config);
+        let registered = user_events::register_eventsets(&mut provider, &exporter_config);
+        // Tracepoint names end with the group name, if there is one
+        let registration = register_provider(match provider_group {
+            ProviderGroup::Linux(name) => format!("{}G{}", provider_name, name),
+            _ => provider_name.to_owned(),
+        });
+
+        let span_options = Arc::new(RealtimeSpanOptions::new(&exporter_config));
+        let event_exporter = Arc::new(user_events::UserEventsExporter::new(
+            provider,
+            registered,
+            exporter_config,
+        ));
+        let _ = event_exporter.log_provider_started();
 
         RealtimeTracerProvider {
             otel_config: Arc::new(otel_config),
-            event_exporter: Arc::new(user_events::UserEventsExporter::new(Arc::new(provider), exporter_config)),
+            span_options,
+            event_exporter: Mutex::new(Some(event_exporter)),
             _x: core::marker::PhantomData,
+            _registration: Some(registration),
+        }
+    }
+}
+
+impl<C: KeywordLevelProvider, E: EventExporter> RealtimeTracerProvider<C, E> {
+    /// Stop exporting spans and unregister the ETW or user_events provider.
+    /// Spans created after this are not recorded, and spans that are still in flight
+    /// log nothing when they end. The provider is unregistered once the last in-flight
+    /// span event has been written.
+    /// Real-time spans are never buffered, so there is nothing to flush and no errors
+    /// are returned; the return value matches the batch exporter's shutdown.
+    pub fn shutdown(&self) -> Vec<TraceError> {
+        let mut event_exporter = self
+            .event_exporter
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        // Dropping the exporter unregisters its provider.
+        event_exporter.take();
+
+        Vec::new()
+    }
+
+    /// Stop logging span events on the current thread until the returned guard is dropped,
+    /// such as around a performance-critical loop, without shutting down the provider.
+    /// This applies to the spans of every tracer provider on the thread, and guards can nest.
+    /// A span that starts or ends while suppressed doesn't log that event, so a span
+    /// should start and end on the same side of the guard.
+    pub fn suppress(&self) -> SuppressGuard {
+        SUPPRESS_DEPTH.with(|depth| depth.set(depth.get() + 1));
+        SuppressGuard {
+            _not_send: PhantomData,
         }
     }
 }
 
+impl<C: KeywordLevelProvider, E: EventExporter + Send + Sync + 'static>
+    RealtimeTracerProvider<C, E>
+{
+    pub(crate) fn spans_enabled(&self) -> SpansEnabled {
+        let event_exporter = self
+            .event_exporter
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .as_ref()
+            .map(Arc::downgrade)
+            .unwrap_or_default();
+        Arc::new(move || {
+            event_exporter
+                .upgrade()
+                .map_or(false, |event_exporter| event_exporter.spans_enabled())
+        })
+    }
+}
+
 impl<C: KeywordLevelProvider, E: EventExporter> opentelemetry_api::trace::TracerProvider
     for RealtimeTracerProvider<C, E>
 {
@@ -309,7 +675,10 @@ impl<C: KeywordLevelProvider, E: EventExporter> opentelemetry_api::trace::Tracer
         let name = name.into();
         // Use default value if name is invalid empty string
         let component_name = if name.is_empty() {
-            Cow::Borrowed("opentelemetry-etw-user_events")
+            match &self.span_options.default_tracer_name {
+                Some(default_name) => Cow::Owned(default_name.to_string()),
+                None => Cow::Borrowed("opentelemetry-etw-user_events"),
+            }
         } else {
             name
         };
@@ -321,8 +690,595 @@ impl<C: KeywordLevelProvider, E: EventExporter> opentelemetry_api::trace::Tracer
 
         RealtimeTracer::new(
             Arc::downgrade(&self.otel_config),
-            Arc::downgrade(&self.event_exporter),
+            self.span_options.clone(),
+            self.event_exporter
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .as_ref()
+                .map(Arc::downgrade)
+                .unwrap_or_default(),
             instrumentation_lib,
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::events;
+    use opentelemetry::KeyValue;
+    use opentelemetry_api::trace::{
+        Span, Status, TraceContextExt, TraceId, Tracer, TracerProvider,
+    };
+    use opentelemetry_sdk::export::trace::ExportResult;
+
+    #[derive(Default)]
+    struct CountingExporter {
+        span_starts: AtomicUsize,
+        span_ends: AtomicUsize,
+        start_statuses: Mutex<Vec<Status>>,
+        span_events: Mutex<Vec<Event>>,
+        end_active_children: Mutex<Vec<(String, Option<u32>)>>,
+    }
+
+    impl EventExporter for CountingExporter {
+        fn enabled(&self, _level: u8, _keyword: u64) -> bool {
+            true
+        }
+
+        fn spans_enabled(&self) -> bool {
+            true
+        }
+
+        fn log_provider_started(&self) -> ExportResult {
+            Ok(())
+        }
+
+        fn log_spans_dropped(&self, _count: u64) -> ExportResult {
+            Ok(())
+        }
+
+        fn log_span_start<S>(&self, span: &S) -> ExportResult
+        where
+            S: opentelemetry_api::trace::Span + EtwSpan,
+        {
+            self.span_starts.fetch_add(1, Ordering::Relaxed);
+            self.start_statuses
+                .lock()
+                .unwrap()
+                .push(span.get_span_data().status.clone());
+            Ok(())
+        }
+
+        fn log_span_end<S>(&self, span: &S) -> ExportResult
+        where
+            S: opentelemetry_api::trace::Span + EtwSpan,
+        {
+            self.span_ends.fetch_add(1, Ordering::Relaxed);
+            self.end_active_children.lock().unwrap().push((
+                span.get_span_data().name.to_string(),
+                span.get_active_children(),
+            ));
+            Ok(())
+        }
+
+        fn log_span_event<S>(&self, event: Event, _span: &S) -> ExportResult
+        where
+            S: opentelemetry_api::trace::Span + EtwSpan,
+        {
+            self.span_events.lock().unwrap().push(event);
+            Ok(())
+        }
+
+        fn log_span_data(&self, _span_data: &SpanData) -> ExportResult {
+            Ok(())
+        }
+    }
+
+    fn test_provider(
+        span_options: RealtimeSpanOptions,
+    ) -> (
+        RealtimeTracerProvider<DefaultKeywordLevelProvider, CountingExporter>,
+        Arc<CountingExporter>,
+    ) {
+        let event_exporter = Arc::new(CountingExporter::default());
+        let provider = RealtimeTracerProvider {
+            otel_config: Arc::new(opentelemetry_sdk::trace::config()),
+            span_options: Arc::new(span_options),
+            event_exporter: Mutex::new(Some(event_exporter.clone())),
+            _x: core::marker::PhantomData,
+            _registration: None,
+        };
+        (provider, event_exporter)
+    }
+
+    fn count_span_events(single_span_event: bool) -> (usize, usize) {
+        let (provider, event_exporter) = test_provider(RealtimeSpanOptions {
+            single_span_event,
+            ..RealtimeSpanOptions::default()
+        });
+
+        provider.tracer("test").in_span("span", |_| {});
+
+        (
+            event_exporter.span_starts.load(Ordering::Relaxed),
+            event_exporter.span_ends.load(Ordering::Relaxed),
+        )
+    }
+
+    #[test]
+    fn suppress() {
+        let (provider, event_exporter) = test_provider(RealtimeSpanOptions::default());
+        let tracer = provider.tracer("test");
+
+        {
+            let _guard = provider.suppress();
+            {
+                let _nested = provider.suppress();
+                tracer.in_span("nested", |cx| cx.span().add_event("event", vec![]));
+            }
+            tracer.in_span("suppressed", |cx| cx.span().add_event("event", vec![]));
+        }
+        assert_eq!(event_exporter.span_starts.load(Ordering::Relaxed), 0);
+        assert_eq!(event_exporter.span_ends.load(Ordering::Relaxed), 0);
+        assert!(event_exporter.span_events.lock().unwrap().is_empty());
+
+        tracer.in_span("logged", |cx| cx.span().add_event("event", vec![]));
+        assert_eq!(event_exporter.span_starts.load(Ordering::Relaxed), 1);
+        assert_eq!(event_exporter.span_ends.load(Ordering::Relaxed), 1);
+        assert_eq!(event_exporter.span_events.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn child_tracking() {
+        let (provider, event_exporter) = test_provider(RealtimeSpanOptions {
+            child_tracker: Some(ChildTracker::new(MAX_TRACKED_PARENTS)),
+            ..RealtimeSpanOptions::default()
+        });
+        let tracer = provider.tracer("test");
+
+        let parent_cx = Context::current_with_span(tracer.start("parent"));
+        let mut child = tracer.start_with_context("child", &parent_cx);
+        tracer.start_with_context("ended_child", &parent_cx).end();
+        // The parent ends before its child
+        parent_cx.span().end();
+        child.end();
+
+        assert_eq!(
+            *event_exporter.end_active_children.lock().unwrap(),
+            vec![
+                ("ended_child".to_string(), Some(0)),
+                ("parent".to_string(), Some(1)),
+                ("child".to_string(), Some(0)),
+            ]
+        );
+        let parent_span_id = parent_cx.span().span_context().span_id();
+        let child_tracker = provider.span_options.child_tracker.as_ref().unwrap();
+        assert_eq!(child_tracker.active_children(parent_span_id), 0);
+    }
+
+    #[test]
+    fn component_name() {
+        let (provider, _) = test_provider(RealtimeSpanOptions::default());
+
+        // The Component field is the name of the tracer the span came from
+        let span = provider.tracer("db").start("query");
+        assert_eq!(span.get_span_data().instrumentation_lib.name, "db");
+    }
+
+    #[test]
+    fn default_tracer_name() {
+        let (provider, _) = test_provider(RealtimeSpanOptions {
+            default_tracer_name: Some(Arc::from("my-service")),
+            ..RealtimeSpanOptions::default()
+        });
+
+        let span = provider.tracer("").start("query");
+        assert_eq!(span.get_span_data().instrumentation_lib.name, "my-service");
+
+        // A tracer's own name takes precedence
+        let span = provider.tracer("db").start("query");
+        assert_eq!(span.get_span_data().instrumentation_lib.name, "db");
+    }
+
+    struct MockClock(Mutex<SystemTime>);
+
+    impl MockClock {
+        fn advance(&self, duration: std::time::Duration) {
+            *self.0.lock().unwrap() += duration;
+        }
+    }
+
+    impl Clock for MockClock {
+        fn now(&self) -> SystemTime {
+            *self.0.lock().unwrap()
+        }
+    }
+
+    #[test]
+    fn mock_clock() {
+        let start_time = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000);
+        let clock = Arc::new(MockClock(Mutex::new(start_time)));
+        let (provider, _) = test_provider(RealtimeSpanOptions {
+            track_event_names: true,
+            clock: Some(clock.clone()),
+            ..RealtimeSpanOptions::default()
+        });
+        let tracer = provider.tracer("test");
+
+        let mut span = tracer.start("span");
+        clock.advance(std::time::Duration::from_millis(250));
+        span.add_event("event", Vec::new());
+        clock.advance(std::time::Duration::from_millis(750));
+        span.end();
+
+        let span_data = span.get_span_data();
+        assert_eq!(span_data.start_time, start_time);
+        assert_eq!(
+            span_data
+                .end_time
+                .duration_since(span_data.start_time)
+                .unwrap(),
+            std::time::Duration::from_secs(1)
+        );
+        assert_eq!(
+            span_data.events.iter().next().unwrap().timestamp,
+            start_time + std::time::Duration::from_millis(250)
+        );
+    }
+
+    #[test]
+    fn shutdown() {
+        let (provider, event_exporter) = test_provider(RealtimeSpanOptions::default());
+        let weak_exporter = Arc::downgrade(&event_exporter);
+        drop(event_exporter);
+
+        let tracer = provider.tracer("test");
+        assert!(tracer.start("span").is_recording());
+
+        assert!(provider.shutdown().is_empty());
+        assert!(weak_exporter.upgrade().is_none());
+
+        assert!(!tracer.is_provider_alive());
+        assert!(!tracer.start("span").is_recording());
+        assert!(!provider.tracer("test").start("span").is_recording());
+    }
+
+    #[test]
+    fn remote_parent() {
+        let (provider, _) = test_provider(RealtimeSpanOptions::default());
+        let tracer = provider.tracer("test");
+
+        let remote_cx = Context::new().with_remote_span_context(SpanContext::new(
+            TraceId::from_bytes(1u128.to_be_bytes()),
+            SpanId::from_bytes(2u64.to_be_bytes()),
+            TraceFlags::SAMPLED,
+            true,
+            TraceState::default(),
+        ));
+        let span = tracer.start_with_context("remote_child", &remote_cx);
+        assert!(span.is_remote_parent());
+        assert_eq!(
+            span.get_span_data().parent_span_id,
+            SpanId::from_bytes(2u64.to_be_bytes())
+        );
+
+        let local_cx = Context::new().with_span(tracer.start("parent"));
+        assert!(!tracer
+            .start_with_context("local_child", &local_cx)
+            .is_remote_parent());
+        assert!(!tracer.start("root").is_remote_parent());
+    }
+
+    #[test]
+    #[cfg(target_os = "windows")]
+    fn context_keyword() {
+        let (provider, _) = test_provider(RealtimeSpanOptions::default());
+        let tracer = provider.tracer("test");
+
+        assert_eq!(tracer.start("span").get_context_keyword(), None);
+
+        let cx = Context::new().with_keyword(0x100);
+        let span = tracer.start_with_context("span", &cx);
+        assert_eq!(span.get_context_keyword(), Some(0x100));
+
+        // Spans nested in the span inherit the keywords from its context
+        let cx = cx.with_span(span);
+        let child = tracer.start_with_context("child", &cx);
+        assert_eq!(child.get_context_keyword(), Some(0x100));
+    }
+
+    #[test]
+    fn root_activity_from_context() {
+        let (provider, _) = test_provider(RealtimeSpanOptions {
+            root_activity_from_context: true,
+            ..RealtimeSpanOptions::default()
+        });
+        let tracer = provider.tracer("test");
+
+        let correlation_id = *b"\x12\x34\x56\x78request-id..";
+        let cx = Context::new().with_root_activity_id(correlation_id);
+        let span = tracer.start_with_context("root", &cx);
+        assert_eq!(span.get_root_activity_id(), Some(correlation_id));
+
+        let span_data = span.get_span_data();
+        let activities = Activities::generate(
+            &span_data.span_context.span_id(),
+            &span_data.parent_span_id,
+            &span_data.span_context.trace_id(),
+            false,
+        )
+        .with_activity_id(span.get_root_activity_id());
+        assert_eq!(activities.activity_id, correlation_id);
+
+        // Child spans inherit the context value, but keep their derived activity ids
+        let cx = cx.with_span(span);
+        let child = tracer.start_with_context("child", &cx);
+        assert_eq!(child.get_root_activity_id(), None);
+
+        assert_eq!(tracer.start("root").get_root_activity_id(), None);
+    }
+
+    #[test]
+    fn status_on_start() {
+        let (provider, event_exporter) = test_provider(RealtimeSpanOptions::default());
+        let tracer = provider.tracer("test");
+
+        tracer
+            .span_builder("preset")
+            .with_status(Status::Ok)
+            .start(&tracer)
+            .end();
+        tracer.start("unset").end();
+
+        assert_eq!(
+            *event_exporter.start_statuses.lock().unwrap(),
+            [Status::Ok, Status::Unset]
+        );
+    }
+
+    #[test]
+    fn event_dedup() {
+        let (provider, event_exporter) = test_provider(RealtimeSpanOptions {
+            event_dedup: true,
+            ..RealtimeSpanOptions::default()
+        });
+
+        provider.tracer("test").in_span("span", |cx| {
+            let span = cx.span();
+            for _ in 0..3 {
+                span.add_event("retry", vec![KeyValue::new("attempt", "same")]);
+            }
+            span.add_event("done", vec![]);
+
+            // Events are held back until a different event is added
+            assert_eq!(event_exporter.span_events.lock().unwrap().len(), 1);
+        });
+
+        let span_events = event_exporter.span_events.lock().unwrap();
+        assert_eq!(span_events.len(), 2);
+        assert_eq!(span_events[0].name, "retry");
+        assert_eq!(
+            span_events[0].attributes,
+            [
+                KeyValue::new("attempt", "same"),
+                KeyValue::new(REPEAT_COUNT_KEY, 3)
+            ]
+        );
+        assert_eq!(span_events[1].name, "done");
+        assert!(span_events[1].attributes.is_empty());
+    }
+
+    #[test]
+    fn single_span_event() {
+        assert_eq!(count_span_events(false), (1, 1));
+        assert_eq!(count_span_events(true), (0, 1));
+    }
+
+    #[test]
+    #[cfg(target_os = "windows")]
+    fn thread_activity_scope() {
+        let (provider, _) = test_provider(RealtimeSpanOptions {
+            thread_activity_scope: true,
+            ..RealtimeSpanOptions::default()
+        });
+
+        let prior_activity_id = Provider::current_thread_activity_id();
+
+        let tracer = provider.tracer("test");
+        let mut span = tracer.start("span");
+        let activities = Activities::generate(
+            &span.get_span_data().span_context.span_id(),
+            &span.get_span_data().parent_span_id,
+            &span.get_span_data().span_context.trace_id(),
+            false,
+        );
+
+        // Events written on this thread without an explicit activity id now use the span's
+        assert!(
+            Provider::current_thread_activity_id() == Guid::from_bytes_be(&activities.activity_id)
+        );
+
+        span.end();
+        assert!(Provider::current_thread_activity_id() == prior_activity_id);
+    }
+
+    #[test]
+    #[cfg(target_os = "windows")]
+    fn nested_thread_activity_scope() {
+        let (provider, _) = test_provider(RealtimeSpanOptions {
+            thread_activity_scope: true,
+            ..RealtimeSpanOptions::default()
+        });
+
+        let prior_activity_id = Provider::current_thread_activity_id();
+
+        let tracer = provider.tracer("test");
+        let parent_cx = Context::current_with_span(tracer.start("parent"));
+        let parent_activities = Activities::generate(
+            &parent_cx.span().span_context().span_id(),
+            &SpanId::INVALID,
+            &parent_cx.span().span_context().trace_id(),
+            false,
+        );
+        let parent_activity_id = Guid::from_bytes_be(&parent_activities.activity_id);
+
+        tracer.start_with_context("child", &parent_cx).end();
+        // Events written after the child ends still carry the parent's activity id
+        assert!(Provider::current_thread_activity_id() == parent_activity_id);
+
+        // A child that outlives its parent restores the id from before the parent started
+        let mut late_child = tracer.start_with_context("late_child", &parent_cx);
+        parent_cx.span().end();
+        assert!(Provider::current_thread_activity_id() != prior_activity_id);
+        late_child.end();
+        assert!(Provider::current_thread_activity_id() == prior_activity_id);
+    }
+
+    #[test]
+    fn track_event_names() {
+        let provider = RealtimeTracerProvider::new(
+            "my_provider_name",
+            &tracelogging_dynamic::Guid::from_name("my_provider_name"),
+            &ProviderGroup::Unset,
+            opentelemetry_sdk::trace::config(),
+            false,
+            ExporterConfig {
+                event_summary: true,
+                ..Default::default()
+            },
+        );
+
+        let tracer = provider.tracer("test");
+        let mut span = tracer.start("span");
+        span.add_event("first", vec![]);
+        span.add_event("second", vec![]);
+        span.add_event("third", vec![]);
+
+        let span_events = &span.get_span_data().events;
+        assert_eq!(events::get_event_count(span_events), 3);
+        assert_eq!(
+            events::get_event_names(span_events, false),
+            "first,second,third"
+        );
+
+        span.end();
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn event_tap() {
+        let tapped = Arc::new(Mutex::new(Vec::new()));
+        let tap_events = tapped.clone();
+        let exporter_config = ExporterConfig {
+            event_tap: Some(Arc::new(move |meta: &EventMeta| {
+                tap_events
+                    .lock()
+                    .unwrap()
+                    .push((meta.name.to_owned(), meta.level, meta.keyword))
+            })),
+            ..ExporterConfig::default()
+        };
+        let span_level = exporter_config.get_span_level();
+        let span_keywords = exporter_config.get_span_keywords();
+
+        let provider = RealtimeTracerProvider::<_, user_events::UserEventsExporter<_>>::new(
+            "event_tap_test",
+            &Guid::zero(),
+            &ProviderGroup::Unset,
+            opentelemetry_sdk::trace::config(),
+            false,
+            exporter_config,
+        );
+        provider.tracer("test").in_span("span", |_| {});
+
+        // Once for the span start event and once for the span end event
+        let expected = ("span".to_owned(), span_level, span_keywords);
+        assert_eq!(*tapped.lock().unwrap(), [expected.clone(), expected]);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn self_description_logged_once() {
+        let tapped = Arc::new(Mutex::new(Vec::new()));
+        let tap_events = tapped.clone();
+        let exporter_config = ExporterConfig {
+            self_description: true,
+            event_tap: Some(Arc::new(move |meta: &EventMeta| {
+                tap_events.lock().unwrap().push(meta.name.to_owned())
+            })),
+            ..ExporterConfig::default()
+        };
+
+        let provider = RealtimeTracerProvider::<_, user_events::UserEventsExporter<_>>::new(
+            "self_description_test",
+            &Guid::zero(),
+            &ProviderGroup::Unset,
+            opentelemetry_sdk::trace::config(),
+            false,
+            exporter_config,
+        );
+        let tracer = provider.tracer("test");
+        tracer.in_span("first", |_| {});
+        tracer.in_span("second", |_| {});
+
+        let tapped = tapped.lock().unwrap();
+        assert_eq!(tapped[0], "ProviderSchema");
+        assert_eq!(
+            tapped
+                .iter()
+                .filter(|name| *name == "ProviderSchema")
+                .count(),
+            1
+        );
+        assert_eq!(tapped.len(), 5);
+    }
+
+    #[test]
+    fn duplicate_provider_reported() {
+        use crate::common::DuplicateProviderError;
+
+        let provider_name = "duplicate_provider_test";
+        let provider_id = Guid::from_name(provider_name);
+        #[cfg(target_os = "windows")]
+        let key = String::from_utf8_lossy(&provider_id.to_utf8_bytes()).into_owned();
+        #[cfg(not(target_os = "windows"))]
+        let key = provider_name.to_owned();
+
+        // The error handler is global, so only the errors for this provider are kept
+        let reported = Arc::new(Mutex::new(Vec::new()));
+        let handler_reported = reported.clone();
+        let expected = DuplicateProviderError {
+            provider: key.clone(),
+        }
+        .to_string();
+        opentelemetry::global::set_error_handler(move |err| {
+            let err = err.to_string();
+            if err.contains(&key) {
+                handler_reported.lock().unwrap().push(err);
+            }
+        })
+        .unwrap();
+
+        let new_provider = || {
+            RealtimeTracerProvider::new(
+                provider_name,
+                &provider_id,
+                &ProviderGroup::Unset,
+                opentelemetry_sdk::trace::config(),
+                false,
+                ExporterConfig::default(),
+            )
+        };
+        let first = new_provider();
+        assert!(reported.lock().unwrap().is_empty());
+
+        let second = new_provider();
+        let reported = reported.lock().unwrap();
+        assert_eq!(reported.len(), 1);
+        assert!(reported[0].contains(&expected));
+
+        drop(first);
+        drop(second);
+    }
+}
@@ -7,28 +7,60 @@ use opentelemetry::{
     Array, Key, Value,
 };
 use opentelemetry_sdk::export::trace::{ExportResult, SpanData};
-use std::{cell::RefCell, sync::Arc, time::SystemTime};
+use opentelemetry_sdk::trace::EvictedQueue;
+use std::{
+    cell::RefCell,
+    sync::{
+        atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering},
+        Arc, RwLock, RwLockReadGuard,
+    },
+    time::{Duration, Instant, SystemTime},
+};
 
 use crate::{exporter_traits::*, common::{json, activities::*, EtwSpan, *}};
+use crate::common::{
+    attributes, common_schema, dropped::DroppedCounts, durations::SpanDuration, events, floats,
+    msgpack, split, status, threads, units,
+};
 
 thread_local! {static EBW: RefCell<EventBuilder> = RefCell::new(EventBuilder::new());}
 
+const SPAN_KINDS: [SpanKind; 5] = [
+    SpanKind::Client,
+    SpanKind::Server,
+    SpanKind::Producer,
+    SpanKind::Consumer,
+    SpanKind::Internal,
+];
+
+// Returns false if any tracepoint failed to register, e.g. because the user_events tracefs
+// was not mounted yet.
 #[allow(dead_code)]
 pub(crate) fn register_eventsets(
     provider: &mut eventheader_dynamic::Provider,
     kwl: &impl KeywordLevelProvider,
-) {
+) -> bool {
+    let registered;
     #[cfg(not(test))]
     {
-        // Standard real-time level/keyword pairs
-        provider.register_set(kwl.get_span_level().into(), kwl.get_span_keywords());
-        provider.register_set(kwl.get_event_level().into(), kwl.get_event_keywords());
-        provider.register_set(kwl.get_links_level().into(), kwl.get_links_keywords());
-
-        // Common Schema events use a level based on a span's Status
-        provider.register_set(eventheader::Level::Informational, kwl.get_span_keywords());
-        provider.register_set(eventheader::Level::Error, kwl.get_span_keywords());
-        provider.register_set(eventheader::Level::Verbose, kwl.get_span_keywords());
+        let mut sets = vec![
+            // Standard real-time level/keyword pairs
+            provider.register_set(kwl.get_span_level().into(), kwl.get_span_keywords()),
+            provider.register_set(kwl.get_event_level().into(), kwl.get_event_keywords()),
+            provider.register_set(kwl.get_links_level().into(), kwl.get_links_keywords()),
+            // Common Schema events use a level based on a span's Status
+            provider.register_set(eventheader::Level::Informational, kwl.get_span_keywords()),
+            provider.register_set(eventheader::Level::Error, kwl.get_span_keywords()),
+            provider.register_set(eventheader::Level::Verbose, kwl.get_span_keywords()),
+        ];
+        // Span start/stop events can use a different level for each kind
+        for kind in &SPAN_KINDS {
+            sets.push(provider.register_set(
+                kwl.get_span_level_for_kind(kind).into(),
+                kwl.get_span_keywords(),
+            ));
+        }
+        registered = sets.iter().all(|es| es.errno() == 0);
     }
     #[cfg(test)]
     {
@@ -45,24 +77,382 @@ pub(crate) fn register_eventsets(
         );
         provider.create_unregistered(true, eventheader::Level::Error, kwl.get_span_keywords());
         provider.create_unregistered(true, eventheader::Level::Verbose, kwl.get_span_keywords());
+
+        // Span start/stop events can use a different level for each kind
+        for kind in &SPAN_KINDS {
+            provider.create_unregistered(
+                true,
+                kwl.get_span_level_for_kind(kind).into(),
+                kwl.get_span_keywords(),
+            );
+        }
+        registered = true;
+    }
+    registered
+}
+
+const MAX_REGISTRATION_RETRIES: u32 = 10;
+const REGISTRATION_RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+// If the provider failed to register, registration is retried when events are exported,
+// at most `max_retries` times and no more than once per `interval`.
+// Once registered, checking the guard is a single atomic load.
+struct RegistrationRetry {
+    registered: AtomicBool,
+    retries_left: AtomicU32,
+    created: Instant,
+    next_retry_ms: AtomicU64,
+    interval: Duration,
+}
+
+impl RegistrationRetry {
+    fn new(registered: bool, max_retries: u32, interval: Duration) -> Self {
+        RegistrationRetry {
+            registered: AtomicBool::new(registered),
+            retries_left: AtomicU32::new(max_retries),
+            created: Instant::now(),
+            next_retry_ms: AtomicU64::new(0),
+            interval,
+        }
+    }
+
+    fn try_register(&self, register: impl FnOnce() -> bool) -> bool {
+        if self.registered.load(Ordering::Acquire) {
+            return true;
+        }
+
+        let now_ms = self.created.elapsed().as_millis() as u64;
+        let next_retry_ms = self.next_retry_ms.load(Ordering::Relaxed);
+        if now_ms < next_retry_ms {
+            return false;
+        }
+
+        // Only one thread retries at a time
+        if self
+            .next_retry_ms
+            .compare_exchange(
+                next_retry_ms,
+                now_ms + self.interval.as_millis() as u64,
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            )
+            .is_err()
+        {
+            return false;
+        }
+
+        if self
+            .retries_left
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1))
+            .is_err()
+        {
+            return false;
+        }
+
+        let registered = register();
+        if registered {
+            self.registered.store(true, Ordering::Release);
+        }
+        registered
+    }
+}
+
+/// A time field's value and format, in the configured resolution since the Unix epoch.
+/// The fields of a span start or end event, gathered once by the caller so that
+/// each part of a split event is written from the same values.
+#[derive(Clone, Copy)]
+struct SpanEventFields<'a> {
+    name: &'a str,
+    activities: &'a Activities,
+    traceparent: Option<&'a str>,
+    remote_parent: Option<bool>,
+    inline_links: Option<&'a str>,
+    inline_events: Option<&'a str>,
+    thread_name: Option<&'a str>,
+    active_children: Option<u32>,
+    component: Option<&'a str>,
+    attribute_count: Option<u16>,
+    event_time: &'a SystemTime,
+    /// Only set for a single span event, which has both the start and end time of the span.
+    start_time: Option<&'a SystemTime>,
+    span_kind: Option<&'a SpanKind>,
+    status: &'a Status,
+    status_message: Option<&'a str>,
+    span_events: Option<&'a EvictedQueue<Event>>,
+    dropped_counts: Option<&'a DroppedCounts>,
+    event_split: Option<usize>,
+    is_start: bool,
+    add_tags: bool,
+    export_payload_as_json: bool,
+}
+
+fn get_time_field(time: &SystemTime, time_format: TimeFormat) -> (u64, FieldFormat) {
+    let since_epoch = time
+        .duration_since(std::time::SystemTime::UNIX_EPOCH)
+        .unwrap();
+    match time_format {
+        TimeFormat::Seconds => (since_epoch.as_secs(), FieldFormat::Time),
+        TimeFormat::Milliseconds => (since_epoch.as_millis() as u64, FieldFormat::UnsignedInt),
+        TimeFormat::Nanoseconds => (since_epoch.as_nanos() as u64, FieldFormat::UnsignedInt),
+    }
+}
+
+// An event set found by `UserEventsExporter::find_set`, with the level and keyword
+// it was registered for, which the event set itself does not expose.
+pub(crate) struct TracepointSet {
+    set: Arc<EventSet>,
+    level: Level,
+    keyword: u64,
+}
+
+impl std::ops::Deref for TracepointSet {
+    type Target = EventSet;
+
+    fn deref(&self) -> &Self::Target {
+        &self.set
     }
 }
 
 pub(crate) struct UserEventsExporter<C: KeywordLevelProvider> {
-    provider: Arc<eventheader_dynamic::Provider>,
+    provider: RwLock<eventheader_dynamic::Provider>,
+    registration: RegistrationRetry,
     exporter_config: ExporterConfig<C>,
+    self_description_logged: AtomicBool,
 }
 
 impl<C: KeywordLevelProvider> UserEventsExporter<C> {
     #[allow(dead_code)]
     pub(crate) fn new(
-        provider: Arc<eventheader_dynamic::Provider>,
+        provider: eventheader_dynamic::Provider,
+        registered: bool,
         exporter_config: ExporterConfig<C>,
     ) -> Self {
         // Unfortunately we can't safely share a cached EventBuilder without adding undesirable locking
         UserEventsExporter {
-            provider,
+            provider: RwLock::new(provider),
+            registration: RegistrationRetry::new(
+                registered,
+                MAX_REGISTRATION_RETRIES,
+                REGISTRATION_RETRY_INTERVAL,
+            ),
             exporter_config,
+            self_description_logged: AtomicBool::new(false),
+        }
+    }
+
+    // Logs the ProviderSchema event the first time a span is exported while a session
+    // has the span keywords enabled.
+    fn log_self_description(&self) {
+        if !self.exporter_config.get_self_description()
+            || self.self_description_logged.load(Ordering::Relaxed)
+        {
+            return;
+        }
+
+        let span_es = if let Some(es) = self.find_set(
+            self.exporter_config.get_span_level().into(),
+            self.exporter_config.get_span_keywords(),
+        ) {
+            es
+        } else {
+            return;
+        };
+
+        if !span_es.enabled() || self.self_description_logged.swap(true, Ordering::Relaxed) {
+            return;
+        }
+
+        EBW.with(|eb| {
+            let mut eb = eb.borrow_mut();
+
+            eb.reset("ProviderSchema", 0);
+            eb.opcode(Opcode::Info);
+
+            eb.add_str(
+                "ProviderName",
+                self.provider().name(),
+                FieldFormat::Default,
+                0,
+            );
+            eb.add_str(
+                "Version",
+                env!("CARGO_PKG_VERSION"),
+                FieldFormat::Default,
+                0,
+            );
+            eb.add_str(
+                "Fields",
+                schema::get_provider_schema_json(),
+                FieldFormat::StringJson,
+                0,
+            );
+
+            let _ = self.write_event(&mut eb, &span_es, "ProviderSchema", None, None);
+        });
+    }
+
+    // Logs a marker event with a single count field, using the span level and keywords.
+    fn log_count_event(&self, name: &str, field_name: &str, count: u64) -> ExportResult {
+        let span_es = if let Some(es) = self.find_set(
+            self.exporter_config.get_span_level().into(),
+            self.exporter_config.get_span_keywords(),
+        ) {
+            es
+        } else {
+            return Ok(());
+        };
+
+        if !span_es.enabled() {
+            return Ok(());
+        }
+
+        EBW.with(|eb| {
+            let mut eb = eb.borrow_mut();
+
+            eb.reset(name, 0);
+            eb.opcode(Opcode::Info);
+
+            eb.add_value(field_name, count, FieldFormat::Default, 0);
+
+            let err = self.write_event(&mut eb, &span_es, name, None, None);
+
+            if err != 0 {
+                Err(TraceError::ExportFailed(Box::new(LinuxError { err })))
+            } else {
+                Ok(())
+            }
+        })
+    }
+
+    // Counts a span that no session enabled, or logs the spans counted before a session
+    // enabled the provider again.
+    fn track_suppressed_span(&self, enabled: bool) {
+        if !enabled {
+            self.exporter_config.add_suppressed_span();
+        } else if let Some(count) = self.exporter_config.take_suppressed_spans() {
+            let _ = self.log_count_event("SuppressedSpans", "SuppressedCount", count);
+        }
+    }
+
+    fn provider(&self) -> RwLockReadGuard<'_, eventheader_dynamic::Provider> {
+        self.provider
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    // If the process started before the user_events tracefs was available, the tracepoints
+    // are registered again from scratch.
+    fn retry_registration(&self) {
+        self.registration.try_register(|| {
+            let mut provider = self
+                .provider
+                .write()
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+            provider.unregister();
+            register_eventsets(&mut provider, &self.exporter_config)
+        });
+    }
+
+    // Keyword 0 matches any session that enabled the provider at the level, the same as ETW.
+    // Each user_events tracepoint has a single level and keyword, so if none is listening to the
+    // keyword 0 tracepoint, use the first enabled tracepoint for one of the configured keywords.
+    fn find_set(&self, level: Level, keyword: u64) -> Option<TracepointSet> {
+        self.retry_registration();
+
+        let provider = self.provider();
+        let find = |keyword| {
+            provider.find_set(level, keyword).map(|set| TracepointSet {
+                set,
+                level,
+                keyword,
+            })
+        };
+        let es = find(keyword);
+        if keyword != 0 || es.as_ref().map_or(false, |es| es.enabled()) {
+            return es;
+        }
+
+        [
+            self.exporter_config.get_span_keywords(),
+            self.exporter_config.get_event_keywords(),
+            self.exporter_config.get_links_keywords(),
+        ]
+        .into_iter()
+        .filter_map(find)
+        .find(|es| es.enabled())
+        .or(es)
+    }
+
+    // The tags for an event or its timestamp field, with the configured custom tags.
+    // EventHeader tags are only 16 bits.
+    fn event_tags(&self, event_tags: u32) -> u16 {
+        (event_tags | self.exporter_config.get_event_tags()) as u16
+    }
+
+    fn field_tags(&self, field_tags: u32) -> u16 {
+        (field_tags | self.exporter_config.get_field_tags()) as u16
+    }
+
+    // Calls the event tap after each successfully written event.
+    fn write_event(
+        &self,
+        eb: &mut EventBuilder,
+        es: &TracepointSet,
+        name: &str,
+        activity_id: Option<&[u8; 16]>,
+        related_id: Option<&[u8; 16]>,
+    ) -> i32 {
+        let err = eb.write(es, activity_id, related_id);
+        if err == 0 {
+            if let Some(event_tap) = self.exporter_config.get_event_tap() {
+                event_tap(&EventMeta {
+                    name,
+                    level: es.level.as_int(),
+                    keyword: es.keyword,
+                });
+            }
+        }
+        err
+    }
+
+    // The attributes that are logged, after the attribute filter and value redaction.
+    fn with_payload_attributes<R>(
+        &self,
+        attribs: &mut dyn Iterator<Item = (&Key, &Value)>,
+        f: impl FnOnce(&mut dyn Iterator<Item = (&Key, &Value)>) -> R,
+    ) -> R {
+        attributes::with_redacted_values(
+            &mut attributes::filter_attributes(
+                attribs,
+                self.exporter_config.get_attribute_filter().map(Arc::as_ref),
+            ),
+            self.exporter_config.get_value_redaction(),
+            f,
+        )
+    }
+
+    // Adds the struct that holds a span event's attributes, or its payload field.
+    // Attributes that don't fit in a struct are logged as top-level fields instead.
+    fn add_attributes_struct(
+        &self,
+        eb: &mut EventBuilder,
+        attribs: &mut dyn Iterator<Item = (&Key, &Value)>,
+        is_payload: bool,
+    ) {
+        let field_count = if is_payload {
+            1
+        } else {
+            attributes::get_attribute_field_count(
+                attribs,
+                self.exporter_config.get_attribute_filter().map(Arc::as_ref),
+                self.exporter_config.get_value_redaction(),
+                self.exporter_config.get_float_sanitization(),
+                self.exporter_config.get_max_array_length(),
+            )
+        };
+
+        if field_count <= common_schema::MAX_STRUCT_FIELDS {
+            eb.add_struct("Attributes", field_count as u8, 0);
         }
     }
 
@@ -70,10 +460,53 @@ impl<C: KeywordLevelProvider> UserEventsExporter<C> {
         &self,
         eb: &mut EventBuilder,
         attribs: &mut dyn Iterator<Item = (&Key, &Value)>,
+    ) {
+        self.with_payload_attributes(attribs, |attribs| {
+            if self.exporter_config.get_unit_annotations() {
+                let annotated = units::annotate_units(attribs);
+                self.add_attribute_fields(
+                    eb,
+                    &mut annotated.iter().map(|(key, value)| (key, value)),
+                );
+            } else {
+                self.add_attribute_fields(eb, attribs);
+            }
+        })
+    }
+
+    fn add_attribute_fields(
+        &self,
+        eb: &mut EventBuilder,
+        attribs: &mut dyn Iterator<Item = (&Key, &Value)>,
     ) {
         for attrib in attribs {
-            let field_name = &attrib.0.to_string();
-            match attrib.1 {
+            if self.exporter_config.get_float_sanitization() && floats::is_non_finite_f64(attrib.1)
+            {
+                continue;
+            }
+
+            let field_name = &attributes::get_field_name(
+                attrib.0,
+                self.exporter_config
+                    .get_attribute_prefix()
+                    .map(|prefix| prefix.as_ref()),
+            );
+            let value = floats::round_floats(attrib.1, self.exporter_config.get_float_rounding());
+            let (value, truncated) =
+                arrays::truncate_array(value, self.exporter_config.get_max_array_length());
+
+            #[cfg(feature = "json")]
+            if self.exporter_config.get_export_arrays_as_json() {
+                if let Some(json_string) = json::get_array_as_json(&value) {
+                    eb.add_str(field_name, &json_string, FieldFormat::StringJson, 0);
+                    if truncated {
+                        Self::add_truncation_marker(eb, field_name);
+                    }
+                    continue;
+                }
+            }
+
+            match value.as_ref() {
                 Value::Bool(b) => {
                     eb.add_value(field_name, *b, FieldFormat::Boolean, 0);
                 }
@@ -84,7 +517,40 @@ impl<C: KeywordLevelProvider> UserEventsExporter<C> {
                     eb.add_value(field_name, *f, FieldFormat::Float, 0);
                 }
                 Value::String(s) => {
-                    eb.add_str(field_name, &s.to_string(), FieldFormat::Default, 0);
+                    let format = if json::is_json_string_key(
+                        attrib.0,
+                        self.exporter_config
+                            .get_json_string_suffix()
+                            .map(|suffix| suffix.as_ref()),
+                    ) {
+                        FieldFormat::StringJson
+                    } else {
+                        match attributes::find_string_out_type(
+                            attrib.0,
+                            self.exporter_config
+                                .get_string_outtype_map()
+                                .map(|map| map.as_ref()),
+                        ) {
+                            Some(StringOutType::Xml) => FieldFormat::StringXml,
+                            Some(StringOutType::Json) => FieldFormat::StringJson,
+                            None => FieldFormat::Default,
+                        }
+                    };
+                    let number = (format == FieldFormat::Default
+                        && self.exporter_config.get_numeric_string_coercion())
+                    .then(|| coercion::parse_numeric_string(s.as_str()))
+                    .flatten();
+                    match number {
+                        Some(coercion::NumericString::I64(i)) => {
+                            eb.add_value(field_name, i, FieldFormat::SignedInt, 0);
+                        }
+                        Some(coercion::NumericString::F64(f)) => {
+                            eb.add_value(field_name, f, FieldFormat::Float, 0);
+                        }
+                        None => {
+                            eb.add_str(field_name, s.as_str(), format, 0);
+                        }
+                    }
                 }
                 Value::Array(array) => match array {
                     Array::Bool(v) => {
@@ -106,12 +572,22 @@ impl<C: KeywordLevelProvider> UserEventsExporter<C> {
                     }
                 },
             }
+
+            if truncated {
+                Self::add_truncation_marker(eb, field_name);
+            }
         }
     }
 
+    // Follows an array field that was cut short by the maximum array length.
+    fn add_truncation_marker(eb: &mut EventBuilder, field_name: &str) {
+        let marker_name = format!("{}{}", field_name, arrays::TRUNCATED_SUFFIX);
+        eb.add_value(&marker_name, true, FieldFormat::Boolean, 0);
+    }
+
     fn write_span_links(
         &self,
-        tlg_provider: &EventSet,
+        tlg_provider: &TracepointSet,
         eb: &mut EventBuilder,
         activities: &Activities,
         event_name: &str,
@@ -119,17 +595,16 @@ impl<C: KeywordLevelProvider> UserEventsExporter<C> {
         links: &mut dyn Iterator<Item = &Link>,
     ) -> ExportResult {
         for link in links {
-            eb.reset(event_name, EVENT_TAG_IGNORE_EVENT_TIME as u16);
+            eb.reset(event_name, self.event_tags(EVENT_TAG_IGNORE_EVENT_TIME));
             eb.opcode(Opcode::Info);
 
+            let (time, time_format) =
+                get_time_field(span_timestamp, self.exporter_config.get_time_format());
             eb.add_value(
                 "time",
-                span_timestamp
-                    .duration_since(std::time::SystemTime::UNIX_EPOCH)
-                    .unwrap()
-                    .as_secs(),
-                FieldFormat::Time,
-                FIELD_TAG_IS_REAL_EVENT_TIME as u16,
+                time,
+                time_format,
+                self.field_tags(FIELD_TAG_IS_REAL_EVENT_TIME),
             );
 
             eb.add_str(
@@ -138,14 +613,31 @@ impl<C: KeywordLevelProvider> UserEventsExporter<C> {
                 FieldFormat::Default,
                 0,
             );
+            eb.add_str(
+                "LinkTraceId",
+                std::fmt::format(format_args!("{:32x}", link.span_context.trace_id())),
+                FieldFormat::Default,
+                0,
+            );
+
+            if self.exporter_config.get_link_attr_count() {
+                eb.add_value(
+                    "LinkAttrCount",
+                    attributes::get_link_attribute_count(link),
+                    FieldFormat::UnsignedInt,
+                    0,
+                );
+            }
 
             self.add_attributes_to_event(
                 eb,
                 &mut link.attributes.iter().map(|kv| (&kv.key, &kv.value)),
             );
 
-            let err = eb.write(
-                &tlg_provider,
+            let err = self.write_event(
+                eb,
+                tlg_provider,
+                event_name,
                 Some(&activities.activity_id),
                 activities.parent_activity_id.as_ref(),
             );
@@ -160,65 +652,110 @@ impl<C: KeywordLevelProvider> UserEventsExporter<C> {
 
     fn write_span_events(
         &self,
-        tlg_provider: &EventSet,
+        tlg_provider: &TracepointSet,
         eb: &mut EventBuilder,
         activities: &Activities,
+        span_start_time: &SystemTime,
+        component: Option<&str>,
         events: &mut dyn Iterator<Item = &Event>,
         export_payload_as_json: bool,
     ) -> ExportResult {
+        let mut limit = events::EventLimit::new(self.exporter_config.get_max_events_emitted());
+
         for event in events {
-            eb.reset(&event.name, EVENT_TAG_IGNORE_EVENT_TIME as u16);
+            if !limit.try_take() {
+                continue;
+            }
+
+            eb.reset(&event.name, self.event_tags(EVENT_TAG_IGNORE_EVENT_TIME));
             eb.opcode(Opcode::Info);
 
+            let (time, time_format) =
+                get_time_field(&event.timestamp, self.exporter_config.get_time_format());
             eb.add_value(
                 "time",
-                event
-                    .timestamp
-                    .duration_since(std::time::SystemTime::UNIX_EPOCH)
-                    .unwrap()
-                    .as_secs(),
-                FieldFormat::Time,
-                FIELD_TAG_IS_REAL_EVENT_TIME as u16,
+                time,
+                time_format,
+                self.field_tags(FIELD_TAG_IS_REAL_EVENT_TIME),
             );
 
-            eb.add_str("SpanId", &activities.span_id, FieldFormat::Default, 0);
+            // The event's activity id already identifies the span it belongs to
+            if !self.exporter_config.get_activity_only_correlation() {
+                eb.add_str("SpanId", activities.span_id, FieldFormat::Default, 0);
 
-            if !activities.parent_span_id.is_empty() {
-                eb.add_str(
-                    "ParentId",
-                    &activities.parent_span_id,
-                    FieldFormat::Default,
+                if !activities.parent_span_id.is_empty() {
+                    eb.add_str(
+                        "ParentId",
+                        activities.parent_span_id,
+                        FieldFormat::Default,
+                        0,
+                    );
+                }
+
+                eb.add_str("TraceId", activities.trace_id_name, FieldFormat::Default, 0);
+            }
+
+            if self.exporter_config.get_event_offsets() {
+                eb.add_value(
+                    "OffsetNs",
+                    durations::get_offset_ns(span_start_time, &event.timestamp),
+                    FieldFormat::UnsignedInt,
                     0,
                 );
             }
 
-            eb.add_str(
-                "TraceId",
-                &activities.trace_id_name,
-                FieldFormat::Default,
-                0,
-            );
-
-            let mut added = false;
+            if let Some(component) = component {
+                eb.add_str("Component", component, FieldFormat::Default, 0);
+            }
 
-            #[cfg(feature = "json")]
-            if export_payload_as_json {
-                let json_string = json::get_attributes_as_json(
-                    &mut event.attributes.iter().map(|kv| (&kv.key, &kv.value)),
+            if let Some(service_instance_id) = self.exporter_config.get_service_instance_id() {
+                eb.add_str(
+                    "ServiceInstanceId",
+                    service_instance_id.as_ref(),
+                    FieldFormat::Default,
+                    0,
                 );
-                eb.add_str("Payload", &json_string, FieldFormat::StringJson, 0);
-                added = true;
             }
 
-            if !added {
-                self.add_attributes_to_event(
+            if self.exporter_config.get_grouped_event_attributes() {
+                self.add_attributes_struct(
                     eb,
                     &mut event.attributes.iter().map(|kv| (&kv.key, &kv.value)),
+                    export_payload_as_json || self.exporter_config.get_export_as_msgpack(),
                 );
             }
 
-            let err = eb.write(
-                &tlg_provider,
+            self.add_payload(
+                eb,
+                &mut event.attributes.iter().map(|kv| (&kv.key, &kv.value)),
+                export_payload_as_json,
+            );
+
+            let err = self.write_event(
+                eb,
+                tlg_provider,
+                &event.name,
+                Some(&activities.activity_id),
+                activities.parent_activity_id.as_ref(),
+            );
+
+            if err != 0 {
+                return Err(TraceError::ExportFailed(Box::new(LinuxError { err })));
+            }
+        }
+
+        if limit.omitted() > 0 {
+            eb.reset("EventsTruncated", 0);
+            eb.opcode(Opcode::Info);
+
+            eb.add_str("SpanId", activities.span_id, FieldFormat::Default, 0);
+            eb.add_str("TraceId", activities.trace_id_name, FieldFormat::Default, 0);
+            eb.add_value("OmittedCount", limit.omitted(), FieldFormat::Default, 0);
+
+            let err = self.write_event(
+                eb,
+                tlg_provider,
+                "EventsTruncated",
                 Some(&activities.activity_id),
                 activities.parent_activity_id.as_ref(),
             );
@@ -231,21 +768,109 @@ impl<C: KeywordLevelProvider> UserEventsExporter<C> {
         Ok(())
     }
 
-    #[allow(clippy::too_many_arguments)]
     fn write_span_event(
         &self,
-        tlg_provider: &EventSet,
+        tlg_provider: &TracepointSet,
+        eb: &mut EventBuilder,
+        fields: &SpanEventFields,
+        attributes: &mut dyn Iterator<Item = (&Key, &Value)>,
+    ) -> ExportResult {
+        match fields.event_split {
+            Some(max_size) if !fields.is_start => {
+                let parts = split::split_attributes(attributes, max_size);
+                let part_count = parts.len() as u32;
+
+                for (index, part) in parts.into_iter().enumerate() {
+                    // The span's links, events and counts are only logged with the first part
+                    let part_fields = if index == 0 {
+                        *fields
+                    } else {
+                        SpanEventFields {
+                            inline_links: None,
+                            inline_events: None,
+                            span_events: None,
+                            dropped_counts: None,
+                            ..*fields
+                        }
+                    };
+                    self.write_span_event_part(
+                        tlg_provider,
+                        eb,
+                        &part_fields,
+                        &mut part.into_iter(),
+                        if part_count > 1 {
+                            Some((index as u32 + 1, part_count))
+                        } else {
+                            None
+                        },
+                    )?;
+                }
+
+                Ok(())
+            }
+            _ => self.write_span_event_part(tlg_provider, eb, fields, attributes, None),
+        }
+    }
+
+    fn add_payload(
+        &self,
         eb: &mut EventBuilder,
-        name: &str,
-        activities: &Activities,
-        event_time: &SystemTime,
-        span_kind: Option<&SpanKind>,
-        status: &Status,
         attributes: &mut dyn Iterator<Item = (&Key, &Value)>,
-        is_start: bool,
-        add_tags: bool,
         export_payload_as_json: bool,
+    ) {
+        let mut added = false;
+
+        #[cfg(feature = "json")]
+        if export_payload_as_json {
+            let json_string =
+                self.with_payload_attributes(attributes, json::get_attributes_as_json);
+            eb.add_str("Payload", &json_string, FieldFormat::StringJson, 0);
+            added = true;
+        }
+
+        #[cfg(feature = "msgpack")]
+        if !added && self.exporter_config.get_export_as_msgpack() {
+            let blob = self.with_payload_attributes(attributes, msgpack::get_attributes_as_msgpack);
+            eb.add_str("Payload", &blob, FieldFormat::HexBytes, 0);
+            added = true;
+        }
+
+        if !added {
+            self.add_attributes_to_event(eb, attributes);
+        }
+    }
+
+    fn write_span_event_part(
+        &self,
+        tlg_provider: &TracepointSet,
+        eb: &mut EventBuilder,
+        fields: &SpanEventFields,
+        attributes: &mut dyn Iterator<Item = (&Key, &Value)>,
+        part: Option<(u32, u32)>,
     ) -> ExportResult {
+        let SpanEventFields {
+            name,
+            activities,
+            traceparent,
+            remote_parent,
+            inline_links,
+            inline_events,
+            thread_name,
+            active_children,
+            component,
+            attribute_count,
+            event_time,
+            start_time,
+            span_kind,
+            status,
+            status_message,
+            span_events,
+            dropped_counts,
+            event_split: _,
+            is_start,
+            add_tags,
+            export_payload_as_json,
+        } = *fields;
         let event_tags = if add_tags {
             EVENT_TAG_IGNORE_EVENT_TIME
         } else {
@@ -253,23 +878,49 @@ impl<C: KeywordLevelProvider> UserEventsExporter<C> {
         };
         let (opcode, time_field_name) = if is_start {
             (Opcode::ActivityStart, "StartTime")
+        } else if start_time.is_some() {
+            (Opcode::Info, "EndTime")
         } else {
             (Opcode::ActivityStop, "EndTime")
         };
 
-        eb.reset(name, event_tags as u16);
+        eb.reset(name, self.event_tags(event_tags));
         eb.opcode(opcode);
 
+        // A single span event has both the start and end time of the span
+        if let Some(start_time) = start_time {
+            let (time, time_format) =
+                get_time_field(start_time, self.exporter_config.get_time_format());
+            eb.add_value("StartTime", time, time_format, 0);
+        }
+
+        let (time, time_format) =
+            get_time_field(event_time, self.exporter_config.get_time_format());
         eb.add_value(
             time_field_name,
-            event_time
-                .duration_since(std::time::SystemTime::UNIX_EPOCH)
-                .unwrap()
-                .as_secs(),
-            FieldFormat::Time,
-            FIELD_TAG_IS_REAL_EVENT_TIME as u16,
+            time,
+            time_format,
+            self.field_tags(FIELD_TAG_IS_REAL_EVENT_TIME),
         );
 
+        if let Some(start_time) = start_time {
+            let duration = SpanDuration::new(start_time, event_time);
+            eb.add_value(
+                "DurationMs",
+                duration.as_millis(),
+                FieldFormat::UnsignedInt,
+                0,
+            );
+            if duration.clock_skew {
+                eb.add_value("ClockSkew", true, FieldFormat::Boolean, 0);
+            }
+        }
+
+        // Some consumers expect the attributes at the start of the payload
+        if self.exporter_config.get_attributes_first() {
+            self.add_payload(eb, attributes, export_payload_as_json);
+        }
+
         if let Some(sk) = span_kind {
             eb.add_str(
                 "Kind",
@@ -285,14 +936,15 @@ impl<C: KeywordLevelProvider> UserEventsExporter<C> {
             );
         }
 
-        if let Status::Error { description } = &status {
-            eb.add_str(
-                "StatusMessage",
-                description.to_string(),
-                FieldFormat::Default,
-                0,
-            );
-        };
+        if is_start && self.exporter_config.get_include_status_on_start() {
+            if let Some(status_name) = status::get_status_name(status) {
+                eb.add_str("Status", status_name, FieldFormat::Default, 0);
+            }
+        }
+
+        if let Some(status_message) = status_message {
+            eb.add_str("StatusMessage", status_message, FieldFormat::Default, 0);
+        }
 
         eb.add_str("SpanId", &activities.span_id, FieldFormat::Default, 0);
 
@@ -312,21 +964,105 @@ impl<C: KeywordLevelProvider> UserEventsExporter<C> {
             0,
         );
 
-        let mut added = false;
+        if let Some(traceparent) = traceparent {
+            eb.add_str("traceparent", traceparent, FieldFormat::Default, 0);
+        }
 
-        #[cfg(feature = "json")]
-        if export_payload_as_json {
-            let json_string = json::get_attributes_as_json(attributes);
-            eb.add_str("Payload", &json_string, FieldFormat::StringJson, 0);
-            added = true;
+        if let Some(remote_parent) = remote_parent {
+            eb.add_value("IsRemoteParent", remote_parent, FieldFormat::Boolean, 0);
         }
 
-        if !added {
-            self.add_attributes_to_event(eb, attributes);
+        if let Some(inline_links) = inline_links {
+            eb.add_str("Links", inline_links, FieldFormat::StringJson, 0);
+        }
+
+        if let Some(inline_events) = inline_events {
+            eb.add_str("Events", inline_events, FieldFormat::StringJson, 0);
+        }
+
+        if let Some(thread_name) = thread_name {
+            eb.add_str("ThreadName", thread_name, FieldFormat::Default, 0);
+        }
+
+        if let Some(active_children) = active_children {
+            eb.add_value(
+                "ActiveChildren",
+                active_children,
+                FieldFormat::UnsignedInt,
+                0,
+            );
+        }
+
+        if let Some(component) = component {
+            eb.add_str("Component", component, FieldFormat::Default, 0);
+        }
+
+        if let Some(service_instance_id) = self.exporter_config.get_service_instance_id() {
+            eb.add_str(
+                "ServiceInstanceId",
+                service_instance_id.as_ref(),
+                FieldFormat::Default,
+                0,
+            );
+        }
+
+        if let Some(attribute_count) = attribute_count {
+            eb.add_value("AttrCount", attribute_count, FieldFormat::UnsignedInt, 0);
+        }
+
+        if let Some((part, part_count)) = part {
+            eb.add_value("Part", part, FieldFormat::UnsignedInt, 0);
+            eb.add_value("PartCount", part_count, FieldFormat::UnsignedInt, 0);
+        }
+
+        if let Some(span_events) = span_events {
+            eb.add_value(
+                "EventCount",
+                events::get_event_count(span_events),
+                FieldFormat::UnsignedInt,
+                0,
+            );
+            eb.add_str(
+                "EventNames",
+                events::get_event_names(span_events, export_payload_as_json),
+                if export_payload_as_json {
+                    FieldFormat::StringJson
+                } else {
+                    FieldFormat::Default
+                },
+                0,
+            );
+        }
+
+        if let Some(dropped_counts) = dropped_counts {
+            eb.add_value(
+                "DroppedAttributes",
+                dropped_counts.attributes,
+                FieldFormat::UnsignedInt,
+                0,
+            );
+            eb.add_value(
+                "DroppedEvents",
+                dropped_counts.events,
+                FieldFormat::UnsignedInt,
+                0,
+            );
+            eb.add_value(
+                "DroppedLinks",
+                dropped_counts.links,
+                FieldFormat::UnsignedInt,
+                0,
+            );
+        }
+
+        if !self.exporter_config.get_attributes_first() {
+            self.add_payload(eb, attributes, export_payload_as_json);
         }
 
-        let err = eb.write(
-            &tlg_provider,
+        let err = self.write_event(
+            eb,
+            tlg_provider,
+            name,
             Some(&activities.activity_id),
             activities.parent_activity_id.as_ref(),
         );
@@ -340,12 +1076,13 @@ impl<C: KeywordLevelProvider> UserEventsExporter<C> {
 
     fn write_common_schema_span<'a, A>(
         &self,
-        tlg_provider: &EventSet,
+        tlg_provider: &TracepointSet,
         eb: &mut EventBuilder,
         name: &str,
         span_data: &SpanData,
         span_context: &SpanContext,
         export_payload_as_json: bool,
+        include_counts: bool,
         _attributes: A,
     ) -> ExportResult
     where
@@ -355,7 +1092,7 @@ impl<C: KeywordLevelProvider> UserEventsExporter<C> {
         let span_id = span_context.span_id().to_string();
 
         let event_tags: u32 = 0; // TODO
-        eb.reset(name, event_tags as u16);
+        eb.reset(name, self.event_tags(event_tags));
         eb.opcode(Opcode::Info);
 
         // Promoting values from PartC to PartA extensions is apparently just a draft spec
@@ -363,17 +1100,52 @@ impl<C: KeywordLevelProvider> UserEventsExporter<C> {
         // let exts = json::extract_common_schema_parta_exts(attributes);
 
         eb.add_value("__csver__", 0x0401u16, FieldFormat::HexInt, 0);
-        eb.add_struct("PartA", 2 /* + exts.len() as u8*/, 0);
+        let app_extension = self.exporter_config.get_app_extension();
+        let sdk_extension = self.exporter_config.get_sdk_extension();
+        let parta_field_count = common_schema::get_parta_field_count(app_extension, sdk_extension);
+
+        eb.add_struct("PartA", parta_field_count /* + exts.len() as u8*/, 0);
         {
             let time: String = chrono::DateTime::to_rfc3339(
                 &chrono::DateTime::<chrono::Utc>::from(span_data.end_time),
             );
             eb.add_str("time", time, FieldFormat::Default, 0);
 
-            eb.add_struct("ext_dt", 2, 0);
+            let trace_flags = self.exporter_config.get_common_schema_trace_flags();
+            eb.add_struct(
+                "ext_dt",
+                common_schema::get_ext_dt_field_count(trace_flags),
+                0,
+            );
             {
                 eb.add_str("traceId", &trace_id, FieldFormat::Default, 0);
                 eb.add_str("spanId", &span_id, FieldFormat::Default, 0);
+                if trace_flags {
+                    eb.add_str(
+                        "traceFlags",
+                        common_schema::get_trace_flags(span_context),
+                        FieldFormat::Default,
+                        0,
+                    );
+                }
+            }
+
+            if let Some(app_extension) = app_extension {
+                eb.add_struct("ext_app", 2, 0);
+                {
+                    eb.add_str("id", app_extension.id.as_ref(), FieldFormat::Default, 0);
+                    eb.add_str("ver", app_extension.ver.as_ref(), FieldFormat::Default, 0);
+                }
+            }
+
+            if sdk_extension {
+                let (lib_name, lib_ver) =
+                    common_schema::get_sdk_extension(&span_data.instrumentation_lib);
+                eb.add_struct("ext_sdk", 2, 0);
+                {
+                    eb.add_str("libName", lib_name, FieldFormat::Default, 0);
+                    eb.add_str("libVer", lib_ver, FieldFormat::Default, 0);
+                }
             }
 
             // for ext in exts {
@@ -397,23 +1169,25 @@ impl<C: KeywordLevelProvider> UserEventsExporter<C> {
         // }
 
         let mut status_message: String = String::default();
-        let mut partb_field_count = 5u8;
-        if span_data.parent_span_id != SpanId::INVALID {
-            partb_field_count += 1;
-        }
         if let Status::Error { description } = &span_data.status {
-            partb_field_count += 1;
             status_message = description.to_string();
         }
-        // TODO: azureResourceProvider: string
-        if !span_data.links.is_empty() {
-            partb_field_count += 1; // Type is an "array", but really it's just a string with a JSON array
+        let compact = self.exporter_config.get_compact_common_schema();
+        let mut promoted_fields = if self.exporter_config.get_semantic_convention_promotion() {
+            common_schema::get_promoted_fields(span_data)
+        } else {
+            Vec::new()
+        };
+        if compact {
+            promoted_fields.retain(|field| !common_schema::is_empty_value(field.value));
         }
+        let partb_fields = common_schema::PartBFields::new(span_data, include_counts, compact);
+        let partb_field_count = partb_fields.field_count() + promoted_fields.len() as u8;
 
         eb.add_struct("PartB", partb_field_count, 0);
         {
             eb.add_str("_typeName", "Span", FieldFormat::Default, 0);
-            if span_data.parent_span_id != SpanId::INVALID {
+            if partb_fields.parent_id {
                 eb.add_str(
                     "parentId",
                     &span_data.parent_span_id.to_string(),
@@ -422,18 +1196,20 @@ impl<C: KeywordLevelProvider> UserEventsExporter<C> {
                 );
             }
             eb.add_str("name", name, FieldFormat::Default, 0);
-            eb.add_value(
-                "kind",
-                match span_data.span_kind {
-                    SpanKind::Internal => 0u8,
-                    SpanKind::Server => 1,
-                    SpanKind::Client => 2,
-                    SpanKind::Producer => 3,
-                    SpanKind::Consumer => 4,
-                },
-                FieldFormat::UnsignedInt,
-                0,
-            );
+            if partb_fields.kind {
+                eb.add_value(
+                    "kind",
+                    match span_data.span_kind {
+                        SpanKind::Internal => 0u8,
+                        SpanKind::Server => 1,
+                        SpanKind::Client => 2,
+                        SpanKind::Producer => 3,
+                        SpanKind::Consumer => 4,
+                    },
+                    FieldFormat::UnsignedInt,
+                    0,
+                );
+            }
             eb.add_str(
                 "startTime",
                 &chrono::DateTime::to_rfc3339(&chrono::DateTime::<chrono::Utc>::from(
@@ -451,11 +1227,11 @@ impl<C: KeywordLevelProvider> UserEventsExporter<C> {
                 FieldFormat::Boolean,
                 0,
             );
-            if !status_message.is_empty() {
+            if partb_fields.status_message {
                 eb.add_str("statusMessage", &status_message, FieldFormat::Default, 0);
             }
             // TODO: azureResourceProvider: string
-            if !span_data.links.is_empty() {
+            if partb_fields.links {
                 let mut links = String::with_capacity(2 + (78 * span_data.links.len()));
                 links += "[";
                 for link in span_data.links.iter() {
@@ -469,14 +1245,54 @@ impl<C: KeywordLevelProvider> UserEventsExporter<C> {
 
                 eb.add_str("links", &links, FieldFormat::StringJson, 0);
             }
-            // TODO: promote HTTP, Database and Messaging fields
+            let (link_count, event_count) = common_schema::get_partb_counts(span_data);
+            if partb_fields.link_count {
+                eb.add_value("linkCount", link_count, FieldFormat::UnsignedInt, 0);
+            }
+            if partb_fields.event_count {
+                eb.add_value("eventCount", event_count, FieldFormat::UnsignedInt, 0);
+            }
+            for field in promoted_fields.iter() {
+                eb.add_str(
+                    field.name,
+                    field.value.as_str().as_ref(),
+                    FieldFormat::Default,
+                    0,
+                );
+            }
+            // TODO: promote HTTP and Messaging fields
         }
 
-        if !span_data.attributes.is_empty() {
-            let partc_field_count = if export_payload_as_json {
+        let attribute_filter = self.exporter_config.get_attribute_filter();
+        let float_sanitization = self.exporter_config.get_float_sanitization();
+        // Only the attributes that are written as fields are counted
+        let is_partc_attribute = |key: &Key, value: &Value| {
+            let is_skipped = common_schema::is_promoted(&promoted_fields, key)
+                || (float_sanitization && floats::is_non_finite_f64(value))
+                || (compact && common_schema::is_empty_value(value));
+            !is_skipped && attribute_filter.map_or(true, |filter| filter.is_allowed(key.as_str()))
+        };
+        let max_array_length = self.exporter_config.get_max_array_length();
+        let partc_fields = common_schema::PartCFields::with_field_counts(
+            span_data
+                .attributes
+                .iter()
+                .filter(|(key, value)| is_partc_attribute(key, value))
+                .map(|(_, value)| arrays::get_field_count(value, max_array_length)),
+        );
+
+        // Empty structs are not permitted
+        if partc_fields.attributes > 0 {
+            let mut partc_attributes = span_data
+                .attributes
+                .iter()
+                .filter(|(key, value)| is_partc_attribute(key, value));
+
+            let partc_field_count =
+                if export_payload_as_json || self.exporter_config.get_export_as_msgpack() {
                 1u8
             } else {
-                span_data.attributes.len() as u8
+                partc_fields.field_count()
             };
 
             eb.add_struct("PartC", partc_field_count, 0);
@@ -485,19 +1301,42 @@ impl<C: KeywordLevelProvider> UserEventsExporter<C> {
 
                 #[cfg(feature = "json")]
                 if export_payload_as_json {
-                    let json_string =
-                        json::get_attributes_as_json(&mut span_data.attributes.iter());
+                    let json_string = self.with_payload_attributes(
+                        &mut partc_attributes,
+                        json::get_attributes_as_json,
+                    );
                     eb.add_str("Payload", &json_string, FieldFormat::StringJson, 0);
                     added = true;
                 }
 
+                #[cfg(feature = "msgpack")]
+                if !added && self.exporter_config.get_export_as_msgpack() {
+                    let blob = self.with_payload_attributes(
+                        &mut partc_attributes,
+                        msgpack::get_attributes_as_msgpack,
+                    );
+                    eb.add_str("Payload", &blob, FieldFormat::HexBytes, 0);
+                    added = true;
+                }
+
                 if !added {
-                    self.add_attributes_to_event(eb, &mut span_data.attributes.iter());
+                    self.add_attributes_to_event(
+                        eb,
+                        &mut partc_attributes.by_ref().take(partc_fields.attributes),
+                    );
+                    if partc_fields.truncated > 0 {
+                        eb.add_value(
+                            "truncatedAttributes",
+                            partc_fields.truncated,
+                            FieldFormat::UnsignedInt,
+                            0,
+                        );
+                    }
                 }
             }
         }
 
-        let err = eb.write(&tlg_provider, None, None);
+        let err = self.write_event(eb, tlg_provider, name, None, None);
 
         if err != 0 {
             return Err(TraceError::ExportFailed(Box::new(LinuxError { err })));
@@ -509,7 +1348,7 @@ impl<C: KeywordLevelProvider> UserEventsExporter<C> {
 
 impl<C: KeywordLevelProvider> EventExporter for UserEventsExporter<C> {
     fn enabled(&self, level: u8, keyword: u64) -> bool {
-        let es = self.provider.find_set(level.into(), keyword);
+        let es = self.find_set(level.into(), keyword);
         if es.is_some() {
             es.unwrap().enabled()
         } else {
@@ -517,18 +1356,85 @@ impl<C: KeywordLevelProvider> EventExporter for UserEventsExporter<C> {
         }
     }
 
+    fn spans_enabled(&self) -> bool {
+        self.enabled(
+            self.exporter_config.get_span_level(),
+            self.exporter_config.get_span_keywords(),
+        )
+    }
+
+    fn log_provider_started(&self) -> ExportResult {
+        if !self.exporter_config.get_export_startup_event() {
+            return Ok(());
+        }
+
+        let span_es = if let Some(es) = self.find_set(
+            self.exporter_config.get_span_level().into(),
+            self.exporter_config.get_span_keywords(),
+        ) {
+            es
+        } else {
+            return Ok(());
+        };
+
+        if !span_es.enabled() {
+            return Ok(());
+        }
+
+        EBW.with(|eb| {
+            let mut eb = eb.borrow_mut();
+
+            eb.reset("ProviderStarted", 0);
+            eb.opcode(Opcode::Info);
+
+            eb.add_str(
+                "ProviderName",
+                self.provider().name(),
+                FieldFormat::Default,
+                0,
+            );
+            eb.add_str(
+                "Version",
+                env!("CARGO_PKG_VERSION"),
+                FieldFormat::Default,
+                0,
+            );
+            eb.add_value("Pid", std::process::id(), FieldFormat::Default, 0);
+
+            let err = self.write_event(&mut eb, &span_es, "ProviderStarted", None, None);
+
+            if err != 0 {
+                Err(TraceError::ExportFailed(Box::new(LinuxError { err })))
+            } else {
+                Ok(())
+            }
+        })
+    }
+
+    fn log_spans_dropped(&self, count: u64) -> ExportResult {
+        self.log_count_event("SpansDropped", "DroppedCount", count)
+    }
+
     // Called by the real-time exporter when a span is started
     fn log_span_start<S>(&self, span: &S) -> ExportResult
     where
         S: opentelemetry_api::trace::Span + EtwSpan,
     {
+        if !self.exporter_config.should_export(span.get_span_data()) {
+            return Ok(());
+        }
+
+        self.log_self_description();
+
         if !self.exporter_config.get_export_span_events() {
             // Common schema events are logged at span end
             return Ok(());
         }
 
-        let span_es = if let Some(es) = self.provider.find_set(
-            self.exporter_config.get_span_level().into(),
+        let span_es = if let Some(es) = self.find_set(
+            self.exporter_config
+                .get_span_level_for_kind(&span.get_span_data().span_kind)
+                .into(),
             self.exporter_config.get_span_keywords(),
         ) {
             es
@@ -550,7 +1456,36 @@ impl<C: KeywordLevelProvider> EventExporter for UserEventsExporter<C> {
             &span_context.span_id(),
             &span_data.parent_span_id,
             &span_context.trace_id(),
-        );
+            self.exporter_config.get_trace_scoped_activities(),
+        )
+        .with_activity_id(span.get_root_activity_id());
+        let status_message = self
+            .exporter_config
+            .get_include_status_on_start()
+            .then(|| {
+                status::get_status_message(
+                    &span_data.status,
+                    &span_data.attributes,
+                    self.exporter_config.get_preserve_ok_description(),
+                )
+            })
+            .flatten();
+        let traceparent = self
+            .exporter_config
+            .get_traceparent_field()
+            .then(|| get_traceparent(span_context));
+        let remote_parent = self
+            .exporter_config
+            .get_remote_flag()
+            .then(|| span.is_remote_parent());
+        let thread_name = self
+            .exporter_config
+            .get_thread_name_field()
+            .then(threads::get_current_thread_name);
+        let component = self
+            .exporter_config
+            .get_component_field()
+            .then_some(span_data.instrumentation_lib.name.as_ref());
 
         EBW.with(|eb| {
             let mut eb = eb.borrow_mut();
@@ -558,18 +1493,42 @@ impl<C: KeywordLevelProvider> EventExporter for UserEventsExporter<C> {
             self.write_span_event(
                 &span_es,
                 &mut eb,
-                &span_data.name,
-                &activities,
-                &span_data.start_time,
-                Some(&span_data.span_kind),
-                &Status::Unset,
+                &SpanEventFields {
+                    name: &span_data.name,
+                    activities: &activities,
+                    traceparent: traceparent.as_deref(),
+                    remote_parent,
+                    inline_links: None,
+                    inline_events: None,
+                    thread_name: thread_name.as_deref(),
+                    active_children: None,
+                    component,
+                    attribute_count: None,
+                    event_time: &span_data.start_time,
+                    start_time: None,
+                    span_kind: Some(&span_data.span_kind),
+                    // The status is usually unknown when the span starts, unless it was set by the SpanBuilder
+                    status: if self.exporter_config.get_include_status_on_start() {
+                        &span_data.status
+                    } else {
+                        &Status::Unset
+                    },
+                    status_message: status_message.as_deref(),
+                    span_events: None,
+                    dropped_counts: None,
+                    event_split: None,
+                    is_start: true,
+                    add_tags: false,
+                    export_payload_as_json,
+                },
                 &mut std::iter::empty(),
-                true,
-                false,
-                export_payload_as_json,
             )?;
 
-            let links_es = if let Some(es) = self.provider.find_set(
+            if self.exporter_config.get_inline_links() {
+                return Ok(());
+            }
+
+            let links_es = if let Some(es) = self.find_set(
                 self.exporter_config.get_links_level().into(),
                 self.exporter_config.get_links_keywords(),
             ) {
@@ -598,58 +1557,157 @@ impl<C: KeywordLevelProvider> EventExporter for UserEventsExporter<C> {
     where
         S: opentelemetry_api::trace::Span + EtwSpan,
     {
+        if !self.exporter_config.should_export(span.get_span_data()) {
+            return Ok(());
+        }
+
+        self.log_self_description();
+
         //let event_keywords = provider.get_event_keywords();
         let export_payload_as_json = self.exporter_config.get_export_as_json();
 
-        let span_es = if let Some(es) = self.provider.find_set(
-            self.exporter_config.get_span_level().into(),
-            self.exporter_config.get_span_keywords(),
-        ) {
-            es
+        let span_data = span.get_span_data();
+        let single_span_event = self.exporter_config.get_single_span_event();
+        // The kind is only left off the end event when there is a start event to carry it
+        let end_span_kind = (single_span_event || !self.exporter_config.get_kind_on_start_only())
+            .then_some(&span_data.span_kind);
+        let component = self
+            .exporter_config
+            .get_component_field()
+            .then_some(span_data.instrumentation_lib.name.as_ref());
+        let attribute_count = self
+            .exporter_config
+            .get_attribute_count_field()
+            .then(|| attributes::get_attribute_count(&span_data.attributes));
+
+        let span_keywords = self.exporter_config.get_span_keywords();
+        let span_level = self
+            .exporter_config
+            .get_span_level_for_kind(&span_data.span_kind);
+        // With status level mapping, the span end event uses the same level as the batch exporter
+        let end_level = if self.exporter_config.get_status_level_mapping() {
+            status::get_status_level(&span_data.status)
         } else {
-            return Ok(());
+            span_level
         };
 
-        if !span_es.enabled() {
+        let span_es = self
+            .find_set(span_level.into(), span_keywords)
+            .filter(|es| es.enabled());
+        let end_es = self
+            .find_set(end_level.into(), span_keywords)
+            .filter(|es| es.enabled());
+
+        self.track_suppressed_span(span_es.is_some() || end_es.is_some());
+        if span_es.is_none() && end_es.is_none() {
             return Ok(());
         }
 
-        let span_data = span.get_span_data();
-
         EBW.with(|eb| {
             let mut eb = eb.borrow_mut();
 
-            if self.exporter_config.get_export_span_events() {
+            if let Some(end_es) = end_es
+                .as_ref()
+                .filter(|_| self.exporter_config.get_export_span_events())
+            {
                 let activities = Activities::generate(
                     &span_data.span_context.span_id(),
                     &span_data.parent_span_id,
                     &span_data.span_context.trace_id(),
+                    self.exporter_config.get_trace_scoped_activities(),
+                )
+                .with_activity_id(span.get_root_activity_id());
+                let status_message = status::get_status_message(
+                    &span_data.status,
+                    &span_data.attributes,
+                    self.exporter_config.get_preserve_ok_description(),
                 );
+                let traceparent = self
+                    .exporter_config
+                    .get_traceparent_field()
+                    .then(|| get_traceparent(&span_data.span_context));
+                let remote_parent = self
+                    .exporter_config
+                    .get_remote_flag()
+                    .then(|| span.is_remote_parent());
+                let active_children = span.get_active_children();
+                let thread_name = self
+                    .exporter_config
+                    .get_thread_name_field()
+                    .then(threads::get_current_thread_name);
+                // Links are logged as a field of the span end event instead of as separate events
+                let inline_links = if self.exporter_config.get_inline_links() {
+                    json::get_links_as_json(&mut span_data.links.iter())
+                } else {
+                    None
+                };
 
                 self.write_span_event(
-                    &span_es,
+                    end_es,
                     &mut eb,
-                    &span_data.name,
-                    &activities,
-                    &span_data.end_time,
-                    Some(&span_data.span_kind),
-                    &span_data.status,
+                    &SpanEventFields {
+                        name: &span_data.name,
+                        activities: &activities,
+                        traceparent: traceparent.as_deref(),
+                        remote_parent,
+                        inline_links: inline_links.as_deref(),
+                        inline_events: None,
+                        thread_name: thread_name.as_deref(),
+                        active_children,
+                        component,
+                        attribute_count,
+                        event_time: &span_data.end_time,
+                        start_time: single_span_event.then_some(&span_data.start_time),
+                        span_kind: end_span_kind,
+                        status: &span_data.status,
+                        status_message: status_message.as_deref(),
+                        span_events: if self.exporter_config.get_export_event_summary() {
+                            Some(&span_data.events)
+                        } else {
+                            None
+                        },
+                        dropped_counts: None,
+                        event_split: self.exporter_config.get_event_split_size(),
+                        is_start: false,
+                        add_tags: false,
+                        export_payload_as_json,
+                    },
                     &mut span_data.attributes.iter(),
-                    false,
-                    false,
-                    export_payload_as_json,
                 )?;
+
+                // Links are normally logged when the span starts, but no start event was logged
+                if single_span_event && inline_links.is_none() {
+                    if let Some(links_es) = self.find_set(
+                        self.exporter_config.get_links_level().into(),
+                        self.exporter_config.get_links_keywords(),
+                    ) {
+                        if links_es.enabled() {
+                            self.write_span_links(
+                                &links_es,
+                                &mut eb,
+                                &activities,
+                                &span_data.name,
+                                &span_data.start_time,
+                                &mut span_data.links.iter(),
+                            )?;
+                        }
+                    }
+                }
             }
 
-            if self.exporter_config.get_export_common_schema_event() {
+            if let Some(span_es) = span_es
+                .as_ref()
+                .filter(|_| self.exporter_config.get_export_common_schema_event())
+            {
                 let attributes = span_data.resource.iter().chain(span_data.attributes.iter());
                 self.write_common_schema_span(
-                    &span_es,
+                    span_es,
                     &mut eb,
                     &span_data.name,
                     span_data,
                     span.span_context(),
                     export_payload_as_json,
+                    self.exporter_config.get_export_count_fields(),
                     attributes,
                 )?;
             }
@@ -663,7 +1721,13 @@ impl<C: KeywordLevelProvider> EventExporter for UserEventsExporter<C> {
     where
         S: opentelemetry_api::trace::Span + EtwSpan,
     {
-        let span_es = if let Some(es) = self.provider.find_set(
+        if !self.exporter_config.should_export(span.get_span_data()) {
+            return Ok(());
+        }
+
+        self.log_self_description();
+
+        let span_es = if let Some(es) = self.find_set(
             self.exporter_config.get_span_level().into(),
             self.exporter_config.get_span_keywords(),
         ) {
@@ -683,68 +1747,90 @@ impl<C: KeywordLevelProvider> EventExporter for UserEventsExporter<C> {
 
         let export_payload_as_json = self.exporter_config.get_export_as_json();
         let span_data = span.get_span_data();
+        let component = self
+            .exporter_config
+            .get_component_field()
+            .then_some(span_data.instrumentation_lib.name.as_ref());
 
         let activities = Activities::generate(
             &span_data.span_context.span_id(),
             &span_data.parent_span_id,
             &span_data.span_context.trace_id(),
-        );
+            self.exporter_config.get_trace_scoped_activities(),
+        )
+        .with_activity_id(span.get_root_activity_id());
 
         EBW.with(|eb| {
             let mut eb = eb.borrow_mut();
 
-            eb.reset(&event.name, EVENT_TAG_IGNORE_EVENT_TIME as u16);
+            eb.reset(&event.name, self.event_tags(EVENT_TAG_IGNORE_EVENT_TIME));
             eb.opcode(Opcode::Info);
 
+            let (time, time_format) =
+                get_time_field(&event.timestamp, self.exporter_config.get_time_format());
             eb.add_value(
                 "time",
-                event
-                    .timestamp
-                    .duration_since(std::time::SystemTime::UNIX_EPOCH)
-                    .unwrap()
-                    .as_secs(),
-                FieldFormat::Time,
-                FIELD_TAG_IS_REAL_EVENT_TIME as u16,
+                time,
+                time_format,
+                self.field_tags(FIELD_TAG_IS_REAL_EVENT_TIME),
             );
 
-            eb.add_str("SpanId", &activities.span_id, FieldFormat::Default, 0);
+            // The event's activity id already identifies the span it belongs to
+            if !self.exporter_config.get_activity_only_correlation() {
+                eb.add_str("SpanId", activities.span_id, FieldFormat::Default, 0);
 
-            if !activities.parent_span_id.is_empty() {
-                eb.add_str(
-                    "ParentId",
-                    &activities.parent_span_id,
-                    FieldFormat::Default,
+                if !activities.parent_span_id.is_empty() {
+                    eb.add_str(
+                        "ParentId",
+                        activities.parent_span_id,
+                        FieldFormat::Default,
+                        0,
+                    );
+                }
+
+                eb.add_str("TraceId", activities.trace_id_name, FieldFormat::Default, 0);
+            }
+
+            if self.exporter_config.get_event_offsets() {
+                eb.add_value(
+                    "OffsetNs",
+                    durations::get_offset_ns(&span_data.start_time, &event.timestamp),
+                    FieldFormat::UnsignedInt,
                     0,
                 );
             }
 
-            eb.add_str(
-                "TraceId",
-                &activities.trace_id_name,
-                FieldFormat::Default,
-                0,
-            );
-
-            let mut added = false;
+            if let Some(component) = component {
+                eb.add_str("Component", component, FieldFormat::Default, 0);
+            }
 
-            #[cfg(feature = "json")]
-            if export_payload_as_json {
-                let json_string = json::get_attributes_as_json(
-                    &mut event.attributes.iter().map(|kv| (&kv.key, &kv.value)),
+            if let Some(service_instance_id) = self.exporter_config.get_service_instance_id() {
+                eb.add_str(
+                    "ServiceInstanceId",
+                    service_instance_id.as_ref(),
+                    FieldFormat::Default,
+                    0,
                 );
-                eb.add_str("Payload", &json_string, FieldFormat::StringJson, 0);
-                added = true;
             }
 
-            if !added {
-                self.add_attributes_to_event(
+            if self.exporter_config.get_grouped_event_attributes() {
+                self.add_attributes_struct(
                     &mut eb,
                     &mut event.attributes.iter().map(|kv| (&kv.key, &kv.value)),
+                    export_payload_as_json || self.exporter_config.get_export_as_msgpack(),
                 );
             }
 
-            let err = eb.write(
+            self.add_payload(
+                &mut eb,
+                &mut event.attributes.iter().map(|kv| (&kv.key, &kv.value)),
+                export_payload_as_json,
+            );
+
+            let err = self.write_event(
+                &mut eb,
                 &span_es,
+                &event.name,
                 Some(&activities.activity_id),
                 activities.parent_activity_id.as_ref(),
             );
@@ -759,27 +1845,39 @@ impl<C: KeywordLevelProvider> EventExporter for UserEventsExporter<C> {
 
     // Called by the batch exporter sometime after span is completed
     fn log_span_data(&self, span_data: &SpanData) -> ExportResult {
-        let export_payload_as_json = self.exporter_config.get_export_as_json();
+        if !self.exporter_config.should_export(span_data) {
+            return Ok(());
+        }
 
-        let level = match span_data.status {
-            Status::Ok => Level::Informational,
-            Status::Error { .. } => Level::Error,
-            Status::Unset => Level::Verbose,
-        };
+        self.log_self_description();
 
-        let span_es = if let Some(es) = self
-            .provider
+        let export_payload_as_json = self.exporter_config.get_export_as_json();
+        let dropped_counts = DroppedCounts::from_span_data(span_data);
+        let single_span_event = self.exporter_config.get_single_span_event();
+        // The kind is only left off the end event when there is a start event to carry it
+        let end_span_kind = (single_span_event || !self.exporter_config.get_kind_on_start_only())
+            .then_some(&span_data.span_kind);
+        let component = self
+            .exporter_config
+            .get_component_field()
+            .then_some(span_data.instrumentation_lib.name.as_ref());
+        let attribute_count = self
+            .exporter_config
+            .get_attribute_count_field()
+            .then(|| attributes::get_attribute_count(&span_data.attributes));
+
+        let level = status::get_status_level(&span_data.status).into();
+
+        let span_es = self
             .find_set(level, self.exporter_config.get_span_keywords())
-        {
+            .filter(|es| es.enabled());
+        self.track_suppressed_span(span_es.is_some());
+        let span_es = if let Some(es) = span_es {
             es
         } else {
             return Ok(());
         };
 
-        if !span_es.enabled() {
-            return Ok(());
-        }
-
         EBW.with(|eb| {
             let mut eb = eb.borrow_mut();
             let mut err = Ok(());
@@ -789,86 +1887,147 @@ impl<C: KeywordLevelProvider> EventExporter for UserEventsExporter<C> {
                     &span_data.span_context.span_id(),
                     &span_data.parent_span_id,
                     &span_data.span_context.trace_id(),
+                    self.exporter_config.get_trace_scoped_activities(),
+                );
+                let status_message = status::get_status_message(
+                    &span_data.status,
+                    &span_data.attributes,
+                    self.exporter_config.get_preserve_ok_description(),
                 );
+                let traceparent = self
+                    .exporter_config
+                    .get_traceparent_field()
+                    .then(|| get_traceparent(&span_data.span_context));
+                // Links are logged as a field of the span end event instead of as separate events
+                let inline_links = if self.exporter_config.get_inline_links() {
+                    json::get_links_as_json(&mut span_data.links.iter())
+                } else {
+                    None
+                };
+                // Span events are logged as a field of the span end event instead of as separate events
+                let inline_events = if self.exporter_config.get_inline_events() {
+                    json::get_events_as_json(&mut span_data.events.iter())
+                } else {
+                    None
+                };
 
-                err = self
-                    .write_span_event(
-                        &span_es,
+                // The span end event shares most of its fields with the start event
+                let start_fields = SpanEventFields {
+                    name: &span_data.name,
+                    activities: &activities,
+                    traceparent: traceparent.as_deref(),
+                    remote_parent: None,
+                    inline_links: None,
+                    inline_events: None,
+                    thread_name: None,
+                    active_children: None,
+                    component,
+                    attribute_count: None,
+                    event_time: &span_data.start_time,
+                    start_time: None,
+                    span_kind: Some(&span_data.span_kind),
+                    status: &span_data.status,
+                    status_message: status_message.as_deref(),
+                    span_events: None,
+                    dropped_counts: None,
+                    event_split: None,
+                    is_start: true,
+                    add_tags: true,
+                    export_payload_as_json,
+                };
+
+                err = if single_span_event {
+                    Ok(())
+                } else {
+                    self.write_span_event(&span_es, &mut eb, &start_fields, &mut std::iter::empty())
+                }
+                .and_then(|_| {
+                    if inline_events.is_some() {
+                        return Ok(());
+                    }
+
+                    let events_es = if let Some(es) = self.find_set(
+                        self.exporter_config.get_event_level().into(),
+                        self.exporter_config.get_event_keywords(),
+                    ) {
+                        es
+                    } else {
+                        return Ok(());
+                    };
+
+                    if !events_es.enabled() {
+                        return Ok(());
+                    }
+
+                    self.write_span_events(
+                        &events_es,
                         &mut eb,
-                        &span_data.name,
                         &activities,
                         &span_data.start_time,
-                        Some(&span_data.span_kind),
-                        &span_data.status,
-                        &mut std::iter::empty(),
-                        true,
-                        true,
+                        component,
+                        &mut span_data.events.iter(),
                         export_payload_as_json,
                     )
-                    .and_then(|_| {
-                        let events_es = if let Some(es) = self.provider.find_set(
-                            self.exporter_config.get_event_level().into(),
-                            self.exporter_config.get_event_keywords(),
-                        ) {
-                            es
-                        } else {
-                            return Ok(());
-                        };
-
-                        if !events_es.enabled() {
-                            return Ok(());
-                        }
-
-                        self.write_span_events(
-                            &events_es,
-                            &mut eb,
-                            &activities,
-                            &mut span_data.events.iter(),
-                            export_payload_as_json,
-                        )
-                    })
-                    .and_then(|_| {
-                        let links_es = if let Some(es) = self.provider.find_set(
-                            self.exporter_config.get_links_level().into(),
-                            self.exporter_config.get_links_keywords(),
-                        ) {
-                            es
-                        } else {
-                            return Ok(());
-                        };
+                })
+                .and_then(|_| {
+                    if inline_links.is_some() {
+                        return Ok(());
+                    }
 
-                        if !links_es.enabled() {
-                            return Ok(());
-                        }
+                    let links_es = if let Some(es) = self.find_set(
+                        self.exporter_config.get_links_level().into(),
+                        self.exporter_config.get_links_keywords(),
+                    ) {
+                        es
+                    } else {
+                        return Ok(());
+                    };
+
+                    if !links_es.enabled() {
+                        return Ok(());
+                    }
 
-                        self.write_span_links(
-                            &links_es,
-                            &mut eb,
-                            &activities,
-                            &span_data.name,
-                            &span_data.start_time,
-                            &mut span_data.links.iter(),
-                        )
-                    })
-                    .and_then(|_| {
-                        self.write_span_event(
-                            &span_es,
-                            &mut eb,
-                            &span_data.name,
-                            &activities,
-                            &span_data.end_time,
-                            Some(&span_data.span_kind),
-                            &span_data.status,
-                            &mut span_data.attributes.iter(),
-                            false,
-                            true,
-                            export_payload_as_json,
-                        )
-                    });
+                    self.write_span_links(
+                        &links_es,
+                        &mut eb,
+                        &activities,
+                        &span_data.name,
+                        &span_data.start_time,
+                        &mut span_data.links.iter(),
+                    )
+                })
+                .and_then(|_| {
+                    self.write_span_event(
+                        &span_es,
+                        &mut eb,
+                        &SpanEventFields {
+                            inline_links: inline_links.as_deref(),
+                            inline_events: inline_events.as_deref(),
+                            attribute_count,
+                            event_time: &span_data.end_time,
+                            start_time: single_span_event.then_some(&span_data.start_time),
+                            span_kind: end_span_kind,
+                            span_events: if self.exporter_config.get_export_event_summary() {
+                                Some(&span_data.events)
+                            } else {
+                                None
+                            },
+                            dropped_counts: if self.exporter_config.get_export_dropped_counts() {
+                                Some(&dropped_counts)
+                            } else {
+                                None
+                            },
+                            event_split: self.exporter_config.get_event_split_size(),
+                            is_start: false,
+                            ..start_fields
+                        },
+                        &mut span_data.attributes.iter(),
+                    )
+                });
             }
 
             if self.exporter_config.get_export_common_schema_event() {
-                let span_es = if let Some(es) = self.provider.find_set(
+                let span_es = if let Some(es) = self.find_set(
                     Level::Informational,
                     self.exporter_config.get_span_keywords(),
                 ) {
@@ -887,6 +2046,7 @@ impl<C: KeywordLevelProvider> EventExporter for UserEventsExporter<C> {
                         span_data,
                         &span_data.span_context,
                         export_payload_as_json,
+                        self.exporter_config.get_export_count_fields(),
                         attributes,
                     );
 
@@ -898,3 +2058,490 @@ impl<C: KeywordLevelProvider> EventExporter for UserEventsExporter<C> {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The field names in the metadata of the event that `eb` last built,
+    // read from the builder's Debug output since it has no accessor for its buffers.
+    fn field_names(eb: &EventBuilder) -> Vec<String> {
+        let debug = format!("{:?}", eb);
+        let meta: Vec<u8> = debug[debug.find("meta: [").unwrap() + "meta: [".len()..]
+            .split(']')
+            .next()
+            .unwrap()
+            .split(", ")
+            .filter(|byte| !byte.is_empty())
+            .map(|byte| byte.parse().unwrap())
+            .collect();
+
+        // Skip the event name, then read each field's name, encoding and optional format and tag
+        let mut rest = &meta[meta.iter().position(|&b| b == 0).unwrap() + 1..];
+        let mut fields = Vec::new();
+        while !rest.is_empty() {
+            let name_len = rest.iter().position(|&b| b == 0).unwrap();
+            fields.push(String::from_utf8_lossy(&rest[..name_len]).into_owned());
+            let mut len = name_len + 2;
+            if rest[name_len + 1] & 0x80 != 0 {
+                len += if rest[len] & 0x80 != 0 { 3 } else { 1 };
+            }
+            rest = &rest[len..];
+        }
+        fields
+    }
+
+    fn span_data_with_attribute() -> SpanData {
+        let mut attributes = opentelemetry_sdk::trace::EvictedHashMap::new(128, 1);
+        attributes.insert(opentelemetry::KeyValue::new("http.method", "GET"));
+        SpanData {
+            span_context: SpanContext::empty_context(),
+            parent_span_id: SpanId::INVALID,
+            span_kind: SpanKind::Server,
+            name: "span".into(),
+            start_time: SystemTime::now(),
+            end_time: SystemTime::now(),
+            attributes,
+            events: EvictedQueue::new(128),
+            links: EvictedQueue::new(128),
+            status: Status::Unset,
+            resource: std::borrow::Cow::Owned(opentelemetry_sdk::Resource::empty()),
+            instrumentation_lib: opentelemetry::InstrumentationLibrary::new("test", None, None),
+        }
+    }
+
+    // The field names of the span end event that the exporter writes for `span_data`.
+    fn end_event_field_names(
+        exporter_config: ExporterConfig<DefaultKeywordLevelProvider>,
+        span_data: &SpanData,
+    ) -> Vec<String> {
+        let mut provider = eventheader_dynamic::Provider::new(
+            "end_event_field_names_test",
+            &eventheader_dynamic::Provider::new_options(),
+        );
+        register_eventsets(&mut provider, &exporter_config);
+        let exporter = UserEventsExporter::new(provider, true, exporter_config);
+
+        assert!(exporter.log_span_data(span_data).is_ok());
+        // The end event is the last event written for the span
+        EBW.with(|eb| field_names(&eb.borrow()))
+    }
+
+    #[test]
+    fn attributes_first() {
+        let span_data = span_data_with_attribute();
+
+        let fields = end_event_field_names(ExporterConfig::default(), &span_data);
+        assert_eq!(fields.first().map(String::as_str), Some("EndTime"));
+        assert_eq!(fields.last().map(String::as_str), Some("http.method"));
+
+        let fields = end_event_field_names(
+            ExporterConfig {
+                attributes_first: true,
+                ..ExporterConfig::default()
+            },
+            &span_data,
+        );
+        let attribute = fields
+            .iter()
+            .position(|name| name == "http.method")
+            .unwrap();
+        let kind = fields.iter().position(|name| name == "Kind").unwrap();
+        assert!(attribute < kind);
+        assert_eq!(fields.first().map(String::as_str), Some("EndTime"));
+    }
+
+    #[cfg(all(target_os = "linux", feature = "msgpack"))]
+    #[test]
+    fn realtime_span_event_msgpack() {
+        use crate::spans::{realtime_tracer::RealtimeTracerProvider, ProviderGroup};
+        use opentelemetry_api::trace::{Span, Tracer, TracerProvider};
+
+        let provider = RealtimeTracerProvider::<_, UserEventsExporter<_>>::new(
+            "realtime_span_event_msgpack_test",
+            &tracelogging_dynamic::Guid::zero(),
+            &ProviderGroup::Unset,
+            opentelemetry_sdk::trace::config(),
+            false,
+            ExporterConfig {
+                msgpack: true,
+                grouped_event_attributes: true,
+                ..ExporterConfig::<DefaultKeywordLevelProvider>::default()
+            },
+        );
+        let mut span = provider.tracer("test").start("span");
+        span.add_event(
+            "event",
+            vec![opentelemetry::KeyValue::new("http.method", "GET")],
+        );
+
+        // The span event is the last event written so far
+        let fields = EBW.with(|eb| field_names(&eb.borrow()));
+        assert_eq!(fields.last().map(String::as_str), Some("Payload"));
+        assert!(fields.contains(&"Attributes".to_owned()));
+        assert!(!fields.contains(&"http.method".to_owned()));
+        span.end();
+    }
+
+    #[test]
+    fn time_formats() {
+        let time =
+            SystemTime::UNIX_EPOCH + std::time::Duration::from_nanos(1_700_000_000_123_456_789);
+
+        assert_eq!(
+            get_time_field(&time, TimeFormat::Seconds),
+            (1_700_000_000, FieldFormat::Time)
+        );
+        assert_eq!(
+            get_time_field(&time, TimeFormat::Milliseconds),
+            (1_700_000_000_123, FieldFormat::UnsignedInt)
+        );
+        assert_eq!(
+            get_time_field(&time, TimeFormat::Nanoseconds),
+            (1_700_000_000_123_456_789, FieldFormat::UnsignedInt)
+        );
+    }
+
+    #[test]
+    fn keyword_zero_matches_any_session() {
+        let mut provider = eventheader_dynamic::Provider::new(
+            "keyword_zero_test",
+            &eventheader_dynamic::Provider::new_options(),
+        );
+        let zero_es = provider.create_unregistered(false, Level::Verbose, 0);
+        let event_es = provider.create_unregistered(true, Level::Verbose, 0x10);
+        provider.create_unregistered(false, Level::Verbose, 0x100);
+
+        let exporter = UserEventsExporter::new(provider, true, ExporterConfig::default());
+
+        // Only a session for the span event keyword is listening, so keyword 0 uses its tracepoint.
+        assert!(exporter.enabled(Level::Verbose.as_int(), 0));
+        assert!(Arc::ptr_eq(
+            &exporter.find_set(Level::Verbose, 0).unwrap().set,
+            &event_es
+        ));
+        assert!(!exporter.enabled(Level::Verbose.as_int(), 0x100));
+        assert!(!zero_es.enabled());
+
+        let mut provider = eventheader_dynamic::Provider::new(
+            "keyword_zero_test",
+            &eventheader_dynamic::Provider::new_options(),
+        );
+        let zero_es = provider.create_unregistered(true, Level::Verbose, 0);
+        provider.create_unregistered(true, Level::Verbose, 0x10);
+
+        let exporter = UserEventsExporter::new(provider, true, ExporterConfig::default());
+
+        // A session listening to the keyword 0 tracepoint is preferred.
+        assert!(Arc::ptr_eq(
+            &exporter.find_set(Level::Verbose, 0).unwrap().set,
+            &zero_es
+        ));
+
+        let mut provider = eventheader_dynamic::Provider::new(
+            "keyword_zero_test",
+            &eventheader_dynamic::Provider::new_options(),
+        );
+        provider.create_unregistered(false, Level::Verbose, 0);
+        provider.create_unregistered(false, Level::Verbose, 0x10);
+
+        let exporter = UserEventsExporter::new(provider, true, ExporterConfig::default());
+        assert!(!exporter.enabled(Level::Verbose.as_int(), 0));
+    }
+
+    #[test]
+    fn null_byte_in_key() {
+        let mut provider = eventheader_dynamic::Provider::new(
+            "null_byte_in_key_test",
+            &eventheader_dynamic::Provider::new_options(),
+        );
+        register_eventsets(&mut provider, &ExporterConfig::default());
+        let exporter = UserEventsExporter::new(provider, true, ExporterConfig::default());
+        let es = exporter
+            .find_set(Level::Verbose, exporter.exporter_config.get_span_keywords())
+            .unwrap();
+
+        let attribs = [opentelemetry::KeyValue::new("bad\0key", 5)];
+        let mut eb = EventBuilder::new();
+        eb.reset("Event", 0);
+        exporter
+            .add_attributes_to_event(&mut eb, &mut attribs.iter().map(|kv| (&kv.key, &kv.value)));
+        assert_eq!(exporter.write_event(&mut eb, &es, "Event", None, None), 0);
+    }
+
+    #[test]
+    fn common_schema_attribute_cap() {
+        let mut provider = eventheader_dynamic::Provider::new(
+            "common_schema_attribute_cap_test",
+            &eventheader_dynamic::Provider::new_options(),
+        );
+        register_eventsets(&mut provider, &ExporterConfig::default());
+        let exporter = UserEventsExporter::new(provider, true, ExporterConfig::default());
+        let es = exporter
+            .find_set(Level::Verbose, exporter.exporter_config.get_span_keywords())
+            .unwrap();
+
+        let mut attributes = opentelemetry_sdk::trace::EvictedHashMap::new(512, 300);
+        for i in 0..300 {
+            attributes.insert(opentelemetry::KeyValue::new(format!("attr{}", i), i as i64));
+        }
+        let span_data = SpanData {
+            span_context: SpanContext::empty_context(),
+            parent_span_id: SpanId::INVALID,
+            span_kind: SpanKind::Internal,
+            name: "span".into(),
+            start_time: SystemTime::now(),
+            end_time: SystemTime::now(),
+            attributes,
+            events: EvictedQueue::new(128),
+            links: EvictedQueue::new(128),
+            status: Status::Unset,
+            resource: std::borrow::Cow::Owned(opentelemetry_sdk::Resource::empty()),
+            instrumentation_lib: opentelemetry::InstrumentationLibrary::new("test", None, None),
+        };
+
+        // Declaring more than 127 PartC fields fails the EventBuilder's debug assertion
+        let mut eb = EventBuilder::new();
+        assert!(exporter
+            .write_common_schema_span(
+                &es,
+                &mut eb,
+                "span",
+                &span_data,
+                &span_data.span_context,
+                false,
+                false,
+                std::iter::empty(),
+            )
+            .is_ok());
+    }
+
+    #[test]
+    fn compact_common_schema() {
+        let exporter_config = ExporterConfig {
+            compact_common_schema: true,
+            ..ExporterConfig::default()
+        };
+        let mut provider = eventheader_dynamic::Provider::new(
+            "compact_common_schema_test",
+            &eventheader_dynamic::Provider::new_options(),
+        );
+        register_eventsets(&mut provider, &exporter_config);
+        let exporter = UserEventsExporter::new(provider, true, exporter_config);
+        let es = exporter
+            .find_set(Level::Verbose, exporter.exporter_config.get_span_keywords())
+            .unwrap();
+
+        let mut attributes = opentelemetry_sdk::trace::EvictedHashMap::new(128, 2);
+        attributes.insert(opentelemetry::KeyValue::new("empty", ""));
+        attributes.insert(opentelemetry::KeyValue::new(
+            "empty_array",
+            opentelemetry::Value::Array(opentelemetry::Array::I64(vec![])),
+        ));
+        let span_data = SpanData {
+            span_context: SpanContext::empty_context(),
+            parent_span_id: SpanId::INVALID,
+            span_kind: SpanKind::Internal,
+            name: "span".into(),
+            start_time: SystemTime::now(),
+            end_time: SystemTime::now(),
+            attributes,
+            events: EvictedQueue::new(128),
+            links: EvictedQueue::new(128),
+            status: Status::Unset,
+            resource: std::borrow::Cow::Owned(opentelemetry_sdk::Resource::empty()),
+            instrumentation_lib: opentelemetry::InstrumentationLibrary::new("test", None, None),
+        };
+
+        // Declaring an empty PartC struct fails the EventBuilder's assertion
+        let mut eb = EventBuilder::new();
+        assert!(exporter
+            .write_common_schema_span(
+                &es,
+                &mut eb,
+                "span",
+                &span_data,
+                &span_data.span_context,
+                false,
+                true,
+                std::iter::empty(),
+            )
+            .is_ok());
+    }
+
+    #[test]
+    fn truncated_arrays() {
+        let exporter_config = ExporterConfig {
+            max_array_length: Some(2),
+            ..ExporterConfig::default()
+        };
+        let mut provider = eventheader_dynamic::Provider::new(
+            "truncated_arrays_test",
+            &eventheader_dynamic::Provider::new_options(),
+        );
+        register_eventsets(&mut provider, &exporter_config);
+        let exporter = UserEventsExporter::new(provider, true, exporter_config);
+        let es = exporter
+            .find_set(Level::Verbose, exporter.exporter_config.get_span_keywords())
+            .unwrap();
+
+        let mut attributes = opentelemetry_sdk::trace::EvictedHashMap::new(128, 2);
+        attributes.insert(opentelemetry::KeyValue::new(
+            "long_array",
+            opentelemetry::Value::Array(opentelemetry::Array::I64(vec![1, 2, 3, 4])),
+        ));
+        let span_data = SpanData {
+            span_context: SpanContext::empty_context(),
+            parent_span_id: SpanId::INVALID,
+            span_kind: SpanKind::Internal,
+            name: "span".into(),
+            start_time: SystemTime::now(),
+            end_time: SystemTime::now(),
+            attributes,
+            events: EvictedQueue::new(128),
+            links: EvictedQueue::new(128),
+            status: Status::Unset,
+            resource: std::borrow::Cow::Owned(opentelemetry_sdk::Resource::empty()),
+            instrumentation_lib: opentelemetry::InstrumentationLibrary::new("test", None, None),
+        };
+
+        // The PartC struct's declared field count includes the truncation marker
+        let mut eb = EventBuilder::new();
+        assert!(exporter
+            .write_common_schema_span(
+                &es,
+                &mut eb,
+                "span",
+                &span_data,
+                &span_data.span_context,
+                false,
+                true,
+                std::iter::empty(),
+            )
+            .is_ok());
+    }
+
+    #[test]
+    fn custom_tags() {
+        let exporter_config = ExporterConfig {
+            event_tags: 0x100,
+            field_tags: 0x200,
+            ..ExporterConfig::default()
+        };
+        let mut provider = eventheader_dynamic::Provider::new(
+            "custom_tags_test",
+            &eventheader_dynamic::Provider::new_options(),
+        );
+        register_eventsets(&mut provider, &exporter_config);
+        let exporter = UserEventsExporter::new(provider, true, exporter_config);
+
+        assert_eq!(
+            exporter.event_tags(EVENT_TAG_IGNORE_EVENT_TIME),
+            (EVENT_TAG_IGNORE_EVENT_TIME | 0x100) as u16
+        );
+        assert_eq!(exporter.event_tags(0), 0x100);
+        assert_eq!(
+            exporter.field_tags(FIELD_TAG_IS_REAL_EVENT_TIME),
+            (FIELD_TAG_IS_REAL_EVENT_TIME | 0x200) as u16
+        );
+
+        let exporter = UserEventsExporter::new(
+            eventheader_dynamic::Provider::new(
+                "default_tags_test",
+                &eventheader_dynamic::Provider::new_options(),
+            ),
+            false,
+            ExporterConfig::default(),
+        );
+        assert_eq!(
+            exporter.event_tags(EVENT_TAG_IGNORE_EVENT_TIME),
+            EVENT_TAG_IGNORE_EVENT_TIME as u16
+        );
+    }
+
+    #[test]
+    fn registration_retry() {
+        let retry = RegistrationRetry::new(false, 3, Duration::ZERO);
+        assert!(!retry.try_register(|| false));
+        assert!(retry.try_register(|| true));
+        // Registered providers are never registered again
+        assert!(retry.try_register(|| panic!("already registered")));
+
+        let retry = RegistrationRetry::new(false, 1, Duration::ZERO);
+        assert!(!retry.try_register(|| false));
+        assert!(!retry.try_register(|| panic!("no retries left")));
+
+        let retry = RegistrationRetry::new(false, 3, Duration::from_secs(3600));
+        assert!(!retry.try_register(|| false));
+        assert!(!retry.try_register(|| panic!("retried too soon")));
+    }
+
+    #[test]
+    fn register_after_failure() {
+        // The tracepoints failed to register, so the provider has no event sets
+        let provider = eventheader_dynamic::Provider::new(
+            "register_after_failure_test",
+            &eventheader_dynamic::Provider::new_options(),
+        );
+        let exporter = UserEventsExporter::new(provider, false, ExporterConfig::default());
+        let span_level = exporter.exporter_config.get_span_level();
+        let span_keywords = exporter.exporter_config.get_span_keywords();
+        assert!(exporter
+            .provider()
+            .find_set(span_level.into(), span_keywords)
+            .is_none());
+
+        // The first export registers the tracepoints again, and events flow to them
+        assert!(exporter.enabled(span_level, span_keywords));
+        assert!(exporter.log_provider_started().is_ok());
+    }
+
+    #[test]
+    fn suppressed_spans() {
+        // No session enabled the provider, so none of its tracepoints are registered
+        let provider = eventheader_dynamic::Provider::new(
+            "suppressed_spans_test",
+            &eventheader_dynamic::Provider::new_options(),
+        );
+        let exporter_config = ExporterConfig {
+            suppressed_spans_event: true,
+            ..ExporterConfig::default()
+        };
+        let suppressed_spans = exporter_config.get_suppressed_spans().clone();
+        let exporter = UserEventsExporter::new(provider, true, exporter_config);
+
+        let span_data = SpanData {
+            span_context: SpanContext::empty_context(),
+            parent_span_id: SpanId::INVALID,
+            span_kind: SpanKind::Internal,
+            name: "span".into(),
+            start_time: SystemTime::now(),
+            end_time: SystemTime::now(),
+            attributes: opentelemetry_sdk::trace::EvictedHashMap::new(128, 0),
+            events: EvictedQueue::new(128),
+            links: EvictedQueue::new(128),
+            status: Status::Unset,
+            resource: std::borrow::Cow::Owned(opentelemetry_sdk::Resource::empty()),
+            instrumentation_lib: opentelemetry::InstrumentationLibrary::new("test", None, None),
+        };
+        assert!(exporter.log_span_data(&span_data).is_ok());
+        assert!(exporter.log_span_data(&span_data).is_ok());
+        assert_eq!(suppressed_spans.load(Ordering::Relaxed), 2);
+
+        // Taking the count for the SuppressedSpans event resets it
+        assert_eq!(exporter.exporter_config.take_suppressed_spans(), Some(2));
+        assert_eq!(suppressed_spans.load(Ordering::Relaxed), 0);
+        assert_eq!(exporter.exporter_config.take_suppressed_spans(), None);
+
+        // Without the event, the count is never reset
+        let exporter_config = ExporterConfig::default();
+        exporter_config.add_suppressed_span();
+        assert_eq!(exporter_config.take_suppressed_spans(), None);
+        assert_eq!(
+            exporter_config
+                .get_suppressed_spans()
+                .load(Ordering::Relaxed),
+            1
+        );
+    }
+}
@@ -178,4 +178,46 @@ mod functional {
 
         result
     }
+
+    const sz_startup_test_session_name: PCSTR = s!("OpenTelemetry-Rust-ETW-Exporter-Startup-Tests");
+    const startup_test_provider_name: &str =
+        "OpenTelemetry-Rust-ETW-Exporter-Startup-Test-Provider";
+    // d6504044-d40c-51f8-4184-09bb39427d8a
+    const startup_test_provider_id: windows::core::GUID =
+        GUID::from_u128(284871478374010183971738321441498758538u128);
+
+    #[test]
+    #[cfg(target_os = "windows")]
+    fn log_startup_event() -> Result<(), windows::core::Error> {
+        // Enable the provider before it is registered, so that the startup event
+        // is written as soon as the exporter registers the provider.
+        let h = EtwSession::get_or_start_etw_session(sz_startup_test_session_name, false)?;
+        h.enable_provider(&startup_test_provider_id)?;
+
+        let mut consumer = EtwEventAsyncWaiter::new();
+        let event_consumer = consumer.get_consumer();
+
+        let trace = ProcessTraceHandle::from_session(sz_startup_test_session_name, event_consumer)?;
+
+        let provider =
+            opentelemetry_etw_user_events::spans::new_exporter(startup_test_provider_name)
+                .with_startup_event()
+                .build_span_exporter();
+
+        // No spans are created, so the only event from the provider is the startup event.
+        let fut = consumer.expect_event_async(|evt| {
+            let event_header = evt.get_event_header();
+            event_header.ProviderId == startup_test_provider_id
+                && event_header.EventDescriptor.Opcode == 0
+        });
+
+        let mut thread = trace.process_trace()?;
+
+        let result = futures::executor::block_on(fut);
+
+        let _ = thread.stop_and_wait(); // We don't care about what ProcessTrace returned
+        drop(provider);
+
+        result
+    }
 }